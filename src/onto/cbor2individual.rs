@@ -1,8 +1,10 @@
 use crate::datatype::*;
 use crate::individual::*;
 use crate::parser::*;
+use cbor::decoder::{DecodeResult, TypeInfo};
 use cbor::types::Type;
 use cbor::{Config, Decoder};
+use std::borrow::Cow;
 use std::io::Cursor;
 
 #[derive(PartialEq, Debug)]
@@ -19,44 +21,103 @@ pub enum TagId {
     Uri = 32,
 }
 
-pub fn parse_cbor(raw: &mut RawObj) -> Result<String, i8> {
-    if raw.data.is_empty() || raw.raw_type != RawType::Cbor {
-        return Err(-1);
+/// The additional information of the initial byte of an indefinite-length item
+const INDEFINITE: u8 = 31;
+
+/// `len_predicates` of an indefinite-length map, the predicates are read until the break stop code
+const INDEFINITE_LEN: u32 = u32::MAX;
+
+pub fn parse_cbor(raw: &mut RawObj) -> Result<String, ParseError> {
+    if raw.data.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    if raw.raw_type != RawType::Cbor {
+        return Err(ParseError::Invalid("expected cbor".to_owned()));
+    }
+
+    let (uri, len, pos) = read_cbor_header(&raw.data)?;
+    let uri = uri.into_owned();
+    raw.len_predicates = len;
+    // the size of an indefinite-length map is unknown
+    raw.top_level_len = if len == INDEFINITE_LEN {
+        0
+    } else {
+        len
+    };
+    // the uri is the first of the predicates
+    raw.cur_predicates = 1;
+    raw.cur = pos;
+    check_not_truncated(raw)?;
+    Ok(uri)
+}
+
+/// The data must not end before the rest of the predicates, the parsing stops at the end of data
+fn check_not_truncated(raw: &RawObj) -> Result<(), ParseError> {
+    if raw.cur >= raw.data.len() as u64 && raw.cur_predicates < raw.len_predicates {
+        return Err(ParseError::Truncated);
     }
+    Ok(())
+}
 
-    let input = Cursor::new(raw.data.to_owned());
+/// The uri of cbor data, the predicates are not read
+pub fn peek_cbor_uri(data: &[u8]) -> Result<String, ParseError> {
+    peek_cbor_uri_borrowed(data).map(Cow::into_owned)
+}
+
+/// The uri of cbor data borrowed from it, the uri which is not a valid UTF-8 text is an error
+pub fn peek_cbor_uri_borrowed(data: &[u8]) -> Result<Cow<'_, str>, ParseError> {
+    if data.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    read_cbor_header(data).map(|(uri, _, _)| uri)
+}
+
+/// The uri borrowed from the data, the count of predicates and the position after the uri
+fn read_cbor_header(data: &[u8]) -> Result<(Cow<'_, str>, u32, u64), ParseError> {
+    let input = Cursor::new(data);
 
     let mut config = Config::default();
-    if raw.data.len() > 200_000 {
+    if data.len() > 200_000 {
         config.max_len_array = 10000;
     }
 
+    let max_size_map = config.max_size_map as u64;
     let mut d = Decoder::new(config, input);
 
-    if let Ok(len) = d.object() {
-        raw.len_predicates = len as u32;
+    let len = match d.typeinfo() {
+        Ok((Type::Object, INDEFINITE)) => Some(INDEFINITE_LEN),
+        Ok((Type::Object, a)) => d.kernel().unsigned(a).ok().filter(|len| *len <= max_size_map).map(|len| len as u32),
+        _ => None,
+    };
+
+    if let Some(len) = len {
         if let Ok(type_info) = d.typeinfo() {
             if let Ok(predicate) = d._text(&type_info) {
                 if predicate == "@" {
-                    if let Ok(type_info) = d.typeinfo() {
-                        if let Ok(uri) = d._text(&type_info) {
-                            raw.cur = d.into_reader().position();
-                            return Ok(uri);
+                    if let Ok((Type::Text, a)) = d.typeinfo() {
+                        if let Ok(size) = d.kernel().unsigned(a) {
+                            let start = d.into_reader().position() as usize;
+                            if let Some(uri) = data[start..].get(..size as usize) {
+                                return match std::str::from_utf8(uri) {
+                                    Ok(uri) => Ok((Cow::Borrowed(uri), len, (start + uri.len()) as u64)),
+                                    Err(e) => Err(ParseError::Invalid(format!("cbor: uri is not valid UTF-8, err={}", e))),
+                                };
+                            }
                         }
                     }
                 } else {
-                    return Err(-1);
+                    return Err(ParseError::Invalid("cbor: first predicate must be [@]".to_owned()));
                 }
             }
         }
     }
 
-    Err(-1)
+    Err(ParseError::Invalid("cbor: fail read uri".to_owned()))
 }
 
-pub fn parse_cbor_to_predicate(expect_predicate: &str, iraw: &mut Individual) -> bool {
+pub fn parse_cbor_to_predicate(expect_predicate: &str, iraw: &mut Individual) -> Result<bool, ParseError> {
     if iraw.raw.cur >= iraw.raw.data.len() as u64 {
-        return false;
+        return Ok(false);
     }
 
     let mut is_found = false;
@@ -69,121 +130,290 @@ pub fn parse_cbor_to_predicate(expect_predicate: &str, iraw: &mut Individual) ->
         config.max_len_array = 10000;
     }
     let mut d = Decoder::new(config, cur);
+    let opts = ValueOptions {
+        max_depth: iraw.raw.max_depth,
+        unknown_tags: iraw.raw.unknown_tags,
+        keep_empty_predicates: iraw.raw.keep_empty_predicates,
+    };
 
     for _ in iraw.raw.cur_predicates..iraw.raw.len_predicates {
-        if let Ok(type_info) = d.typeinfo() {
-            if let Ok(predicate) = d._text(&type_info) {
-                if predicate == expect_predicate {
-                    is_found = true;
-                }
-                if !add_value(&predicate, &mut d, &mut iraw.obj) {
-                    iraw.raw.cur = d.into_reader().position();
-                    return false;
-                }
+        iraw.raw.cur_predicates += 1;
+        let predicate = match d.typeinfo() {
+            Ok((Type::Break, _)) if iraw.raw.len_predicates == INDEFINITE_LEN => break,
+            Ok(type_info) => d._text(&type_info),
+            Err(e) => Err(e),
+        };
+        let predicate = match predicate {
+            Ok(p) => p,
+            Err(e) => {
+                iraw.raw.cur = iraw.raw.data.len() as u64;
+                return Err(ParseError::Invalid(format!("cbor: fail read predicate, err={:?}", e)));
             }
+        };
+        if predicate == expect_predicate {
+            is_found = true;
+        }
+        if let Err(e) = add_value(&predicate, &mut d, &mut iraw.obj, 0, opts) {
+            iraw.raw.cur = iraw.raw.data.len() as u64;
+            return Err(e);
         }
 
         if is_found {
             iraw.raw.cur = d.into_reader().position();
-            return true;
+            check_not_truncated(&iraw.raw)?;
+            return Ok(true);
         }
     }
 
-    iraw.raw.cur = d.into_reader().position();
-    false
+    iraw.raw.cur = iraw.raw.data.len() as u64;
+    Ok(false)
 }
 
-fn add_value(predicate: &str, d: &mut Decoder<Cursor<&[u8]>>, indv: &mut IndividualObj) -> bool {
-    if let Ok((type_info, tag)) = d.typeinfo_and_tag() {
-        match type_info.0 {
-            Type::Bool => {
-                if let Ok(b) = d._bool(&type_info) {
-                    indv.add_bool(&predicate, b);
-                }
+/// The next predicate of the raw data, the cursor is not moved
+pub(crate) fn peek_cbor_predicate(raw: &RawObj) -> Option<String> {
+    if raw.cur_predicates >= raw.len_predicates || raw.cur >= raw.data.len() as u64 {
+        return None;
+    }
+    let mut cur = Cursor::new(raw.data.as_slice());
+    cur.set_position(raw.cur);
+    let mut d = Decoder::new(Config::default(), cur);
+    match d.typeinfo() {
+        Ok((Type::Break, _)) | Err(_) => None,
+        Ok(type_info) => d._text(&type_info).ok(),
+    }
+}
+
+fn fail_read_value(predicate: &str) -> ParseError {
+    ParseError::Invalid(format!("cbor: fail read value of [{}]", predicate))
+}
+
+/// The settings of the value decoding taken from `RawObj`
+#[derive(Clone, Copy)]
+struct ValueOptions {
+    max_depth: usize,
+    unknown_tags: UnknownTagPolicy,
+    keep_empty_predicates: bool,
+}
+
+fn is_known_tag(tag: u64) -> bool {
+    [
+        TagId::None as u64,
+        TagId::TextRu as u64,
+        TagId::TextEn as u64,
+        TagId::StandardDateTime as u64,
+        TagId::EpochDateTime as u64,
+        TagId::PositiveBigint as u64,
+        TagId::NegativeBigint as u64,
+        TagId::DecimalFraction as u64,
+        TagId::CborEncoded as u64,
+        TagId::Uri as u64,
+    ]
+    .contains(&tag)
+}
+
+/// Reads over the rest of the value after its type info, the nested values may be tagged
+fn skip_typed_value(d: &mut Decoder<Cursor<&[u8]>>, type_info: TypeInfo) -> DecodeResult<()> {
+    match type_info {
+        (Type::Array, INDEFINITE) | (Type::Object, INDEFINITE) | (Type::Text, INDEFINITE) | (Type::Bytes, INDEFINITE) => loop {
+            match d.typeinfo_and_tag()? {
+                ((Type::Break, _), _) => return Ok(()),
+                (type_info, _) => skip_typed_value(d, type_info)?,
+            }
+        },
+        (Type::Array, a) | (Type::Object, a) => {
+            let mut n = d.kernel().unsigned(a)?;
+            if type_info.0 == Type::Object {
+                n *= 2;
+            }
+            for _ in 0..n {
+                let (type_info, _) = d.typeinfo_and_tag()?;
+                skip_typed_value(d, type_info)?;
+            }
+            Ok(())
+        }
+        (Type::Text, _) | (Type::Bytes, _) => d._text(&type_info).map(drop),
+        (Type::Float16, _) => d.kernel().f16(&type_info).map(drop),
+        (Type::Float32, _) => d.kernel().f32(&type_info).map(drop),
+        (Type::Float64, _) => d.kernel().f64(&type_info).map(drop),
+        (Type::UInt8, a)
+        | (Type::UInt16, a)
+        | (Type::UInt32, a)
+        | (Type::UInt64, a)
+        | (Type::Int8, a)
+        | (Type::Int16, a)
+        | (Type::Int32, a)
+        | (Type::Int64, a) => d.kernel().unsigned(a).map(drop),
+        _ => Ok(()),
+    }
+}
+
+fn add_value(predicate: &str, d: &mut Decoder<Cursor<&[u8]>>, indv: &mut IndividualObj, level: usize, opts: ValueOptions) -> Result<(), ParseError> {
+    if level > opts.max_depth {
+        return Err(ParseError::DepthExceeded(opts.max_depth));
+    }
+
+    match d.typeinfo_and_tag() {
+        Ok((type_info, tag)) => add_typed_value(predicate, type_info, tag, d, indv, level, opts),
+        Err(_) => Err(fail_read_value(predicate)),
+    }
+}
+
+/// The next integer, the decoder returns negative numbers less by one
+fn read_i64(predicate: &str, d: &mut Decoder<Cursor<&[u8]>>) -> Result<i64, ParseError> {
+    let (type_info, _tag) = d.typeinfo_and_tag().map_err(|_| fail_read_value(predicate))?;
+    let i = d._i64(&type_info).map_err(|_| fail_read_value(predicate))?;
+    Ok(if i < 0 {
+        i + 1
+    } else {
+        i
+    })
+}
+
+fn add_typed_value(
+    predicate: &str,
+    type_info: TypeInfo,
+    tag: u64,
+    d: &mut Decoder<Cursor<&[u8]>>,
+    indv: &mut IndividualObj,
+    level: usize,
+    opts: ValueOptions,
+) -> Result<(), ParseError> {
+    if !is_known_tag(tag) {
+        match opts.unknown_tags {
+            UnknownTagPolicy::Strict => {
+                return Err(ParseError::Invalid(format!("cbor: unknown tag {} in value of [{}]", tag, predicate)));
+            }
+            UnknownTagPolicy::SkipValue => {
+                return skip_typed_value(d, type_info).map_err(|_| fail_read_value(predicate));
             }
-            Type::Bytes => {
-                if let Ok(t) = d._text(&type_info) {
-                    if tag == TagId::Uri as u64 {
-                        indv.add_uri(&predicate, &t);
-                    } else {
-                        let mut lang = Lang::NONE;
-
-                        if tag == TagId::TextRu as u64 || tag == TagId::TextEn as u64 {
-                            if tag == TagId::TextRu as u64 {
-                                lang = Lang::RU;
-                            } else if tag == TagId::TextEn as u64 {
-                                lang = Lang::EN;
+            UnknownTagPolicy::AsString => {
+                let s = match type_info.0 {
+                    Type::Text | Type::Bytes => d._text(&type_info).ok(),
+                    Type::Bool => d._bool(&type_info).ok().map(|b| b.to_string()),
+                    Type::UInt8 | Type::Int8 | Type::UInt16 | Type::Int16 | Type::UInt32 | Type::Int32 | Type::Int64 | Type::UInt64 => {
+                        // the decoder returns negative numbers less by one, as above
+                        d._i64(&type_info).ok().map(|i| {
+                            if i < 0 {
+                                i + 1
+                            } else {
+                                i
                             }
-                        }
+                            .to_string()
+                        })
+                    }
+                    _ => None,
+                };
+                return match s {
+                    Some(s) => {
+                        indv.add_string(predicate, &s, Lang::NONE);
+                        Ok(())
+                    }
+                    None => Err(fail_read_value(predicate)),
+                };
+            }
+        }
+    }
 
-                        indv.add_string(predicate, &t, lang);
+    match type_info.0 {
+        Type::Array if type_info.1 == INDEFINITE => {
+            if tag == TagId::DecimalFraction as u64 {
+                error!("parse cbor: indefinite-length decimal fraction, predicate={}, id={}", predicate, indv.uri);
+                return Err(fail_read_value(predicate));
+            }
+            if level + 1 > opts.max_depth {
+                return Err(ParseError::DepthExceeded(opts.max_depth));
+            }
+            // the items up to the break stop code
+            let mut is_empty = true;
+            loop {
+                match d.typeinfo_and_tag() {
+                    Ok(((Type::Break, _), _)) => {
+                        if is_empty && level == 0 && opts.keep_empty_predicates {
+                            indv.resources.entry(predicate.to_owned()).or_default();
+                        }
+                        break;
                     }
+                    Ok((type_info, tag)) => {
+                        is_empty = false;
+                        add_typed_value(predicate, type_info, tag, d, indv, level + 1, opts)?
+                    }
+                    Err(_) => return Err(fail_read_value(predicate)),
                 }
             }
-            Type::Text => {
-                if let Ok(t) = d._text(&type_info) {
-                    if tag == TagId::Uri as u64 {
-                        indv.add_uri(&predicate, &t);
-                    } else {
-                        let mut lang = Lang::NONE;
-
-                        if tag == TagId::TextRu as u64 || tag == TagId::TextEn as u64 {
-                            if tag == TagId::TextRu as u64 {
-                                lang = Lang::RU;
-                            } else if tag == TagId::TextEn as u64 {
-                                lang = Lang::EN;
-                            }
-                        }
+        }
+        Type::Bool => {
+            let b = d._bool(&type_info).map_err(|_| fail_read_value(predicate))?;
+            indv.add_bool(&predicate, b);
+        }
+        Type::Bytes => {
+            let t = d._text(&type_info).map_err(|_| fail_read_value(predicate))?;
+            if tag == TagId::Uri as u64 {
+                indv.add_uri(&predicate, &t);
+            } else {
+                let mut lang = Lang::NONE;
 
-                        indv.add_string(predicate, &t, lang);
+                if tag == TagId::TextRu as u64 || tag == TagId::TextEn as u64 {
+                    if tag == TagId::TextRu as u64 {
+                        lang = Lang::RU;
+                    } else if tag == TagId::TextEn as u64 {
+                        lang = Lang::EN;
                     }
                 }
+
+                indv.add_string(predicate, &t, lang);
             }
-            Type::UInt8 | Type::Int8 | Type::UInt16 | Type::Int16 | Type::UInt32 | Type::Int32 | Type::Int64 | Type::UInt64 => {
-                if let Ok(mut i) = d._i64(&type_info) {
-                    if i < 0 {
-                        i += 1; // ?! this cbor decoder returned not correct negative number
-                    }
+        }
+        Type::Text => {
+            let t = d._text(&type_info).map_err(|_| fail_read_value(predicate))?;
+            if tag == TagId::Uri as u64 {
+                indv.add_uri(&predicate, &t);
+            } else {
+                let mut lang = Lang::NONE;
 
-                    if tag == TagId::EpochDateTime as u64 {
-                        indv.add_datetime(&predicate, i);
-                    } else {
-                        indv.add_integer(&predicate, i);
+                if tag == TagId::TextRu as u64 || tag == TagId::TextEn as u64 {
+                    if tag == TagId::TextRu as u64 {
+                        lang = Lang::RU;
+                    } else if tag == TagId::TextEn as u64 {
+                        lang = Lang::EN;
                     }
                 }
+
+                indv.add_string(predicate, &t, lang);
             }
-            Type::Array => {
-                if let Ok(len) = d._array(&type_info) {
-                    if tag == TagId::DecimalFraction as u64 {
-                        if let Ok((type_info, _tag)) = d.typeinfo_and_tag() {
-                            if let Ok(mut m) = d._i64(&type_info) {
-                                if m < 0 {
-                                    m += 1; // ?! this cbor decoder returned not correct negative number
-                                }
-                                if let Ok((type_info, _tag)) = d.typeinfo_and_tag() {
-                                    if let Ok(mut e) = d._i64(&type_info) {
-                                        if e < 0 {
-                                            e += 1; // ?! this cbor decoder returned not correct negative number
-                                        }
-                                        indv.add_decimal_d(&predicate, m as i64, e);
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        for _x in 0..len {
-                            add_value(predicate, d, indv);
-                        }
-                    }
+        }
+        Type::UInt8 | Type::Int8 | Type::UInt16 | Type::Int16 | Type::UInt32 | Type::Int32 | Type::Int64 | Type::UInt64 => {
+            let mut i = d._i64(&type_info).map_err(|_| fail_read_value(predicate))?;
+            if i < 0 {
+                i += 1; // ?! this cbor decoder returned not correct negative number
+            }
+
+            if tag == TagId::EpochDateTime as u64 {
+                indv.add_datetime(&predicate, i);
+            } else {
+                indv.add_integer(&predicate, i);
+            }
+        }
+        Type::Array => {
+            if let Ok(len) = d._array(&type_info) {
+                if tag == TagId::DecimalFraction as u64 {
+                    let m = read_i64(predicate, d)?;
+                    let e = read_i64(predicate, d)?;
+                    indv.add_decimal_d(&predicate, m, e);
+                } else if len == 0 && level == 0 && opts.keep_empty_predicates {
+                    indv.resources.entry(predicate.to_owned()).or_default();
                 } else {
-                    return false;
+                    for _x in 0..len {
+                        add_value(predicate, d, indv, level + 1, opts)?;
+                    }
                 }
+            } else {
+                return Err(fail_read_value(predicate));
             }
-            _ => {
-                error!("parse cbor:unknown type {:?}, predicate={}, id={}", type_info.0, predicate, indv.uri);
-                return false;
-            }
+        }
+        _ => {
+            error!("parse cbor:unknown type {:?}, predicate={}, id={}", type_info.0, predicate, indv.uri);
+            return Err(fail_read_value(predicate));
         }
     }
-    true
+    Ok(())
 }
@@ -0,0 +1,86 @@
+use crate::datatype::DataType;
+use crate::individual::*;
+use std::collections::{HashMap, HashSet};
+
+/// Index of individuals by their classes (the uri values of `rdf:type`)
+#[derive(Debug, Default)]
+pub struct ClassIndex {
+    subjects: HashMap<String, Vec<String>>,
+    is_indexed: HashSet<(String, String)>,
+}
+
+impl ClassIndex {
+    pub fn new() -> Self {
+        ClassIndex::default()
+    }
+
+    pub fn add(&mut self, indv: &mut Individual) {
+        if let Some(types) = indv.get_resources("rdf:type") {
+            for t in types.iter().filter(|r| r.rtype == DataType::Uri) {
+                let key = (t.get_uri().to_owned(), indv.get_id().to_owned());
+                if !self.is_indexed.contains(&key) {
+                    self.subjects.entry(key.0.clone()).or_default().push(key.1.clone());
+                    self.is_indexed.insert(key);
+                }
+            }
+        }
+    }
+
+    /// Uris of individuals of class, in the order they were added
+    pub fn get(&self, class_iri: &str) -> &[String] {
+        self.subjects.get(class_iri).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn classes(&self) -> Vec<&str> {
+        self.subjects.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.subjects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subjects.is_empty()
+    }
+}
+
+impl Extend<Individual> for ClassIndex {
+    fn extend<I: IntoIterator<Item = Individual>>(&mut self, iter: I) {
+        for mut indv in iter {
+            self.add(&mut indv);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(uri: &str, classes: &[&str]) -> Individual {
+        let mut indv = Individual::default();
+        indv.set_id(uri);
+        for class in classes {
+            indv.add_uri("rdf:type", class);
+        }
+        indv
+    }
+
+    #[test]
+    fn index_of_typed_individuals() {
+        let mut index = ClassIndex::new();
+        index.extend(vec![typed("d:a", &["v-s:Document"]), typed("d:b", &["v-s:Document", "v-s:Person"]), typed("d:c", &[])]);
+        let mut untyped_value = typed("d:d", &[]);
+        untyped_value.add_string("rdf:type", "v-s:Person", crate::datatype::Lang::NONE);
+        index.add(&mut untyped_value);
+        // the second add of the same individual is not indexed twice
+        index.add(&mut typed("d:a", &["v-s:Document"]));
+
+        assert_eq!(index.get("v-s:Document"), &["d:a".to_owned(), "d:b".to_owned()]);
+        assert_eq!(index.get("v-s:Person"), &["d:b".to_owned()]);
+        assert!(index.get("v-s:Absent").is_empty());
+        let mut classes = index.classes();
+        classes.sort_unstable();
+        assert_eq!(classes, vec!["v-s:Document", "v-s:Person"]);
+        assert_eq!(index.len(), 2);
+    }
+}
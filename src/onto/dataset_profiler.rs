@@ -0,0 +1,75 @@
+use crate::datatype::DataType;
+use crate::individual::*;
+use std::collections::HashMap;
+
+/// Statistics of a dataset read individual by individual: count of individuals, count of values
+/// per predicate and count of values per datatype over all predicates
+#[derive(Debug, Default, Clone)]
+pub struct DatasetProfiler {
+    individuals: u64,
+    predicate_counts: HashMap<String, u64>,
+    datatype_counts: HashMap<DataType, u64>,
+}
+
+impl DatasetProfiler {
+    pub fn new() -> Self {
+        DatasetProfiler::default()
+    }
+
+    /// Adds the values of the parsed predicates of individual to the statistics
+    pub fn record(&mut self, indv: &Individual) {
+        self.individuals += 1;
+        for (predicate, values) in indv.get_obj().get_resources() {
+            *self.predicate_counts.entry(predicate.to_owned()).or_default() += values.len() as u64;
+            for r in values {
+                *self.datatype_counts.entry(r.rtype.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    pub fn individual_count(&self) -> u64 {
+        self.individuals
+    }
+
+    /// Count of values of the predicate over all recorded individuals
+    pub fn predicate_count(&self, predicate: &str) -> u64 {
+        self.predicate_counts.get(predicate).copied().unwrap_or(0)
+    }
+
+    /// Count of values of every datatype over all predicates, datatypes without values are absent
+    pub fn datatype_histogram(&self) -> HashMap<DataType, u64> {
+        self.datatype_counts.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatype::Lang;
+
+    #[test]
+    fn histogram_of_known_datatype_mix() {
+        let mut a = Individual::default();
+        a.set_id("d:a");
+        a.add_uri("rdf:type", "v-s:Document");
+        a.add_string("rdfs:label", "a", Lang::EN);
+        a.add_string("rdfs:label", "а", Lang::RU);
+        a.add_integer("v-s:count", 1);
+        let mut b = Individual::default();
+        b.set_id("d:b");
+        b.add_uri("rdf:type", "v-s:Document");
+        b.add_string("v-s:count", "2", Lang::NONE);
+        b.add_datetime("v-s:created", 0);
+
+        let mut profiler = DatasetProfiler::new();
+        profiler.record(&a);
+        profiler.record(&b);
+
+        let expected: HashMap<DataType, u64> = [(DataType::Uri, 2), (DataType::String, 3), (DataType::Integer, 1), (DataType::Datetime, 1)].iter().cloned().collect();
+        assert_eq!(profiler.datatype_histogram(), expected);
+        assert_eq!(profiler.individual_count(), 2);
+        assert_eq!(profiler.predicate_count("v-s:count"), 2);
+        assert_eq!(profiler.predicate_count("rdfs:label"), 2);
+        assert_eq!(profiler.predicate_count("v-s:absent"), 0);
+    }
+}
@@ -1,4 +1,10 @@
-#[derive(PartialEq, Debug, Clone)]
+use crate::resource::{decode_base64, encode_base64};
+use chrono::{TimeZone, Utc};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
 #[repr(u8)]
 pub enum DataType {
     Uri = 1,
@@ -38,6 +44,39 @@ impl DataType {
     }
 }
 
+/// The names of `DataType::new_from_str` in any case, e.g. `datetime` or `Datetime`
+impl FromStr for DataType {
+    type Err = LexicalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut name = s.to_ascii_lowercase();
+        if let Some(first) = name.get_mut(..1) {
+            first.make_ascii_uppercase();
+        }
+        DataType::new_from_str(&name).ok_or_else(|| LexicalError::UnknownDatatype(s.to_owned()))
+    }
+}
+
+/// A datatype name or a value which does not match the lexical form of its datatype
+#[derive(PartialEq, Debug, Clone)]
+pub enum LexicalError {
+    UnknownDatatype(String),
+    InvalidValue { predicate: String, value: String, datatype: DataType },
+}
+
+impl fmt::Display for LexicalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexicalError::UnknownDatatype(name) => write!(f, "unknown datatype [{}]", name),
+            LexicalError::InvalidValue {
+                predicate,
+                value,
+                datatype,
+            } => write!(f, "value [{}] of [{}] is not a valid {:?}", value, predicate, datatype),
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 #[repr(u8)]
 pub enum Lang {
@@ -89,3 +128,246 @@ pub fn exponent_to_scale(m: &i64, e: &i64) -> (i64, u32) {
 
     (num, scale)
 }
+
+/// `mantissa * 10^exp` with the trailing zeros of the mantissa moved to the exponent, zero is `(0, 0)`.
+/// Equal values have equal normalized forms.
+pub fn normalize_decimal(mantissa: i64, exp: i64) -> (i64, i64) {
+    if mantissa == 0 {
+        return (0, 0);
+    }
+    let (mut m, mut e) = (mantissa, exp);
+    while m % 10 == 0 {
+        m /= 10;
+        e = e.saturating_add(1);
+    }
+    (m, e)
+}
+
+/// Numeric comparison of `m1 * 10^e1` and `m2 * 10^e2`, total for every mantissa and exponent
+pub fn compare_decimals(m1: i64, e1: i64, m2: i64, e2: i64) -> Ordering {
+    let (m1, e1) = normalize_decimal(m1, e1);
+    let (m2, e2) = normalize_decimal(m2, e2);
+    let by_sign = m1.signum().cmp(&m2.signum());
+    if by_sign != Ordering::Equal || m1 == 0 {
+        return by_sign;
+    }
+
+    let (a, b) = (m1.unsigned_abs(), m2.unsigned_abs());
+    let (da, db) = (a.to_string().len() as i128, b.to_string().len() as i128);
+    // the position of the leading digit decides, then the digits aligned to the same length
+    let magnitude = (da + e1 as i128).cmp(&(db + e2 as i128)).then_with(|| {
+        let len = da.max(db) as u32;
+        (u128::from(a) * 10_u128.pow(len - da as u32)).cmp(&(u128::from(b) * 10_u128.pow(len - db as u32)))
+    });
+    if m1 < 0 {
+        magnitude.reverse()
+    } else {
+        magnitude
+    }
+}
+
+/// The exponents out of `-MAX_PLAIN_EXPONENT..=MAX_PLAIN_EXPONENT` are not written in the plain notation
+pub const MAX_PLAIN_EXPONENT: i64 = 1000;
+
+/// `format_decimal` writes the decimal in the `E` notation, zero is always `0`
+pub fn is_e_notation(mantissa: i64, exp: i64) -> bool {
+    mantissa != 0 && !(-MAX_PLAIN_EXPONENT..=MAX_PLAIN_EXPONENT).contains(&exp)
+}
+
+/// Plain notation of `mantissa * 10^exp`: the `.` separator, no grouping and no exponent, independent of the locale.
+/// A negative exponent gives as many fraction digits, `format_decimal(150, -2)` is `1.50`.
+/// An exponent beyond `MAX_PLAIN_EXPONENT` is written in the `E` notation, `format_decimal(15, 2000)` is `15E2000`:
+/// it is not a lexical form of `xsd:decimal`, the serializers write such a value as `xsd:double`.
+pub fn format_decimal(mantissa: i64, exp: i64) -> String {
+    let digits = mantissa.unsigned_abs().to_string();
+    let sign = if mantissa < 0 {
+        "-"
+    } else {
+        ""
+    };
+
+    if is_e_notation(mantissa, exp) {
+        return format!("{}{}E{}", sign, digits, exp);
+    }
+    if mantissa == 0 && !(-MAX_PLAIN_EXPONENT..0).contains(&exp) {
+        return "0".to_owned();
+    }
+
+    if exp >= 0 {
+        return format!("{}{}{}", sign, digits, "0".repeat(exp as usize));
+    }
+
+    let scale = exp.unsigned_abs() as usize;
+    if digits.len() > scale {
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        format!("{}{}.{}", sign, int, frac)
+    } else {
+        format!("{}0.{}{}", sign, "0".repeat(scale - digits.len()), digits)
+    }
+}
+
+/// Unit of the integer value of datetime
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum EpochUnit {
+    Seconds,
+    Millis,
+}
+
+impl EpochUnit {
+    /// Datetime is stored in seconds, the fraction of a second is dropped
+    pub fn to_seconds(self, v: i64) -> i64 {
+        match self {
+            EpochUnit::Seconds => v,
+            EpochUnit::Millis => v.div_euclid(1000),
+        }
+    }
+}
+
+/// Lexical form of the binary values in text formats
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum BinaryEncoding {
+    /// `xsd:base64Binary`
+    #[default]
+    Base64,
+    /// `xsd:hexBinary`, the digits in upper case
+    Hex,
+}
+
+impl BinaryEncoding {
+    /// The encoding of the datatype given as the prefixed name or the full IRI
+    pub fn from_datatype(datatype: &str) -> Option<BinaryEncoding> {
+        match datatype {
+            "xsd:base64Binary" | "http://www.w3.org/2001/XMLSchema#base64Binary" => Some(BinaryEncoding::Base64),
+            "xsd:hexBinary" | "http://www.w3.org/2001/XMLSchema#hexBinary" => Some(BinaryEncoding::Hex),
+            _ => None,
+        }
+    }
+
+    pub fn datatype(self) -> &'static str {
+        match self {
+            BinaryEncoding::Base64 => "xsd:base64Binary",
+            BinaryEncoding::Hex => "xsd:hexBinary",
+        }
+    }
+
+    pub fn encode(self, data: &[u8]) -> String {
+        match self {
+            BinaryEncoding::Base64 => encode_base64(data),
+            BinaryEncoding::Hex => data.iter().map(|b| format!("{:02X}", b)).collect(),
+        }
+    }
+
+    /// `None` for a malformed value, hex digits are accepted in any case
+    pub fn decode(self, value: &str) -> Option<Vec<u8>> {
+        match self {
+            BinaryEncoding::Base64 => decode_base64(value),
+            BinaryEncoding::Hex => {
+                if value.len() % 2 == 1 || !value.is_ascii() {
+                    return None;
+                }
+                (0..value.len()).step_by(2).map(|i| u8::from_str_radix(&value[i..i + 2], 16).ok()).collect()
+            }
+        }
+    }
+}
+
+/// Bytes of the literal of `xsd:base64Binary` or `xsd:hexBinary`, `None` for other datatypes and malformed values
+pub fn decode_binary_literal(value: &str, datatype: &str) -> Option<Vec<u8>> {
+    BinaryEncoding::from_datatype(datatype).and_then(|enc| enc.decode(value))
+}
+
+/// ISO-8601 (xsd:dateTime) representation of unix time in seconds, `None` if out of range
+pub fn datetime_secs_to_xsd(secs: i64) -> Option<String> {
+    Utc.timestamp_opt(secs, 0).single().map(|dt| format!("{:?}", dt))
+}
+
+/// ISO-8601 (xsd:dateTime) representation of unix time in milliseconds, `None` if out of range
+pub fn datetime_millis_to_xsd(millis: i64) -> Option<String> {
+    Utc.timestamp_millis_opt(millis).single().map(|dt| format!("{:?}", dt))
+}
+
+/// Resolves `now` and `now+<n><unit>` / `now-<n><unit>` (unit is one of `s`, `m`, `h`, `d`)
+/// relative to the base unix time in seconds
+pub fn parse_relative_datetime(s: &str, base: i64) -> Option<i64> {
+    let rest = s.trim().strip_prefix("now")?;
+    if rest.is_empty() {
+        return Some(base);
+    }
+
+    let sign = match rest.as_bytes()[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let body = &rest[1..];
+    let mul = match body.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86400,
+        _ => return None,
+    };
+    let n = &body[..body.len() - 1];
+    if n.is_empty() || !n.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    n.parse::<i64>().ok()?.checked_mul(mul)?.checked_mul(sign).and_then(|d| base.checked_add(d))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_decimal_with_huge_exponents() {
+        assert_eq!(format_decimal(150, -2), "1.50");
+        assert_eq!(format_decimal(15, 2), "1500");
+        assert_eq!(format_decimal(1, i64::MAX), format!("1E{}", i64::MAX));
+        assert_eq!(format_decimal(-15, i64::MIN), format!("-15E{}", i64::MIN));
+        assert_eq!(format_decimal(0, i64::MAX), "0");
+        assert_eq!(format_decimal(0, i64::MIN), "0");
+        assert_eq!(format_decimal(1, -MAX_PLAIN_EXPONENT).len(), MAX_PLAIN_EXPONENT as usize + 2);
+        assert!(is_e_notation(1, MAX_PLAIN_EXPONENT + 1));
+        assert!(!is_e_notation(0, i64::MAX));
+    }
+
+    #[test]
+    fn format_decimal_of_negative_mantissa() {
+        assert_eq!(format_decimal(-5, 0), "-5");
+        assert_eq!(format_decimal(-5, 2), "-500");
+        assert_eq!(format_decimal(-5, -1), "-0.5");
+        assert_eq!(format_decimal(-15, -3), "-0.015");
+        assert_eq!(format_decimal(-150, -2), "-1.50");
+        assert_eq!(format_decimal(0, -2), "0.00");
+        assert_eq!(format_decimal(i64::MIN, 0), i64::MIN.to_string());
+    }
+
+    #[test]
+    fn same_integer_as_seconds_and_millis() {
+        let v = 1_600_000_000_000;
+        let secs = datetime_secs_to_xsd(v).unwrap();
+        let millis = datetime_millis_to_xsd(v).unwrap();
+        assert_ne!(secs, millis);
+        assert!(secs.starts_with("+52671-"));
+        assert_eq!(millis, "2020-09-13T12:26:40Z");
+        assert_eq!(datetime_secs_to_xsd(EpochUnit::Millis.to_seconds(v)).unwrap(), millis);
+        assert_eq!(EpochUnit::Millis.to_seconds(-1), -1);
+        assert_eq!(datetime_secs_to_xsd(i64::MAX), None);
+    }
+
+    #[test]
+    fn relative_datetime() {
+        let base = 1_600_000_000;
+        assert_eq!(parse_relative_datetime("now", base), Some(base));
+        assert_eq!(parse_relative_datetime(" now-1d ", base), Some(base - 86_400));
+        assert_eq!(parse_relative_datetime("now+90m", base), Some(base + 5_400));
+        assert_eq!(parse_relative_datetime("now-2h", base), Some(base - 7_200));
+        assert_eq!(parse_relative_datetime("now+1s", base), Some(base + 1));
+        assert_eq!(parse_relative_datetime("yesterday", base), None);
+        assert_eq!(parse_relative_datetime("now-d", base), None);
+        assert_eq!(parse_relative_datetime("now-1w", base), None);
+        assert_eq!(parse_relative_datetime("now--1d", base), None);
+        assert_eq!(parse_relative_datetime("now1d", base), None);
+        assert_eq!(parse_relative_datetime("now+9999999999999999d", base), None);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::datatype::DataType;
+use crate::individual::*;
+use serde_json::json;
+use serde_json::value::Value as JSONValue;
+use std::collections::HashMap;
+
+/// Summary of an export: the subjects in the order they were written, count of subjects per class
+/// (the uri values of `rdf:type`) and the total count of written triples
+#[derive(Debug, Default, Clone)]
+pub struct ExportManifest {
+    subjects: Vec<String>,
+    class_counts: HashMap<String, usize>,
+    triples: usize,
+}
+
+impl ExportManifest {
+    pub fn new() -> Self {
+        ExportManifest::default()
+    }
+
+    /// Records the individual written with `triples` triples
+    pub fn record(&mut self, indv: &Individual, triples: usize) {
+        self.subjects.push(indv.get_id().to_owned());
+        for t in indv.values("rdf:type").filter(|r| r.rtype == DataType::Uri) {
+            *self.class_counts.entry(t.get_uri().to_owned()).or_default() += 1;
+        }
+        self.triples += triples;
+    }
+
+    pub fn subjects(&self) -> &[String] {
+        &self.subjects
+    }
+
+    pub fn class_count(&self, class_iri: &str) -> usize {
+        self.class_counts.get(class_iri).copied().unwrap_or(0)
+    }
+
+    pub fn triple_count(&self) -> usize {
+        self.triples
+    }
+
+    /// `{"subjects": [...], "classes": {"class": count, ...}, "triples": count}`
+    pub fn to_json(&self) -> JSONValue {
+        json!({
+            "subjects": self.subjects,
+            "classes": self.class_counts,
+            "triples": self.triples,
+        })
+    }
+}
@@ -1,14 +1,18 @@
-use crate::datatype::{DataType, Lang};
+use crate::datatype::{datetime_secs_to_xsd, normalize_decimal, DataType, Lang, LexicalError};
 use crate::parser::*;
-use crate::resource::{Resource, Value};
+use crate::resource::{decode_base64, Resource, Value};
 use chrono::offset::LocalResult::Single;
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use hmac::{Hmac, Mac};
 use num::FromPrimitive;
 use num_traits::pow;
 use rust_decimal::Decimal;
+use sha2::Sha256;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::io::{Error, ErrorKind};
+use std::iter::FromIterator;
 use std::ops::Sub;
 use std::str::FromStr;
 
@@ -31,6 +35,17 @@ pub struct RawObj {
     pub len_predicates: u32,
     pub cur_predicates: u32,
     pub raw_type: RawType,
+    /// count of elements of the top-level structure of the raw data
+    pub top_level_len: u32,
+    /// the limit of nested values, the parsing fails with `ParseError::DepthExceeded` when it is crossed
+    pub max_depth: usize,
+    /// the handling of cbor values with an unknown tag
+    pub unknown_tags: UnknownTagPolicy,
+    /// a predicate with an empty array of values is kept as present without values, by default it is dropped.
+    /// The serializers write such a predicate as an empty array, so the round-trip is lossless
+    pub keep_empty_predicates: bool,
+    /// the handling of msgpack `bin` values tagged as strings
+    pub bin_policy: MsgpackBinPolicy,
 }
 
 impl RawObj {
@@ -41,6 +56,11 @@ impl RawObj {
             cur: 0,
             len_predicates: 0,
             cur_predicates: 0,
+            top_level_len: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            unknown_tags: UnknownTagPolicy::Strict,
+            keep_empty_predicates: false,
+            bin_policy: MsgpackBinPolicy::TryUtf8,
         }
     }
 
@@ -51,6 +71,11 @@ impl RawObj {
             cur: 0,
             len_predicates: 0,
             cur_predicates: 0,
+            top_level_len: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            unknown_tags: UnknownTagPolicy::Strict,
+            keep_empty_predicates: false,
+            bin_policy: MsgpackBinPolicy::TryUtf8,
         }
     }
 }
@@ -78,6 +103,17 @@ impl Individual {
         }
     }
 
+    /// Empty individual with the space reserved for the given count of predicates
+    pub fn with_capacity(predicates: usize) -> Self {
+        Individual {
+            obj: IndividualObj {
+                uri: String::default(),
+                resources: HashMap::with_capacity(predicates),
+            },
+            raw: RawObj::new_empty(),
+        }
+    }
+
     pub fn new_from_obj(obj: &IndividualObj) -> Self {
         let mut new_obj = IndividualObj {
             uri: obj.uri.to_owned(),
@@ -100,10 +136,51 @@ impl Individual {
                 len_predicates: 0,
                 cur_predicates: 0,
                 raw_type: RawType::Cbor,
+                top_level_len: 0,
+                max_depth: DEFAULT_MAX_DEPTH,
+                unknown_tags: UnknownTagPolicy::Strict,
+                keep_empty_predicates: false,
+                bin_policy: MsgpackBinPolicy::TryUtf8,
             },
         }
     }
 
+    /// Individual of the triples `(predicate, value, datatype name)`, the name is parsed by `DataType::from_str`.
+    /// Every value is checked against its datatype: an integer or a decimal in the decimal notation, a boolean
+    /// as `true` or `false`, a datetime in the forms of `add_datetime_from_str`, a binary in base64,
+    /// an uri is not empty. Strings are taken as is, without the language.
+    pub fn from_pairs_typed(uri: &str, pairs: &[(&str, &str, &str)]) -> Result<Individual, LexicalError> {
+        let mut indv = Individual::default();
+        indv.set_id(uri);
+        for (predicate, value, datatype) in pairs {
+            let datatype: DataType = datatype.parse()?;
+            let invalid = || LexicalError::InvalidValue {
+                predicate: (*predicate).to_owned(),
+                value: (*value).to_owned(),
+                datatype: datatype.clone(),
+            };
+            match datatype {
+                DataType::Uri if value.is_empty() => return Err(invalid()),
+                DataType::Uri => indv.add_uri(predicate, value),
+                DataType::String => indv.add_string(predicate, value, Lang::NONE),
+                DataType::Integer => indv.add_integer(predicate, value.parse().map_err(|_| invalid())?),
+                DataType::Datetime => indv.add_datetime(predicate, datetime_from_str(value).ok_or_else(invalid)?),
+                DataType::Decimal => {
+                    let d = Decimal::from_str(value).map_err(|_| invalid())?;
+                    let mantissa = i64::try_from(d.mantissa()).map_err(|_| invalid())?;
+                    indv.add_decimal_d(predicate, mantissa, -(d.scale() as i64));
+                }
+                DataType::Boolean => match *value {
+                    "true" => indv.add_bool(predicate, true),
+                    "false" => indv.add_bool(predicate, false),
+                    _ => return Err(invalid()),
+                },
+                DataType::Binary => indv.add_binary(predicate, decode_base64(value).ok_or_else(invalid)?),
+            }
+        }
+        Ok(indv)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.obj.resources.is_empty()
     }
@@ -116,6 +193,12 @@ impl Individual {
         self.obj.remove(predicate)
     }
 
+    /// Keeps only the predicates for which `f(predicate, values)` returns true, all predicates are parsed first
+    pub fn retain<F: FnMut(&str, &[Resource]) -> bool>(&mut self, mut f: F) {
+        self.parse_all();
+        self.obj.resources.retain(|predicate, values| f(predicate, values));
+    }
+
     pub fn clear(&mut self, predicate: &str) {
         self.obj.clear(predicate);
     }
@@ -133,33 +216,10 @@ impl Individual {
     }
 
     pub fn add_datetime_from_str(&mut self, predicate: &str, value: &str) {
-        if value.contains('Z') {
-            if let Ok(v) = DateTime::parse_from_rfc3339(&value) {
-                self.add_datetime(&predicate, v.timestamp());
-            } else {
-                error!("fail parse [{}] to datetime", value);
-            }
+        if let Some(v) = datetime_from_str(value) {
+            self.add_datetime(&predicate, v);
         } else {
-            let ndt;
-            if value.len() == 10 {
-                if value.contains('.') {
-                    ndt = NaiveDateTime::parse_from_str(&(value.to_owned() + "T00:00:00"), "%d.%m.%YT%H:%M:%S");
-                } else {
-                    ndt = NaiveDateTime::parse_from_str(&(value.to_owned() + "T00:00:00"), "%Y-%m-%dT%H:%M:%S");
-                }
-            } else {
-                ndt = NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S")
-            }
-
-            if let Ok(v) = ndt {
-                if let Single(offset) = Local.offset_from_local_datetime(&v) {
-                    self.add_datetime(&predicate, v.sub(offset).timestamp());
-                } else {
-                    self.add_datetime(&predicate, v.timestamp());
-                }
-            } else {
-                error!("fail parse [{}] to datetime", value);
-            }
+            error!("fail parse [{}] to datetime", value);
         }
     }
 
@@ -271,6 +331,18 @@ impl Individual {
         &self.obj.uri
     }
 
+    /// Is the subject a blank node, the uri of a blank node has the prefix `_:` in every format
+    pub fn subject_is_blank(&self) -> bool {
+        self.obj.uri.starts_with("_:")
+    }
+
+    /// Parses the raw data up to the predicate if it is not parsed yet
+    fn parse_predicate(&mut self, predicate: &str) {
+        if !self.obj.resources.contains_key(predicate) && self.raw.cur < self.raw.data.len() as u64 {
+            parse_to_predicate(predicate, self);
+        }
+    }
+
     pub fn is_exists(&mut self, predicate: &str) -> bool {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
@@ -459,6 +531,330 @@ impl Individual {
         })
     }
 
+    /// Languages of `required` missing in the string values of each predicate, the tags are compared case-insensitive.
+    /// Predicates with all required languages present are not included.
+    pub fn lang_coverage(&mut self, predicates: &[&str], required: &[&str]) -> HashMap<String, Vec<String>> {
+        let mut res = HashMap::new();
+        for predicate in predicates {
+            self.parse_predicate(predicate);
+            let present: Vec<&str> = self
+                .obj
+                .resources
+                .get(*predicate)
+                .map(|v| {
+                    v.iter()
+                        .filter_map(|el| match &el.value {
+                            Value::Str(_, l) if *l != Lang::NONE => Some(l.to_string()),
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let missing: Vec<String> = required.iter().map(|l| l.trim().to_ascii_lowercase()).filter(|l| !present.contains(&l.as_str())).collect();
+            if !missing.is_empty() {
+                res.insert((*predicate).to_owned(), missing);
+            }
+        }
+        res
+    }
+
+    /// Converts the string values of predicates which are absolute IRIs (`scheme:rest`) into uri values,
+    /// the rest of the values are kept as is
+    pub fn promote_uris(&mut self, predicates: &[&str]) {
+        self.parse_all();
+        for predicate in predicates {
+            if let Some(values) = self.obj.resources.get_mut(*predicate) {
+                for el in values.iter_mut() {
+                    if let Value::Str(s, _) = &el.value {
+                        if is_absolute_iri(s) {
+                            el.value = Value::Uri(s.to_owned());
+                            el.rtype = DataType::Uri;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Trims the string values and collapses every run of whitespace inside them into a single space,
+    /// whitespace is any Unicode `White_Space` char, so `\r\n`, tabs and no-break spaces are included.
+    /// With `predicates` only their values are normalized. Values of other datatypes are kept as is.
+    pub fn normalize_whitespace(&mut self, predicates: Option<&[&str]>) {
+        self.parse_all();
+        for (predicate, values) in self.obj.resources.iter_mut() {
+            if predicates.is_some_and(|ps| !ps.contains(&predicate.as_str())) {
+                continue;
+            }
+            for el in values.iter_mut() {
+                if let Value::Str(s, _) = &mut el.value {
+                    *s = s.split_whitespace().collect::<Vec<&str>>().join(" ");
+                }
+            }
+        }
+    }
+
+    /// Replaces the values of the listed predicates by the mode, other predicates are kept as is.
+    /// The replacement values are strings, the language of a string value is kept.
+    pub fn redact(&mut self, predicates: &[&str], replacement: RedactMode) {
+        self.parse_all();
+        for predicate in predicates {
+            if replacement == RedactMode::Remove {
+                self.obj.resources.remove(*predicate);
+                continue;
+            }
+            if let Some(values) = self.obj.resources.get_mut(*predicate) {
+                for el in values.iter_mut() {
+                    let lang = match &el.value {
+                        Value::Str(_, l) => l.clone(),
+                        _ => Lang::NONE,
+                    };
+                    let s = match &replacement {
+                        RedactMode::Hash(salt) => hmac_sha256_hex(salt.as_bytes(), el.value_as_str().as_bytes()),
+                        _ => String::new(),
+                    };
+                    el.value = Value::Str(s, lang);
+                    el.rtype = DataType::String;
+                }
+            }
+        }
+    }
+
+    /// Values of the parsed predicate in the stored order, empty for an absent predicate.
+    /// The raw data is not parsed here: call `parse_all` first for an individual read from raw data.
+    pub fn values(&self, predicate: &str) -> impl Iterator<Item = &Resource> {
+        self.obj.resources.get(predicate).into_iter().flatten()
+    }
+
+    /// Predicates with values of a datatype other than expected, every offending datatype is reported once,
+    /// sorted by predicate. Predicates absent in `expected` are not checked.
+    pub fn datatype_conflicts(&self, expected: &HashMap<String, DataType>) -> Vec<(String, DataType)> {
+        let mut res: Vec<(String, DataType)> = vec![];
+        for (predicate, values) in self.obj.resources.iter() {
+            if let Some(dt) = expected.get(predicate) {
+                for el in values.iter().filter(|el| el.rtype != *dt) {
+                    if !res.iter().any(|(p, t)| p == predicate && *t == el.rtype) {
+                        res.push((predicate.to_owned(), el.rtype.clone()));
+                    }
+                }
+            }
+        }
+        res.sort_by(|a, b| a.0.cmp(&b.0));
+        res
+    }
+
+    /// Pairs predicate-value of the parsed part, predicates sorted, values in the stored order
+    pub fn flatten(&self) -> Vec<(String, Resource)> {
+        let mut predicates: Vec<&String> = self.obj.resources.keys().collect();
+        predicates.sort();
+        let mut res = vec![];
+        for predicate in predicates {
+            for el in self.obj.resources[predicate].iter() {
+                res.push((predicate.to_owned(), el.get_copy()));
+            }
+        }
+        res
+    }
+
+    /// Merges the predicates of other into self, the values of predicates present in both are merged by the policy.
+    /// An empty uri of self is taken from other, `false` if the uri differs and the policy rejects it.
+    pub fn merge(&mut self, other: &Individual, policy: MergePolicy) -> bool {
+        self.merge_resources(other, policy, None)
+    }
+
+    /// As `merge`, the values taken from other get the id of source, which is kept by the copies of them
+    pub fn merge_with_source(&mut self, other: &Individual, policy: MergePolicy, source: u16) -> bool {
+        self.merge_resources(other, policy, Some(source))
+    }
+
+    fn merge_resources(&mut self, other: &Individual, policy: MergePolicy, source: Option<u16>) -> bool {
+        self.parse_all();
+
+        if self.obj.uri.is_empty() {
+            self.obj.uri = other.obj.uri.clone();
+        } else if self.obj.uri != other.obj.uri {
+            match policy.uri {
+                UriPolicy::KeepTarget => {}
+                UriPolicy::TakeSource => self.obj.uri = other.obj.uri.clone(),
+                UriPolicy::Reject => return false,
+            }
+        }
+
+        for (predicate, resources) in other.obj.resources.iter() {
+            let stamped: Vec<Resource>;
+            let resources = match source {
+                Some(source) => {
+                    stamped = resources
+                        .iter()
+                        .map(|el| {
+                            let mut r = el.get_copy();
+                            r.source = source;
+                            r
+                        })
+                        .collect();
+                    &stamped
+                }
+                None => resources,
+            };
+            if self.obj.resources.get(predicate).is_none_or(|v| v.is_empty()) {
+                self.obj.add_resources(predicate, resources);
+                continue;
+            }
+            match policy.values {
+                ValuePolicy::Append => self.obj.add_resources(predicate, resources),
+                ValuePolicy::KeepBoth => {
+                    let values = self.obj.resources.entry(predicate.to_owned()).or_default();
+                    for el in resources.iter() {
+                        if !values.iter().any(|v| v.is_same_value(el)) {
+                            let mut r = el.get_copy();
+                            r.order = values.len() as u16;
+                            values.push(r);
+                        }
+                    }
+                }
+                ValuePolicy::PreferSelf => {}
+                ValuePolicy::PreferOther => self.obj.set_resources(predicate, resources),
+            }
+        }
+        true
+    }
+
+    /// The serialization carries no version, the detected format and the arity of its top-level structure are used instead
+    pub fn format_version(&self) -> FormatMarker {
+        FormatMarker {
+            raw_type: self.raw.raw_type,
+            arity: self.raw.top_level_len,
+        }
+    }
+
+    /// Count of values of the parsed predicate
+    pub fn value_count(&self, predicate: &str) -> usize {
+        self.obj.resources.get(predicate).map_or(0, |v| v.len())
+    }
+
+    /// Count of the triples `write_individual` writes for the parsed part: binaries, datetimes out of range
+    /// and the values of `v-s:updateCounter` are not written
+    pub fn triple_count(&self) -> usize {
+        self.obj
+            .resources
+            .iter()
+            .filter(|(predicate, _)| *predicate != "v-s:updateCounter")
+            .map(|(_, values)| {
+                values
+                    .iter()
+                    .filter(|r| match r.rtype {
+                        DataType::Binary => false,
+                        DataType::Datetime => datetime_secs_to_xsd(r.get_datetime()).is_some(),
+                        _ => true,
+                    })
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Up to `limit` values of the parsed predicate starting from `offset`, empty if the offset is past the end
+    pub fn values_page(&self, predicate: &str, offset: usize, limit: usize) -> Vec<&Resource> {
+        self.values(predicate).skip(offset).take(limit).collect()
+    }
+
+    /// Hash of the meaning of the parsed part: the order of values and the representation of numbers are ignored,
+    /// so integer `7` and decimal `7.0` hash equal. Stable across runs (FNV-1a).
+    pub fn semantic_hash(&self) -> u64 {
+        let mut items = vec![];
+        for (predicate, values) in self.obj.resources.iter() {
+            for el in values.iter() {
+                let form = match &el.value {
+                    Value::Int(i) => format!("num:{:?}", normalize_decimal(*i, 0)),
+                    Value::Num(m, e) => format!("num:{:?}", normalize_decimal(*m, *e)),
+                    Value::Str(s, l) => format!("str@{}:{}", l.to_string(), s),
+                    _ => format!("{:?}:{}", el.rtype, el.value_as_str()),
+                };
+                items.push(format!("{}\t{}", predicate, form));
+            }
+        }
+        items.sort();
+
+        let mut data = String::new();
+        for item in items.iter() {
+            data.push_str(item);
+            data.push('\n');
+        }
+        fnv1a(data.as_bytes())
+    }
+
+    /// Replaces the blank nodes, the uri of individual and the uri values with the prefix `_:`, by the skolem IRIs
+    /// `<base>/.well-known/genid/<hash of the blank id>`. The same blank id always gets the same IRI.
+    pub fn skolemize(&mut self, base: &str) {
+        self.parse_all();
+        let skolem = |id: &str| format!("{}/.well-known/genid/{:016x}", base.trim_end_matches('/'), fnv1a(id.as_bytes()));
+
+        if let Some(id) = self.obj.uri.strip_prefix("_:") {
+            self.obj.uri = skolem(id);
+        }
+        for values in self.obj.resources.values_mut() {
+            for el in values.iter_mut() {
+                if let Value::Uri(s) = &el.value {
+                    if let Some(id) = s.strip_prefix("_:") {
+                        el.value = Value::Uri(skolem(id));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Is the first value of predicate an uri, `None` if predicate has no values
+    pub fn first_is_uri(&mut self, predicate: &str) -> Option<bool> {
+        self.parse_predicate(predicate);
+        self.obj.resources.get(predicate).and_then(|v| v.first()).map(|r| r.rtype == DataType::Uri)
+    }
+
+    /// The first uri value of the parsed `rdf:type`, `None` for an individual without a type.
+    /// The raw data is not parsed here, as for `values`.
+    pub fn class(&self) -> Option<&str> {
+        self.values("rdf:type").find_map(Resource::as_uri)
+    }
+
+    /// Uri values of the parsed `rdf:type` in the stored order. The raw data is not parsed here, as for `values`.
+    pub fn classes(&self) -> Vec<&str> {
+        self.values("rdf:type").filter_map(Resource::as_uri).collect()
+    }
+
+    /// Predicates having the uri of individual among their uri values, sorted. All predicates are parsed.
+    pub fn self_references(&mut self) -> Vec<String> {
+        self.parse_all();
+        let mut res: Vec<String> = vec![];
+        if self.obj.uri.is_empty() {
+            return res;
+        }
+        for (predicate, values) in self.obj.resources.iter() {
+            if values.iter().any(|el| el.rtype == DataType::Uri && el.get_uri() == self.obj.uri) {
+                res.push(predicate.to_owned());
+            }
+        }
+        res.sort();
+        res
+    }
+
+    /// The first value of every predicate as a flag: booleans as is, integers `0`/`1` and strings `true`/`false`
+    /// are coerced. Predicates absent or with the first value of another kind are omitted.
+    pub fn bool_flags(&mut self, predicates: &[&str]) -> HashMap<String, bool> {
+        let mut res = HashMap::new();
+        for predicate in predicates {
+            self.parse_predicate(predicate);
+            let flag = match self.values(predicate).next().map(|r| &r.value) {
+                Some(Value::Bool(b)) => Some(*b),
+                Some(Value::Int(0)) => Some(false),
+                Some(Value::Int(1)) => Some(true),
+                Some(Value::Str(s, _)) if s == "false" => Some(false),
+                Some(Value::Str(s, _)) if s == "true" => Some(true),
+                _ => None,
+            };
+            if let Some(b) = flag {
+                res.insert((*predicate).to_owned(), b);
+            }
+        }
+        res
+    }
+
     pub fn get_first_literal_or_err(&mut self, predicate: &str) -> Result<String, std::io::Error> {
         if let Some(v) = self.get_first_literal(predicate) {
             Ok(v)
@@ -470,11 +866,11 @@ impl Individual {
     pub fn get_first_literal(&mut self, predicate: &str) -> Option<String> {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
-                Some(v) => match &v[0].value {
-                    Value::Str(s, _l) => {
+                Some(v) => match v.first().map(|r| &r.value) {
+                    Some(Value::Str(s, _l)) => {
                         return Some(s.to_string());
                     }
-                    Value::Uri(s) => {
+                    Some(Value::Uri(s)) => {
                         return Some(s.to_string());
                     }
                     _ => {
@@ -496,6 +892,26 @@ impl Individual {
         None
     }
 
+    /// Members of the container in the parsed part: the values of the numbered predicates `<predicate>N`,
+    /// ordered by N, `rdf:_` for `rdf:Seq`, `rdf:Bag` and `rdf:Alt`. Predicates without a positive index are ignored.
+    pub fn get_container(&self, predicate: &str) -> Vec<Resource> {
+        let mut members: Vec<(u64, &Vec<Resource>)> =
+            self.obj.resources.iter().filter_map(|(p, values)| p.strip_prefix(predicate).and_then(container_index).map(|n| (n, values))).collect();
+        members.sort_by_key(|(n, _)| *n);
+        members.iter().flat_map(|(_, values)| values.iter().map(|el| el.get_copy())).collect()
+    }
+
+    /// The first literal found under the aliases of predicate, tried in the given order
+    pub fn get_first_literal_aliased(&mut self, aliases: &[&str]) -> Option<String> {
+        for predicate in aliases {
+            self.parse_predicate(predicate);
+            if let Some(Value::Str(s, _)) | Some(Value::Uri(s)) = self.values(predicate).next().map(|r| &r.value) {
+                return Some(s.to_owned());
+            }
+        }
+        None
+    }
+
     pub fn get_first_literal_with_lang(&mut self, predicate: &str, lang: &[Lang]) -> Option<String> {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
@@ -534,8 +950,8 @@ impl Individual {
     pub fn get_first_bool(&mut self, predicate: &str) -> Option<bool> {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
-                Some(v) => match &v[0].value {
-                    Value::Bool(s) => {
+                Some(v) => match v.first().map(|r| &r.value) {
+                    Some(Value::Bool(s)) => {
                         return Some(*s);
                     }
                     _ => {
@@ -560,8 +976,8 @@ impl Individual {
     pub fn get_first_binobj(&mut self, predicate: &str) -> Option<Vec<u8>> {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
-                Some(v) => match &v[0].value {
-                    Value::Binary(s) => {
+                Some(v) => match v.first().map(|r| &r.value) {
+                    Some(Value::Binary(s)) => {
                         return Some(s.clone());
                     }
                     _ => {
@@ -587,7 +1003,7 @@ impl Individual {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
                 Some(v) => {
-                    if let Value::Int(i) = &v[0].value {
+                    if let Some(Value::Int(i)) = v.first().map(|r| &r.value) {
                         return Some(*i);
                     }
                 }
@@ -610,7 +1026,7 @@ impl Individual {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
                 Some(v) => {
-                    if let Value::Num(m, e) = &v[0].value {
+                    if let Some(Value::Num(m, e)) = v.first().map(|r| &r.value) {
                         return Some((*m, *e));
                     }
                 }
@@ -633,7 +1049,7 @@ impl Individual {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
                 Some(v) => {
-                    if let Value::Datetime(i) = &v[0].value {
+                    if let Some(Value::Datetime(i)) = v.first().map(|r| &r.value) {
                         return Some(*i);
                     }
                 }
@@ -656,7 +1072,7 @@ impl Individual {
         for _ in 0..2 {
             match self.obj.resources.get(predicate) {
                 Some(v) => {
-                    return Some(v[0].get_float());
+                    return v.first().map(|r| r.get_float());
                 }
                 None => {
                     if self.raw.cur < self.raw.data.len() as u64 {
@@ -684,6 +1100,16 @@ impl Individual {
         self
     }
 
+    /// Parses the rest of the raw data, the first parse error is returned
+    pub fn try_parse_all(&mut self) -> Result<(), ParseError> {
+        while self.raw.cur < self.raw.data.len() as u64 {
+            if !try_parse_to_predicate("?", self)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
     pub fn apply_predicate_as_set(&mut self, predicate: &str, new_data: &mut Individual) {
         if let Some(v) = new_data.obj.resources.get(predicate) {
             self.obj.set_resources(predicate, v);
@@ -759,6 +1185,163 @@ impl Individual {
 
         true
     }
+
+    /// Human-readable dump of the parsed part: uri and then predicates sorted,
+    /// every value with its datatype and language
+    pub fn pretty(&self) -> String {
+        let mut predicates: Vec<&String> = self.obj.resources.keys().collect();
+        predicates.sort();
+        let width = predicates.iter().map(|p| p.chars().count()).max().unwrap_or(0);
+
+        let mut res = self.obj.uri.to_owned();
+        for predicate in predicates {
+            let values: Vec<String> = self.obj.resources[predicate]
+                .iter()
+                .map(|r| {
+                    let lang = r.get_lang();
+                    if lang == Lang::NONE {
+                        format!("{} ({:?})", r.value_as_str(), r.rtype)
+                    } else {
+                        format!("{} ({:?}@{})", r.value_as_str(), r.rtype, lang.to_string())
+                    }
+                })
+                .collect();
+            res.push_str(&format!("\n  {:width$} -> [{}]", predicate, values.join(", "), width = width));
+        }
+        res
+    }
+}
+
+/// Detects the format of the raw data and parses all predicates.
+/// The data is copied, the uri can be read without a copy by `peek_uri_borrowed`.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use v_common_onto::individual::Individual;
+/// use v_common_onto::individual2msgpack::to_msgpack;
+///
+/// let mut src = Individual::default();
+/// src.set_id("d:a");
+/// src.add_integer("v-s:n", 1);
+/// let mut raw = vec![];
+/// to_msgpack(&src, &mut raw).unwrap();
+///
+/// let mut indv = Individual::try_from(raw.as_slice()).unwrap();
+/// assert_eq!(indv.get_id(), "d:a");
+/// assert_eq!(indv.get_first_integer("v-s:n"), Some(1));
+/// ```
+impl TryFrom<&[u8]> for Individual {
+    type Error = ParseError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut indv = Individual::new_raw(RawObj::new(bytes.to_vec()));
+        parse_raw(&mut indv)?;
+        indv.try_parse_all()?;
+        Ok(indv)
+    }
+}
+
+/// Index of the container member predicate `_N`, a positive number without leading zeros
+pub(crate) fn container_index(n: &str) -> Option<u64> {
+    if n.starts_with('0') || !n.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    n.parse().ok()
+}
+
+/// The lowercase hex string of HMAC-SHA256 of the data with the key
+fn hmac_sha256_hex(key: &[u8], data: &[u8]) -> String {
+    // HMAC takes a key of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac: any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// FNV-1a hash, stable across runs
+fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in data {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Predicates of the parsed parts added in `b` and removed from `a`, both sorted.
+/// Predicates without values count as absent, the changes of values are ignored.
+pub fn predicate_diff(a: &Individual, b: &Individual) -> (Vec<String>, Vec<String>) {
+    let has = |indv: &Individual, predicate: &str| indv.obj.resources.get(predicate).is_some_and(|v| !v.is_empty());
+
+    let mut added: Vec<String> = b.obj.resources.keys().filter(|p| has(b, p) && !has(a, p)).cloned().collect();
+    let mut removed: Vec<String> = a.obj.resources.keys().filter(|p| has(a, p) && !has(b, p)).cloned().collect();
+    added.sort();
+    removed.sort();
+    (added, removed)
+}
+
+/// Values of the parsed parts removed from `old` and added in `new`, as individuals with the uri of `new`.
+/// Decimals are compared by their numeric value.
+pub fn value_diff(old: &Individual, new: &Individual) -> (Individual, Individual) {
+    let missing = |from: &Individual, other: &Individual| {
+        let mut res = Individual::default();
+        res.set_id(new.get_id());
+        for (predicate, values) in from.obj.resources.iter() {
+            for el in values.iter().filter(|el| !other.values(predicate).any(|v| v.is_same_value(el))) {
+                res.obj.add_resources(predicate, std::slice::from_ref(el));
+            }
+        }
+        res
+    };
+    (missing(old, new), missing(new, old))
+}
+
+/// Unix time of the string in one of the forms accepted by `add_datetime_from_str`:
+/// RFC 3339 in UTC, local `YYYY-MM-DDTHH:MM:SS`, `YYYY-MM-DD` or `DD.MM.YYYY`
+pub(crate) fn datetime_from_str(value: &str) -> Option<i64> {
+    if value.contains('Z') {
+        return DateTime::parse_from_rfc3339(&value).ok().map(|v| v.timestamp());
+    }
+
+    let ndt = if value.len() == 10 {
+        if value.contains('.') {
+            NaiveDateTime::parse_from_str(&(value.to_owned() + "T00:00:00"), "%d.%m.%YT%H:%M:%S")
+        } else {
+            NaiveDateTime::parse_from_str(&(value.to_owned() + "T00:00:00"), "%Y-%m-%dT%H:%M:%S")
+        }
+    } else {
+        NaiveDateTime::parse_from_str(&value, "%Y-%m-%dT%H:%M:%S")
+    };
+
+    let v = ndt.ok()?;
+    if let Single(offset) = Local.offset_from_local_datetime(&v) {
+        Some(v.sub(offset).timestamp())
+    } else {
+        Some(v.timestamp())
+    }
+}
+
+/// RFC 3987 absolute IRI: a scheme, `:` and a non-empty rest without whitespace and forbidden characters
+fn is_absolute_iri(s: &str) -> bool {
+    let (scheme, rest) = match s.find(':') {
+        Some(pos) => (&s[..pos], &s[pos + 1..]),
+        None => return false,
+    };
+    let mut scheme_chars = scheme.chars();
+    scheme_chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme_chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+        && !rest.is_empty()
+        && !rest.chars().any(|c| c.is_whitespace() || c.is_control() || "<>\"{}|\\^`".contains(c))
+}
+
+/// Groups the pairs predicate-value into an individual, the uri is left empty
+impl FromIterator<(String, Resource)> for Individual {
+    fn from_iter<I: IntoIterator<Item = (String, Resource)>>(iter: I) -> Self {
+        let mut indv = Individual::default();
+        for (predicate, r) in iter {
+            indv.obj.add_resources(&predicate, std::slice::from_ref(&r));
+        }
+        indv
+    }
 }
 
 impl fmt::Display for Individual {
@@ -795,19 +1378,28 @@ impl IndividualObj {
         self.resources.remove(predicate).is_some()
     }
 
+    /// The values of the predicate, the key is allocated only for a new predicate
+    pub(crate) fn values_entry(&mut self, predicate: &str) -> &mut Vec<Resource> {
+        if !self.resources.contains_key(predicate) {
+            self.resources.insert(predicate.to_owned(), Vec::new());
+        }
+        self.resources.get_mut(predicate).expect("the predicate is inserted")
+    }
+
     pub fn clear(&mut self, predicate: &str) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
     }
 
     pub fn add_unique_resources(&mut self, predicate: &str, b: &[Resource]) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
 
         for el in b.iter() {
             if !values.contains(el) {
                 values.push(Resource {
                     rtype: el.rtype.clone(),
                     order: el.order,
+                    source: el.source,
                     value: el.value.clone(),
                 });
             }
@@ -815,7 +1407,7 @@ impl IndividualObj {
     }
 
     pub fn remove_resources(&mut self, predicate: &str, b: &[Resource]) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
 
         for el in b.iter() {
             let mut idx = 0;
@@ -835,37 +1427,40 @@ impl IndividualObj {
     }
 
     pub fn set_resources(&mut self, predicate: &str, b: &[Resource]) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         for el in b.iter() {
             values.push(Resource {
                 rtype: el.rtype.clone(),
                 order: el.order,
+                source: el.source,
                 value: el.value.clone(),
             });
         }
     }
 
     pub fn add_resources(&mut self, predicate: &str, b: &[Resource]) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
 
         for el in b.iter() {
             values.push(Resource {
                 rtype: el.rtype.clone(),
                 order: values.len() as u16,
+                source: el.source,
                 value: el.value.clone(),
             });
         }
     }
 
     pub fn exclude_and_set_resources(&mut self, predicate: &str, exclude: &[Resource]) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         let mut new_values = vec![];
         for el in values.iter() {
             if !exclude.contains(el) {
                 new_values.push(Resource {
                     rtype: el.rtype.clone(),
                     order: el.order,
+                    source: el.source,
                     value: el.value.clone(),
                 });
             }
@@ -879,147 +1474,807 @@ impl IndividualObj {
     }
 
     pub fn add_bool(&mut self, predicate: &str, b: bool) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.push(Resource {
             rtype: DataType::Boolean,
             order: values.len() as u16,
+            source: 0,
             value: Value::Bool(b),
         });
     }
 
     pub fn set_bool(&mut self, predicate: &str, b: bool) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         values.push(Resource {
             rtype: DataType::Boolean,
             order: 0,
+            source: 0,
             value: Value::Bool(b),
         });
     }
 
     pub fn add_datetime(&mut self, predicate: &str, i: i64) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.push(Resource {
             rtype: DataType::Datetime,
             order: values.len() as u16,
+            source: 0,
             value: Value::Datetime(i),
         });
     }
 
     pub fn set_datetime(&mut self, predicate: &str, i: i64) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         values.push(Resource {
             rtype: DataType::Datetime,
             order: 0,
+            source: 0,
             value: Value::Datetime(i),
         });
     }
 
     pub fn add_binary(&mut self, predicate: &str, v: Vec<u8>) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.push(Resource {
             rtype: DataType::Binary,
             order: values.len() as u16,
+            source: 0,
             value: Value::Binary(v),
         });
     }
 
     pub fn set_binary(&mut self, predicate: &str, v: Vec<u8>) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         values.push(Resource {
             rtype: DataType::Binary,
             order: 0,
+            source: 0,
             value: Value::Binary(v),
         });
     }
 
     pub fn add_integer(&mut self, predicate: &str, i: i64) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.push(Resource {
             rtype: DataType::Integer,
             order: values.len() as u16,
+            source: 0,
             value: Value::Int(i),
         });
     }
 
     pub fn set_integer(&mut self, predicate: &str, i: i64) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         values.push(Resource {
             rtype: DataType::Integer,
             order: 0,
+            source: 0,
             value: Value::Int(i),
         });
     }
 
     pub fn add_decimal_d(&mut self, predicate: &str, mantissa: i64, exponent: i64) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.push(Resource {
             rtype: DataType::Decimal,
             order: values.len() as u16,
+            source: 0,
             value: Value::Num(mantissa, exponent),
         });
     }
 
     pub fn set_decimal_d(&mut self, predicate: &str, mantissa: i64, exponent: i64) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         values.push(Resource {
             rtype: DataType::Decimal,
             order: 0,
+            source: 0,
             value: Value::Num(mantissa, exponent),
         });
     }
 
     pub fn add_uri(&mut self, predicate: &str, s: &str) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.push(Resource {
             rtype: DataType::Uri,
             order: values.len() as u16,
+            source: 0,
             value: Value::Uri(s.to_owned()),
         });
     }
 
     pub fn set_uri(&mut self, predicate: &str, s: &str) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         values.push(Resource {
             rtype: DataType::Uri,
             order: 0,
+            source: 0,
             value: Value::Uri(s.to_owned()),
         });
     }
 
     pub fn set_uris(&mut self, predicate: &str, ss: Vec<String>) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         for s in ss {
             values.push(Resource {
                 rtype: DataType::Uri,
                 order: 0,
+                source: 0,
                 value: Value::Uri(s.to_owned()),
             });
         }
     }
 
     pub fn add_string(&mut self, predicate: &str, s: &str, lang: Lang) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.push(Resource {
             rtype: DataType::String,
             order: values.len() as u16,
+            source: 0,
             value: Value::Str(s.to_owned(), lang),
         });
     }
 
     pub fn set_string(&mut self, predicate: &str, s: &str, lang: Lang) {
-        let values = self.resources.entry(predicate.to_owned()).or_default();
+        let values = self.values_entry(predicate);
         values.clear();
         values.push(Resource {
             rtype: DataType::String,
             order: 0,
+            source: 0,
             value: Value::Str(s.to_owned(), lang),
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keep_both_with_decimal_out_of_decimal_range() {
+        let mut a = Individual::default();
+        a.set_id("d:a");
+        a.add_decimal_d("v-s:amount", 1, -29);
+        let mut b = Individual::default();
+        b.set_id("d:a");
+        b.add_decimal_d("v-s:amount", 10, -30);
+        b.add_decimal_d("v-s:amount", 1, 20);
+
+        let policy = MergePolicy {
+            values: ValuePolicy::KeepBoth,
+            uri: UriPolicy::Reject,
+        };
+        assert!(a.merge(&b, policy));
+        let nums: Vec<(i64, i64)> = a.values("v-s:amount").map(|r| r.get_num()).collect();
+        assert_eq!(nums, vec![(1, -29), (1, 20)]);
+    }
+
+    #[test]
+    fn value_diff_with_decimal_out_of_decimal_range() {
+        let mut old = Individual::default();
+        old.set_id("d:a");
+        old.add_decimal_d("v-s:amount", 1, -29);
+        old.add_decimal_d("v-s:amount", 5, 0);
+        let mut new = Individual::default();
+        new.set_id("d:a");
+        new.add_decimal_d("v-s:amount", 10, -30);
+        new.add_decimal_d("v-s:amount", 1, 20);
+
+        let (removed, added) = value_diff(&old, &new);
+        let nums = |indv: &Individual| indv.values("v-s:amount").map(|r| r.get_num()).collect::<Vec<(i64, i64)>>();
+        assert_eq!(nums(&removed), vec![(5, 0)]);
+        assert_eq!(nums(&added), vec![(1, 20)]);
+    }
+
+    #[test]
+    fn semantic_hash_of_decimal_out_of_decimal_range() {
+        let hash = |m: i64, e: i64| {
+            let mut indv = Individual::default();
+            indv.set_id("d:a");
+            indv.add_decimal_d("v-s:amount", m, e);
+            indv.semantic_hash()
+        };
+        assert_eq!(hash(1, -29), hash(100, -31));
+        assert_eq!(hash(1, 20), hash(1000, 17));
+        assert_ne!(hash(1, -29), hash(1, -28));
+
+        let mut int = Individual::default();
+        int.set_id("d:a");
+        int.add_integer("v-s:amount", 70);
+        assert_eq!(int.semantic_hash(), hash(700, -1));
+    }
+
+    #[test]
+    fn accessors_parse_the_raw_data() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_uri("rdf:type", "v-s:Document");
+        src.add_uri("v-s:parent", "d:a");
+        src.add_string("rdfs:label", "документ", Lang::RU);
+        src.add_string("v-s:title", "title", Lang::NONE);
+        src.add_bool("v-s:deleted", true);
+        let mut data = vec![];
+        crate::individual2msgpack::to_msgpack(&src, &mut data).unwrap();
+        let raw = || {
+            let mut indv = Individual::new_raw(RawObj::new(data.clone()));
+            parse_raw(&mut indv).unwrap();
+            indv
+        };
+
+        assert_eq!(raw().get_first_literal_aliased(&["v-s:name", "v-s:title"]), Some("title".to_owned()));
+        assert_eq!(raw().first_is_uri("v-s:parent"), Some(true));
+        assert_eq!(raw().self_references(), vec!["v-s:parent".to_owned()]);
+        assert_eq!(raw().bool_flags(&["v-s:deleted"]).get("v-s:deleted"), Some(&true));
+        let missing = raw().lang_coverage(&["rdfs:label"], &["ru", "en"]);
+        assert_eq!(missing.get("rdfs:label"), Some(&vec!["en".to_owned()]));
+
+        let mut indv = raw();
+        assert_eq!(indv.class(), None);
+        indv.parse_all();
+        assert_eq!(indv.class(), Some("v-s:Document"));
+    }
+
+    #[test]
+    fn class_of_single_multi_and_no_type() {
+        let mut single = Individual::default();
+        single.set_id("d:a");
+        single.add_uri("rdf:type", "v-s:Document");
+        assert_eq!(single.class(), Some("v-s:Document"));
+        assert_eq!(single.classes(), vec!["v-s:Document"]);
+
+        let mut multi = Individual::default();
+        multi.set_id("d:b");
+        multi.add_string("rdf:type", "not a class", Lang::NONE);
+        multi.add_uri("rdf:type", "v-s:Person");
+        multi.add_uri("rdf:type", "v-s:Employee");
+        assert_eq!(multi.class(), Some("v-s:Person"));
+        assert_eq!(multi.classes(), vec!["v-s:Person", "v-s:Employee"]);
+
+        let mut untyped = Individual::default();
+        untyped.set_id("d:c");
+        untyped.add_integer("v-s:count", 1);
+        assert_eq!(untyped.class(), None);
+        assert!(untyped.classes().is_empty());
+    }
+
+    #[test]
+    fn first_is_uri_of_uri_and_string() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_uri("v-s:parent", "d:b");
+        indv.add_string("v-s:parent", "d:c", Lang::NONE);
+        indv.add_string("rdfs:label", "d:b", Lang::NONE);
+        indv.add_uri("rdfs:label", "d:c");
+        assert_eq!(indv.first_is_uri("v-s:parent"), Some(true));
+        assert_eq!(indv.first_is_uri("rdfs:label"), Some(false));
+        assert_eq!(indv.first_is_uri("v-s:absent"), None);
+    }
+
+    #[test]
+    fn pretty_shows_datatypes_and_languages() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_uri("rdf:type", "v-s:Document");
+        indv.add_string("rdfs:label", "документ", Lang::RU);
+        indv.add_integer("v-s:count", 2);
+        indv.add_integer("v-s:count", 3);
+        assert_eq!(
+            indv.pretty(),
+            "d:a\n  rdf:type   -> [v-s:Document (Uri)]\n  rdfs:label -> [документ (String@ru)]\n  v-s:count  -> [2 (Integer), 3 (Integer)]"
+        );
+    }
+
+    #[test]
+    fn try_from_msgpack_and_cbor() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_uri("rdf:type", "v-s:Document");
+        src.add_integer("v-s:count", 7);
+
+        let mut msgpack = vec![];
+        crate::individual2msgpack::to_msgpack(&src, &mut msgpack).unwrap();
+        let mut cbor = vec![];
+        crate::individual2cbor::to_cbor(&src, &mut cbor).unwrap();
+        for raw in [msgpack, cbor].iter() {
+            let mut indv = Individual::try_from(raw.as_slice()).unwrap();
+            assert_eq!(indv.get_id(), "d:a");
+            assert_eq!(indv.get_first_integer("v-s:count"), Some(7));
+            assert_eq!(indv.get_obj().get_resources(), src.get_obj().get_resources());
+        }
+
+        assert!(matches!(Individual::try_from(&[][..]), Err(ParseError::Empty)));
+        assert!(Individual::try_from(&[0x92, 0xa3, b'd'][..]).is_err());
+    }
+
+    #[test]
+    fn lang_coverage_reports_missing_languages() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_string("rdfs:label", "документ", Lang::RU);
+        indv.add_string("rdfs:label", "document", Lang::EN);
+        indv.add_string("rdfs:comment", "комментарий", Lang::RU);
+        indv.add_string("rdfs:comment", "no language", Lang::NONE);
+
+        let missing = indv.lang_coverage(&["rdfs:label", "rdfs:comment", "v-s:absent"], &["RU", " en "]);
+        assert_eq!(missing.len(), 2);
+        assert_eq!(missing["rdfs:comment"], vec!["en".to_owned()]);
+        assert_eq!(missing["v-s:absent"], vec!["ru".to_owned(), "en".to_owned()]);
+
+        let missing = indv.lang_coverage(&["rdfs:label"], &["ru", "en", "de"]);
+        assert_eq!(missing["rdfs:label"], vec!["de".to_owned()]);
+    }
+
+    #[test]
+    fn promote_uris_of_absolute_iris_only() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_string("v-s:link", "https://example.org/a?b=c", Lang::NONE);
+        indv.add_string("v-s:link", "not an iri", Lang::NONE);
+        indv.add_string("v-s:link", "1:2", Lang::NONE);
+        indv.add_string("v-s:link", "ftp:", Lang::NONE);
+        indv.add_string("rdfs:comment", "https://example.org/kept", Lang::NONE);
+        indv.promote_uris(&["v-s:link"]);
+
+        let link: Vec<(DataType, String)> = indv.values("v-s:link").map(|r| (r.rtype.clone(), r.value_as_str())).collect();
+        assert_eq!(
+            link,
+            vec![
+                (DataType::Uri, "https://example.org/a?b=c".to_owned()),
+                (DataType::String, "not an iri".to_owned()),
+                (DataType::String, "1:2".to_owned()),
+                (DataType::String, "ftp:".to_owned()),
+            ]
+        );
+        assert_eq!(indv.first_is_uri("rdfs:comment"), Some(false));
+    }
+
+    #[test]
+    fn values_in_stored_order() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_integer("v-s:mixed", 1);
+        indv.add_string("v-s:mixed", "two", Lang::NONE);
+        indv.add_integer("v-s:mixed", 3);
+        indv.add_uri("v-s:mixed", "d:four");
+
+        let mut counts: HashMap<DataType, usize> = HashMap::new();
+        for r in indv.values("v-s:mixed") {
+            *counts.entry(r.rtype.clone()).or_default() += 1;
+        }
+        assert_eq!(counts[&DataType::Integer], 2);
+        assert_eq!(counts[&DataType::String], 1);
+        assert_eq!(counts[&DataType::Uri], 1);
+        let all: Vec<String> = indv.values("v-s:mixed").map(|r| r.value_as_str()).collect();
+        assert_eq!(all, vec!["1", "two", "3", "d:four"]);
+        assert_eq!(indv.values("v-s:absent").count(), 0);
+    }
+
+    #[test]
+    fn values_page_of_mid_range_and_past_the_end() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        for i in 0..10 {
+            indv.add_integer("v-s:count", i);
+        }
+        assert_eq!(indv.value_count("v-s:count"), 10);
+        assert_eq!(indv.value_count("v-s:absent"), 0);
+
+        let page: Vec<i64> = indv.values_page("v-s:count", 4, 3).iter().map(|r| r.get_int()).collect();
+        assert_eq!(page, vec![4, 5, 6]);
+        let tail: Vec<i64> = indv.values_page("v-s:count", 8, 5).iter().map(|r| r.get_int()).collect();
+        assert_eq!(tail, vec![8, 9]);
+        assert!(indv.values_page("v-s:count", 10, 5).is_empty());
+        assert!(indv.values_page("v-s:count", usize::MAX, 5).is_empty());
+        assert!(indv.values_page("v-s:absent", 0, 5).is_empty());
+    }
+
+    #[test]
+    fn datatype_conflicts_of_mixed_values() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_integer("v-s:count", 1);
+        indv.add_string("v-s:count", "two", Lang::NONE);
+        indv.add_string("v-s:count", "three", Lang::NONE);
+        indv.add_integer("v-s:count", 4);
+        indv.add_uri("v-s:parent", "d:b");
+        indv.add_string("v-s:free", "any", Lang::NONE);
+
+        let mut expected = HashMap::new();
+        expected.insert("v-s:count".to_owned(), DataType::Integer);
+        expected.insert("v-s:parent".to_owned(), DataType::Uri);
+        assert_eq!(indv.datatype_conflicts(&expected), vec![("v-s:count".to_owned(), DataType::String)]);
+    }
+
+    #[test]
+    fn flatten_repeats_the_predicate_per_value() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_integer("v-s:count", 1);
+        indv.add_integer("v-s:count", 2);
+        indv.add_uri("rdf:type", "v-s:Document");
+
+        let flat: Vec<(String, String)> = indv.flatten().iter().map(|(p, r)| (p.to_owned(), r.value_as_str())).collect();
+        assert_eq!(
+            flat,
+            vec![("rdf:type".to_owned(), "v-s:Document".to_owned()), ("v-s:count".to_owned(), "1".to_owned()), ("v-s:count".to_owned(), "2".to_owned())]
+        );
+    }
+
+    #[test]
+    fn from_iterator_groups_repeated_predicates() {
+        let pairs = vec![
+            ("v-s:parent".to_owned(), Resource::new_uri("d:p2")),
+            ("v-s:flag".to_owned(), Resource::new_bool(true)),
+            ("v-s:parent".to_owned(), Resource::new_uri("d:p1")),
+        ];
+        let mut indv: Individual = pairs.into_iter().collect();
+        assert_eq!(indv.get_id(), "");
+        assert_eq!(indv.get_obj().get_resources().len(), 2);
+        assert_eq!(indv.get_literals("v-s:parent"), Some(vec!["d:p2".to_owned(), "d:p1".to_owned()]));
+        assert_eq!(indv.get_first_bool("v-s:flag"), Some(true));
+
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_integer("v-s:count", 1);
+        src.add_integer("v-s:count", 2);
+        let mut copy: Individual = src.flatten().into_iter().collect();
+        copy.set_id("d:a");
+        assert_eq!(copy.get_obj().get_resources(), src.get_obj().get_resources());
+    }
+
+    #[test]
+    fn self_references_of_uri_values() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_uri("v-s:parent", "d:b");
+        indv.add_uri("v-s:sameAs", "d:c");
+        indv.add_uri("v-s:sameAs", "d:a");
+        indv.add_uri("v-s:backwardTarget", "d:a");
+        indv.add_string("rdfs:label", "d:a", Lang::NONE);
+        assert_eq!(indv.self_references(), vec!["v-s:backwardTarget".to_owned(), "v-s:sameAs".to_owned()]);
+
+        let mut other = Individual::default();
+        other.set_id("d:b");
+        other.add_uri("v-s:parent", "d:a");
+        assert!(other.self_references().is_empty());
+    }
+
+    #[test]
+    fn bool_flags_with_coercion() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_bool("v-s:deleted", true);
+        indv.add_integer("v-s:isDraft", 0);
+        indv.add_integer("v-s:isPublic", 1);
+        indv.add_string("v-s:isLocked", "false", Lang::NONE);
+        indv.add_integer("v-s:count", 2);
+        indv.add_string("v-s:name", "yes", Lang::NONE);
+
+        let flags = indv.bool_flags(&["v-s:deleted", "v-s:isDraft", "v-s:isPublic", "v-s:isLocked", "v-s:count", "v-s:name", "v-s:absent"]);
+        let expected: HashMap<String, bool> =
+            [("v-s:deleted", true), ("v-s:isDraft", false), ("v-s:isPublic", true), ("v-s:isLocked", false)].iter().map(|(p, b)| ((*p).to_owned(), *b)).collect();
+        assert_eq!(flags, expected);
+    }
+
+    #[test]
+    fn predicate_diff_of_added_and_removed() {
+        let mut a = Individual::default();
+        a.set_id("d:a");
+        a.add_uri("rdf:type", "v-s:Document");
+        a.add_integer("v-s:count", 1);
+        a.add_string("v-s:oldName", "a", Lang::NONE);
+        a.obj.resources.insert("v-s:empty".to_owned(), vec![]);
+
+        let mut b = Individual::default();
+        b.set_id("d:a");
+        b.add_uri("rdf:type", "v-s:Document");
+        b.add_integer("v-s:count", 2);
+        b.add_string("v-s:newName", "a", Lang::NONE);
+
+        assert_eq!(predicate_diff(&a, &b), (vec!["v-s:newName".to_owned()], vec!["v-s:oldName".to_owned()]));
+        assert_eq!(predicate_diff(&b, &a), (vec!["v-s:oldName".to_owned()], vec!["v-s:newName".to_owned()]));
+        assert_eq!(predicate_diff(&a, &a), (vec![], vec![]));
+    }
+
+    #[test]
+    fn retain_drops_predicates_without_values() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_integer("v-s:count", 0);
+        indv.add_string("v-s:name", "a", Lang::NONE);
+        indv.add_string("v-s:comment", "", Lang::NONE);
+        indv.obj.resources.insert("v-s:none".to_owned(), vec![]);
+
+        indv.retain(|_, values| values.iter().any(|r| !r.value_as_str().is_empty()));
+        let mut predicates: Vec<&String> = indv.get_obj().get_resources().keys().collect();
+        predicates.sort();
+        assert_eq!(predicates, vec!["v-s:count", "v-s:name"]);
+        assert_eq!(indv.get_id(), "d:a");
+    }
+
+    #[test]
+    fn skolemize_maps_one_blank_id_to_one_iri() {
+        let blank = || {
+            let mut indv = Individual::default();
+            indv.set_id("_:b0");
+            indv.add_uri("v-s:sameAs", "_:b0");
+            indv.add_uri("v-s:part", "_:b1");
+            indv.add_uri("v-s:parent", "d:p");
+            indv.add_string("rdfs:label", "_:b0", Lang::NONE);
+            indv
+        };
+
+        let mut indv = blank();
+        indv.skolemize("http://example.org/");
+        let subject = indv.get_id().to_owned();
+        assert!(subject.starts_with("http://example.org/.well-known/genid/"), "{}", subject);
+        assert!(!indv.subject_is_blank());
+        assert_eq!(indv.get_first_literal("v-s:sameAs"), Some(subject.clone()));
+        let part = indv.get_first_literal("v-s:part").unwrap();
+        assert!(part.starts_with("http://example.org/.well-known/genid/") && part != subject, "{}", part);
+        assert_eq!(indv.get_first_literal("v-s:parent"), Some("d:p".to_owned()));
+        assert_eq!(indv.get_first_literal("rdfs:label"), Some("_:b0".to_owned()));
+
+        // stable across runs and individuals
+        let mut again = blank();
+        again.skolemize("http://example.org");
+        assert_eq!(again.get_id(), subject);
+        assert_eq!(again.get_first_literal("v-s:part"), Some(part));
+    }
+
+    #[test]
+    fn container_members_in_index_order() {
+        let mut indv = Individual::default();
+        indv.set_id("d:seq");
+        indv.add_uri("rdf:type", "rdf:Seq");
+        indv.add_string("rdf:_10", "third", Lang::NONE);
+        indv.add_string("rdf:_2", "second", Lang::NONE);
+        indv.add_string("rdf:_1", "first", Lang::NONE);
+        indv.add_string("rdf:_0", "no index", Lang::NONE);
+        indv.add_string("rdf:_x", "no index", Lang::NONE);
+
+        let members: Vec<String> = indv.get_container("rdf:_").iter().map(|r| r.value_as_str()).collect();
+        assert_eq!(members, vec!["first", "second", "third"]);
+        assert!(indv.get_container("v-s:_").is_empty());
+    }
+
+    #[test]
+    fn normalize_whitespace_of_strings() {
+        let fill = || {
+            let mut indv = Individual::default();
+            indv.set_id("d:a");
+            indv.add_string("rdfs:label", "\tmain  title \r\n second\u{a0}line  ", Lang::EN);
+            indv.add_string("v-s:comment", " keep  me ", Lang::NONE);
+            indv.add_uri("v-s:parent", "d:p");
+            indv.add_integer("v-s:count", 1);
+            indv
+        };
+
+        let mut indv = fill();
+        indv.normalize_whitespace(None);
+        assert_eq!(indv.get_first_literal_with_lang("rdfs:label", &[Lang::EN]), Some("main title second line".to_owned()));
+        assert_eq!(indv.get_first_literal("v-s:comment"), Some("keep me".to_owned()));
+        assert_eq!(indv.get_first_literal("v-s:parent"), Some("d:p".to_owned()));
+        assert_eq!(indv.get_first_integer("v-s:count"), Some(1));
+
+        let mut indv = fill();
+        indv.normalize_whitespace(Some(&["rdfs:label"]));
+        assert_eq!(indv.get_first_literal("rdfs:label"), Some("main title second line".to_owned()));
+        assert_eq!(indv.get_first_literal("v-s:comment"), Some(" keep  me ".to_owned()));
+    }
+
+    #[test]
+    fn from_pairs_typed_of_valid_set_and_invalid_datetime() {
+        let mut indv = Individual::from_pairs_typed(
+            "d:a",
+            &[
+                ("rdf:type", "v-s:Document", "uri"),
+                ("v-s:created", "2023-01-01T00:00:00Z", "datetime"),
+                ("v-s:count", "42", "integer"),
+                ("v-s:amount", "1.50", "decimal"),
+                ("v-s:deleted", "false", "boolean"),
+                ("rdfs:label", "title", "string"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(indv.get_id(), "d:a");
+        assert_eq!(indv.get_first_literal("rdf:type"), Some("v-s:Document".to_owned()));
+        assert_eq!(indv.get_first_datetime("v-s:created"), Some(1_672_531_200));
+        assert_eq!(indv.get_first_integer("v-s:count"), Some(42));
+        assert_eq!(indv.get_first_bool("v-s:deleted"), Some(false));
+        assert_eq!(indv.get_first_literal("rdfs:label"), Some("title".to_owned()));
+        assert_eq!(indv.get_first_number("v-s:amount"), Some((150, -2)));
+
+        match Individual::from_pairs_typed("d:a", &[("v-s:created", "2023-13-45T99:00:00Z", "datetime")]) {
+            Err(LexicalError::InvalidValue {
+                predicate,
+                value,
+                datatype,
+            }) => {
+                assert_eq!(predicate, "v-s:created");
+                assert_eq!(value, "2023-13-45T99:00:00Z");
+                assert_eq!(datatype, DataType::Datetime);
+            }
+            other => panic!("expected an invalid value, got {:?}", other.map(|i| i.get_id().to_owned())),
+        }
+        match Individual::from_pairs_typed("d:a", &[("v-s:created", "2023", "timestamp")]) {
+            Err(LexicalError::UnknownDatatype(name)) => assert_eq!(name, "timestamp"),
+            other => panic!("expected an unknown datatype, got {:?}", other.map(|i| i.get_id().to_owned())),
+        }
+    }
+
+    #[test]
+    fn redact_of_string_predicate_by_every_mode() {
+        let fill = || {
+            let mut indv = Individual::default();
+            indv.set_id("d:a");
+            indv.add_string("v-s:email", "a@example.org", Lang::EN);
+            indv.add_string("v-s:email", "b@example.org", Lang::NONE);
+            indv.add_string("rdfs:label", "keep", Lang::NONE);
+            indv
+        };
+        let emails = |indv: &Individual| -> Vec<(String, Lang)> {
+            indv.values("v-s:email").map(|r| (r.get_str().to_owned(), r.get_lang())).collect()
+        };
+
+        let mut indv = fill();
+        indv.redact(&["v-s:email", "v-s:absent"], RedactMode::Remove);
+        assert!(indv.get_resources("v-s:email").is_none());
+        assert_eq!(indv.get_first_literal("rdfs:label"), Some("keep".to_owned()));
+
+        let mut indv = fill();
+        indv.redact(&["v-s:email"], RedactMode::Blank);
+        assert_eq!(emails(&indv), vec![(String::new(), Lang::EN), (String::new(), Lang::NONE)]);
+        assert_eq!(indv.get_first_literal("rdfs:label"), Some("keep".to_owned()));
+
+        let mut indv = fill();
+        indv.redact(&["v-s:email"], RedactMode::Hash("salt".to_owned()));
+        let hashed = emails(&indv);
+        assert_eq!(hashed.len(), 2);
+        for (s, _) in &hashed {
+            assert_eq!(s.len(), 64);
+            assert!(s.chars().all(|c| c.is_ascii_hexdigit()));
+            assert!(!s.contains("example.org"));
+        }
+        assert_ne!(hashed[0].0, hashed[1].0);
+        // the same salt gives the same hash, another salt a different one
+        let mut same = fill();
+        same.redact(&["v-s:email"], RedactMode::Hash("salt".to_owned()));
+        assert_eq!(emails(&same), hashed);
+        let mut other = fill();
+        other.redact(&["v-s:email"], RedactMode::Hash("pepper".to_owned()));
+        assert_ne!(emails(&other)[0].0, hashed[0].0);
+        assert_eq!(indv.get_first_literal("rdfs:label"), Some("keep".to_owned()));
+
+        // HMAC-SHA256 with the salt as the key
+        let mut indv = Individual::default();
+        indv.add_string("v-s:text", "The quick brown fox jumps over the lazy dog", Lang::NONE);
+        indv.redact(&["v-s:text"], RedactMode::Hash("key".to_owned()));
+        assert_eq!(indv.get_first_literal("v-s:text"), Some("f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8".to_owned()));
+    }
+
+    #[test]
+    fn with_capacity_does_not_change_behavior() {
+        let fill = |indv: &mut Individual| {
+            indv.set_id("d:a");
+            for n in 0..20 {
+                indv.add_integer(&format!("v-s:p{}", n), n);
+            }
+        };
+        let mut sized = Individual::with_capacity(20);
+        assert!(sized.get_obj().get_resources().capacity() >= 20);
+        fill(&mut sized);
+        let mut plain = Individual::default();
+        fill(&mut plain);
+        assert_eq!(sized.get_obj().get_resources(), plain.get_obj().get_resources());
+
+        let mut data = vec![];
+        crate::individual2msgpack::to_msgpack(&plain, &mut data).unwrap();
+        let mut parsed = Individual::new_raw(RawObj::new(data));
+        parse_raw(&mut parsed).unwrap();
+        assert!(parsed.get_obj().get_resources().capacity() >= 20);
+        parsed.parse_all();
+        assert_eq!(parsed.get_obj().get_resources(), plain.get_obj().get_resources());
+    }
+
+    fn counts(indv: &Individual) -> Vec<i64> {
+        indv.values("v-s:count").map(|r| r.get_int()).collect()
+    }
+
+    #[test]
+    fn merge_by_every_value_policy() {
+        let mut other = Individual::default();
+        other.set_id("d:a");
+        other.add_integer("v-s:count", 2);
+        other.add_integer("v-s:count", 3);
+        other.add_uri("v-s:parent", "d:p");
+
+        let merged = |values: ValuePolicy| {
+            let mut indv = Individual::default();
+            indv.set_id("d:a");
+            indv.add_integer("v-s:count", 1);
+            indv.add_integer("v-s:count", 2);
+            assert!(indv.merge(
+                &other,
+                MergePolicy {
+                    values,
+                    uri: UriPolicy::Reject,
+                }
+            ));
+            // a predicate absent in self is taken from other by every policy
+            assert_eq!(indv.first_is_uri("v-s:parent"), Some(true));
+            indv
+        };
+        assert_eq!(counts(&merged(ValuePolicy::Append)), vec![1, 2, 2, 3]);
+        assert_eq!(counts(&merged(ValuePolicy::KeepBoth)), vec![1, 2, 3]);
+        assert_eq!(counts(&merged(ValuePolicy::PreferSelf)), vec![1, 2]);
+        assert_eq!(counts(&merged(ValuePolicy::PreferOther)), vec![2, 3]);
+    }
+
+    #[test]
+    fn merged_values_carry_the_source() {
+        let from = |count: i64| {
+            let mut indv = Individual::default();
+            indv.set_id("d:a");
+            indv.add_integer("v-s:count", count);
+            indv
+        };
+        let sources = |indv: &Individual, predicate: &str| indv.values(predicate).map(|r| r.source).collect::<Vec<u16>>();
+
+        let mut indv = from(1);
+        let mut other = from(2);
+        other.add_string("v-s:name", "b", Lang::NONE);
+        assert!(indv.merge_with_source(&other, MergePolicy::default(), 7));
+        assert!(indv.merge_with_source(&from(3), MergePolicy::default(), 9));
+        assert_eq!(counts(&indv), vec![1, 2, 3]);
+        assert_eq!(sources(&indv, "v-s:count"), vec![0, 7, 9]);
+        assert_eq!(sources(&indv, "v-s:name"), vec![7]);
+        assert_eq!(indv.values("v-s:name").map(|r| r.get_copy().source).collect::<Vec<u16>>(), vec![7]);
+
+        // the source is not serialized and is ignored by the comparison
+        let mut raw = vec![];
+        crate::individual2msgpack::to_msgpack(&indv, &mut raw).unwrap();
+        let mut parsed = Individual::try_from(raw.as_slice()).unwrap();
+        assert_eq!(sources(&parsed, "v-s:count"), vec![0, 0, 0]);
+        assert_eq!(parsed.get_obj().get_resources(), indv.get_obj().get_resources());
+        assert_eq!(parsed.get_first_literal("v-s:name"), Some("b".to_owned()));
+    }
+
+    #[test]
+    fn merge_by_every_uri_policy() {
+        let mut other = Individual::default();
+        other.set_id("d:b");
+        other.add_integer("v-s:count", 2);
+
+        let merged = |uri: UriPolicy| {
+            let mut indv = Individual::default();
+            indv.set_id("d:a");
+            indv.add_integer("v-s:count", 1);
+            let res = indv.merge(
+                &other,
+                MergePolicy {
+                    values: ValuePolicy::Append,
+                    uri,
+                },
+            );
+            (res, indv)
+        };
+        let (res, indv) = merged(UriPolicy::Reject);
+        assert!(!res);
+        assert_eq!((indv.get_id(), counts(&indv)), ("d:a", vec![1]));
+        let (res, indv) = merged(UriPolicy::KeepTarget);
+        assert!(res);
+        assert_eq!((indv.get_id(), counts(&indv)), ("d:a", vec![1, 2]));
+        let (res, indv) = merged(UriPolicy::TakeSource);
+        assert!(res);
+        assert_eq!((indv.get_id(), counts(&indv)), ("d:b", vec![1, 2]));
+    }
+}
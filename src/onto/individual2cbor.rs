@@ -0,0 +1,104 @@
+use crate::cbor2individual::TagId;
+use crate::datatype::*;
+use crate::individual::*;
+use crate::resource::*;
+use std::io::{Error, ErrorKind};
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const CBOR_FALSE: u8 = 0xf4;
+const CBOR_TRUE: u8 = 0xf5;
+
+/// The initial byte and the argument, the multi-byte arguments are little-endian as `cbor2individual` reads them
+fn write_head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8);
+    } else if n <= 0xff {
+        out.push(major | 24);
+        out.push(n as u8);
+    } else if n <= 0xffff {
+        out.push(major | 25);
+        out.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        out.push(major | 26);
+        out.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+/// The decoder reads a negative number less by one, so it is written as `-x` instead of `-1 - x`
+fn write_int(out: &mut Vec<u8>, i: i64) -> Result<(), Error> {
+    if i == i64::MIN {
+        return Err(Error::new(ErrorKind::InvalidInput, "cbor: i64::MIN can not be written"));
+    }
+    if i < 0 {
+        write_head(out, MAJOR_NEGATIVE, i.unsigned_abs());
+    } else {
+        write_head(out, MAJOR_UNSIGNED, i as u64);
+    }
+    Ok(())
+}
+
+fn write_text(out: &mut Vec<u8>, s: &str) {
+    write_head(out, MAJOR_TEXT, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_resource(out: &mut Vec<u8>, predicate: &str, r: &Resource) -> Result<(), Error> {
+    match &r.value {
+        Value::Int(i) => write_int(out, *i)?,
+        Value::Datetime(i) => {
+            write_head(out, MAJOR_TAG, TagId::EpochDateTime as u64);
+            write_int(out, *i)?;
+        }
+        Value::Num(m, e) => {
+            write_head(out, MAJOR_TAG, TagId::DecimalFraction as u64);
+            write_head(out, MAJOR_ARRAY, 2);
+            write_int(out, *m)?;
+            write_int(out, *e)?;
+        }
+        Value::Bool(b) => out.push(if *b {
+            CBOR_TRUE
+        } else {
+            CBOR_FALSE
+        }),
+        Value::Uri(s) => {
+            write_head(out, MAJOR_TAG, TagId::Uri as u64);
+            write_text(out, s);
+        }
+        Value::Str(s, l) => {
+            match l {
+                Lang::RU => write_head(out, MAJOR_TAG, TagId::TextRu as u64),
+                Lang::EN => write_head(out, MAJOR_TAG, TagId::TextEn as u64),
+                Lang::NONE => {}
+            }
+            write_text(out, s);
+        }
+        // the bytes are read back as a string
+        Value::Binary(_) => return Err(Error::new(ErrorKind::InvalidInput, format!("cbor: binary value of [{}] can not be written", predicate))),
+    }
+    Ok(())
+}
+
+/// Writes the individual as a cbor map: the uri under the key `@`, the values of every predicate as an array.
+/// The format has no binary values, an individual with them is an error.
+pub fn to_cbor(indv: &Individual, out: &mut Vec<u8>) -> Result<(), Error> {
+    write_head(out, MAJOR_MAP, indv.obj.resources.len() as u64 + 1);
+    write_text(out, "@");
+    write_text(out, &indv.obj.uri);
+    for (predicate, resources) in &indv.obj.resources {
+        write_text(out, predicate);
+        write_head(out, MAJOR_ARRAY, resources.len() as u64);
+        for r in resources {
+            write_resource(out, predicate, r)?;
+        }
+    }
+    Ok(())
+}
@@ -1,9 +1,7 @@
-use crate::datatype::{exponent_to_scale, DataType, Lang};
+use crate::datatype::{datetime_secs_to_xsd, format_decimal, DataType, Lang};
 use crate::individual::*;
 use crate::resource::{Resource, Value};
-use chrono::{TimeZone, Utc};
-use rust_decimal::Decimal;
-use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+use serde::ser::{Error, Serialize, SerializeMap, SerializeStruct, Serializer};
 use serde_json::json;
 use serde_json::value::Value as JSONValue;
 
@@ -53,10 +51,10 @@ impl Serialize for Resource {
             Value::Int(i) => {
                 tup.serialize_field("data", &*i)?;
             }
-            Value::Datetime(i) => {
-                let dt = *i;
-                tup.serialize_field("data", &format!("{:?}", &Utc.timestamp(dt, 0)))?;
-            }
+            Value::Datetime(i) => match datetime_secs_to_xsd(*i) {
+                Some(dt) => tup.serialize_field("data", &dt)?,
+                None => return Err(S::Error::custom(format!("datetime {} is out of range", i))),
+            },
             Value::Bool(b) => {
                 tup.serialize_field("data", &*b)?;
             }
@@ -84,11 +82,7 @@ impl Serialize for Value {
         S: Serializer,
     {
         match &self {
-            Value::Num(m, e) => {
-                let (num, scale) = exponent_to_scale(m, e);
-                let d = Decimal::new(num, scale);
-                serializer.serialize_str(&d.to_string())
-            }
+            Value::Num(m, e) => serializer.serialize_str(&format_decimal(*m, *e)),
             Value::Int(i) => serializer.serialize_i64(*i),
             Value::Datetime(i) => serializer.serialize_i64(*i),
             Value::Bool(b) => serializer.serialize_bool(*b),
@@ -0,0 +1,168 @@
+use crate::individual::*;
+use crate::individual2turtle::format_individual;
+use crate::prefix_map::PrefixMap;
+use rio_api::formatter::TriplesFormatter;
+use rio_api::model::*;
+use std::io;
+use std::io::Write;
+
+const XSD_NS: &str = "http://www.w3.org/2001/XMLSchema#";
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+
+/// Collects the triples as N-Quads lines with the prefixed names expanded to full IRIs
+struct NQuadsCollector<'a> {
+    prefixes: &'a PrefixMap,
+    graph: Option<String>,
+    lines: Vec<String>,
+}
+
+impl<'a> NQuadsCollector<'a> {
+    /// Full IRI of the prefixed name, `xsd:` and `rdf:` are known without the declaration.
+    /// A name with an unknown prefix is kept as is.
+    fn expand(&self, name: &str) -> String {
+        if let Some(iri) = self.prefixes.expand(name) {
+            return iri;
+        }
+        if let Some(local) = name.strip_prefix("xsd:") {
+            return format!("{}{}", XSD_NS, local);
+        }
+        if let Some(local) = name.strip_prefix("rdf:") {
+            return format!("{}{}", RDF_NS, local);
+        }
+        name.to_owned()
+    }
+}
+
+impl<'a> TriplesFormatter for NQuadsCollector<'a> {
+    type Error = io::Error;
+
+    fn format(&mut self, triple: &Triple<'_>) -> Result<(), io::Error> {
+        let subject_iri;
+        let subject = match triple.subject {
+            NamedOrBlankNode::NamedNode(n) => {
+                subject_iri = self.expand(n.iri);
+                NamedNode {
+                    iri: &subject_iri,
+                }
+                .into()
+            }
+            s => s,
+        };
+
+        let predicate_iri = self.expand(triple.predicate.iri);
+
+        let object_iri;
+        let object = match triple.object {
+            Term::NamedNode(n) => {
+                object_iri = self.expand(n.iri);
+                NamedNode {
+                    iri: &object_iri,
+                }
+                .into()
+            }
+            Term::Literal(Literal::Typed {
+                value,
+                datatype,
+            }) => {
+                object_iri = self.expand(datatype.iri);
+                Literal::Typed {
+                    value,
+                    datatype: NamedNode {
+                        iri: &object_iri,
+                    },
+                }
+                .into()
+            }
+            o => o,
+        };
+
+        let quad = Quad {
+            subject,
+            predicate: NamedNode {
+                iri: &predicate_iri,
+            },
+            object,
+            graph_name: self.graph.as_ref().map(|iri| {
+                NamedNode {
+                    iri,
+                }
+                .into()
+            }),
+        };
+        self.lines.push(quad.to_string());
+        Ok(())
+    }
+}
+
+/// Writes the individuals as N-Quads for a bulk loader: full IRIs without prefixes, one quad per line,
+/// the lines sorted and without duplicates. With the graph every quad is placed into it.
+pub fn bulk_export(inds: &[Individual], prefixes: &PrefixMap, graph: Option<&str>, w: &mut impl Write) -> Result<(), io::Error> {
+    let mut collector = NQuadsCollector {
+        prefixes,
+        graph: None,
+        lines: vec![],
+    };
+    collector.graph = graph.map(|g| collector.expand(g));
+
+    for indv in inds.iter() {
+        format_individual(indv, &mut collector)?;
+    }
+
+    collector.lines.sort_unstable();
+    collector.lines.dedup();
+    for line in collector.lines.iter() {
+        writeln!(w, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatype::Lang;
+    use rio_api::parser::QuadsParser;
+    use rio_turtle::{NQuadsParser, TurtleError};
+
+    fn document(uri: &str, count: i64) -> Individual {
+        let mut indv = Individual::default();
+        indv.set_id(uri);
+        indv.add_uri("rdf:type", "v-s:Document");
+        indv.add_string("rdfs:label", "label", Lang::EN);
+        indv.add_integer("v-s:count", count);
+        indv
+    }
+
+    #[test]
+    fn bulk_export_is_sorted_and_prefix_free() {
+        let mut prefixes = PrefixMap::new();
+        prefixes.add_prefix("d", "http://example.org/d#");
+        prefixes.add_prefix("v-s", "http://example.org/v-s#");
+        prefixes.add_prefix("rdfs", "http://www.w3.org/2000/01/rdf-schema#");
+        let indvs = [document("d:b", 2), document("d:a", 1), document("d:a", 1)];
+
+        let mut out = vec![];
+        bulk_export(&indvs, &prefixes, Some("d:graph"), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines.windows(2).all(|w| w[0] < w[1]), "{}", text);
+        assert_eq!(lines[0], "<http://example.org/d#a> <http://example.org/v-s#count> \"1\"^^<http://www.w3.org/2001/XMLSchema#integer> <http://example.org/d#graph> .");
+
+        let mut count = 0;
+        NQuadsParser::new(text.as_bytes())
+            .parse_all(&mut |q| -> Result<(), TurtleError> {
+                assert!(q.to_string().starts_with("<http://example.org/d#"));
+                assert_eq!(q.graph_name.map(|g| g.to_string()).as_deref(), Some("<http://example.org/d#graph>"));
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(count, 6);
+
+        let mut out = vec![];
+        bulk_export(&indvs[..1], &prefixes, None, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(!text.contains("graph"), "{}", text);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::individual::*;
+use crate::individual2turtle::{extract_prefixes, format_individual};
+use rio_api::formatter::TriplesFormatter;
+use rio_api::model::*;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XSD_NS: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Writes the triples as RDF/XML, one `rdf:Description` for every run of triples with the same subject.
+/// The prefixes become XML namespaces, prefixed names of subjects, objects and datatypes are expanded to full IRIs.
+pub struct RdfXmlFormatter<W: Write> {
+    write: W,
+    prefixes: HashMap<String, String>,
+    is_header_written: bool,
+    current_subject: Option<String>,
+}
+
+impl<W: Write> RdfXmlFormatter<W> {
+    /// The keys of prefixes are without the trailing `:`
+    pub fn new(write: W, prefixes: &HashMap<String, String>) -> Self {
+        let mut prefixes: HashMap<String, String> = prefixes.iter().map(|(p, ns)| (p.trim_end_matches(':').to_owned(), ns.to_owned())).collect();
+        prefixes.insert("rdf".to_owned(), RDF_NS.to_owned());
+        RdfXmlFormatter {
+            write,
+            prefixes,
+            is_header_written: false,
+            current_subject: None,
+        }
+    }
+
+    fn write_header(&mut self) -> Result<(), io::Error> {
+        if !self.is_header_written {
+            self.is_header_written = true;
+            writeln!(self.write, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+            write!(self.write, "<rdf:RDF")?;
+            let mut keys: Vec<&String> = self.prefixes.keys().collect();
+            keys.sort();
+            for prefix in keys {
+                write!(self.write, "\n    xmlns:{}=\"{}\"", prefix, escape_xml(&self.prefixes[prefix]))?;
+            }
+            writeln!(self.write, ">")?;
+        }
+        Ok(())
+    }
+
+    /// Full IRI of the prefixed name, the name is kept as is if its prefix is unknown
+    fn expand(&self, name: &str) -> String {
+        if let Some(pos) = name.find(':') {
+            if let Some(ns) = self.prefixes.get(&name[..pos]) {
+                return format!("{}{}", ns, &name[pos + 1..]);
+            }
+            if &name[..pos] == "xsd" {
+                return format!("{}{}", XSD_NS, &name[pos + 1..]);
+            }
+        }
+        name.to_owned()
+    }
+
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        self.write_header()?;
+        if self.current_subject.is_some() {
+            writeln!(self.write, "  </rdf:Description>")?;
+        }
+        writeln!(self.write, "</rdf:RDF>")?;
+        Ok(self.write)
+    }
+}
+
+impl<W: Write> TriplesFormatter for RdfXmlFormatter<W> {
+    type Error = io::Error;
+
+    fn format(&mut self, triple: &Triple<'_>) -> Result<(), io::Error> {
+        self.write_header()?;
+
+        let subject = match triple.subject {
+            NamedOrBlankNode::NamedNode(n) => format!("rdf:about=\"{}\"", escape_xml(&self.expand(n.iri))),
+            NamedOrBlankNode::BlankNode(n) => format!("rdf:nodeID=\"{}\"", escape_xml(n.id)),
+        };
+        if self.current_subject.as_ref() != Some(&subject) {
+            if self.current_subject.is_some() {
+                writeln!(self.write, "  </rdf:Description>")?;
+            }
+            writeln!(self.write, "  <rdf:Description {}>", subject)?;
+            self.current_subject = Some(subject);
+        }
+
+        // the element name of predicate is its prefixed name, a full IRI gets a local namespace declaration
+        let iri = triple.predicate.iri;
+        let (element, ns_decl) = match iri.split_once(':') {
+            Some((prefix, local)) if self.prefixes.contains_key(prefix) && is_ncname(local) => (iri.to_owned(), String::new()),
+            _ => {
+                let iri = self.expand(iri);
+                match split_qname(&iri) {
+                    Some(pos) => (format!("p:{}", &iri[pos..]), format!(" xmlns:p=\"{}\"", escape_xml(&iri[..pos]))),
+                    None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("predicate [{}] can not be written as XML element", iri))),
+                }
+            }
+        };
+
+        match triple.object {
+            Term::NamedNode(n) => writeln!(self.write, "    <{}{} rdf:resource=\"{}\"/>", element, ns_decl, escape_xml(&self.expand(n.iri)))?,
+            Term::BlankNode(n) => writeln!(self.write, "    <{}{} rdf:nodeID=\"{}\"/>", element, ns_decl, escape_xml(n.id))?,
+            Term::Literal(Literal::Simple {
+                value,
+            }) => writeln!(self.write, "    <{}{}>{}</{}>", element, ns_decl, escape_xml(value), element)?,
+            Term::Literal(Literal::LanguageTaggedString {
+                value,
+                language,
+            }) => writeln!(self.write, "    <{}{} xml:lang=\"{}\">{}</{}>", element, ns_decl, escape_xml(language), escape_xml(value), element)?,
+            Term::Literal(Literal::Typed {
+                value,
+                datatype,
+            }) => writeln!(
+                self.write,
+                "    <{}{} rdf:datatype=\"{}\">{}</{}>",
+                element,
+                ns_decl,
+                escape_xml(&self.expand(datatype.iri)),
+                escape_xml(value),
+                element
+            )?,
+        }
+        Ok(())
+    }
+}
+
+/// XML name without colons, the non-ASCII letters are accepted
+fn is_ncname(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '.' | '_' | '\u{B7}')),
+        _ => false,
+    }
+}
+
+/// Split point of the IRI into a namespace and the longest local name after the last `#` or `/` which is an NCName
+fn split_qname(iri: &str) -> Option<usize> {
+    let start = iri.rfind(['#', '/'])? + 1;
+    iri[start..].char_indices().map(|(i, _)| start + i).find(|pos| is_ncname(&iri[*pos..]))
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\r' => res.push_str("&#13;"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+pub fn to_rdfxml(indvs: &[Individual], all_prefixes: &HashMap<String, String>) -> Result<Vec<u8>, io::Error> {
+    let used_prefixes = extract_prefixes(indvs, all_prefixes);
+    let mut formatter = RdfXmlFormatter::new(Vec::default(), &used_prefixes);
+
+    for indv in indvs.iter() {
+        format_individual(indv, &mut formatter)?;
+    }
+
+    formatter.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatype::Lang;
+    use rio_api::parser::TriplesParser;
+    use rio_xml::{RdfXmlError, RdfXmlParser};
+
+    fn prefixes() -> HashMap<String, String> {
+        [("d:", "http://ex.org/d/"), ("v-s:", "http://ex.org/vs/")].iter().map(|(p, ns)| (p.to_string(), ns.to_string())).collect()
+    }
+
+    fn reparse(xml: &[u8]) -> Vec<String> {
+        let mut triples = vec![];
+        RdfXmlParser::new(xml, None)
+            .parse_all(&mut |t| -> Result<(), RdfXmlError> {
+                triples.push(t.to_string());
+                Ok(())
+            })
+            .unwrap();
+        triples.sort();
+        triples
+    }
+
+    #[test]
+    fn rdfxml_is_accepted_by_a_parser() {
+        let mut indv = Individual::default();
+        indv.set_id("d:doc1");
+        indv.add_string("v-s:label", "A & <b> \"c\"", Lang::EN);
+        indv.add_integer("v-s:count", 42);
+        indv.add_uri("v-s:next", "d:doc2");
+        indv.add_string("http://ex.org/p/1abc", "local name after the digit", Lang::NONE);
+
+        let xml = to_rdfxml(&[indv], &prefixes()).unwrap();
+        assert_eq!(
+            reparse(&xml),
+            vec![
+                "<http://ex.org/d/doc1> <http://ex.org/p/1abc> \"local name after the digit\" .",
+                "<http://ex.org/d/doc1> <http://ex.org/vs/count> \"42\"^^<http://www.w3.org/2001/XMLSchema#integer> .",
+                "<http://ex.org/d/doc1> <http://ex.org/vs/label> \"A & <b> \\\"c\\\"\"@en .",
+                "<http://ex.org/d/doc1> <http://ex.org/vs/next> <http://ex.org/d/doc2> .",
+            ]
+        );
+    }
+
+    #[test]
+    fn predicate_without_ncname_local_part_is_rejected() {
+        for predicate in ["http://ex.org/p/123", "v-s:123"] {
+            let mut indv = Individual::default();
+            indv.set_id("d:doc1");
+            indv.add_string(predicate, "x", Lang::NONE);
+            let err = to_rdfxml(&[indv], &prefixes()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -1,19 +1,32 @@
 use crate::datatype::*;
+use crate::export_manifest::ExportManifest;
 use crate::individual::*;
+use crate::prefix_map::PrefixMap;
 use crate::resource::*;
-use crate::turtle_formatters_with_prefixes::TurtleFormatterWithPrefixes;
-use chrono::{TimeZone, Utc};
+use crate::turtle_formatters_with_prefixes::{TurtleFormatterWithPrefixes, TurtleStyle};
 use rio_api::formatter::TriplesFormatter;
 use rio_api::model::*;
-use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
 use std::io;
+use std::path::Path;
 
-fn from_boolean<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a> {
-    let subject = NamedNode {
-        iri: id,
-    };
+/// Individuals keep blank nodes as uris with the `_:` prefix
+fn subject_node(id: &str) -> NamedOrBlankNode<'_> {
+    match id.strip_prefix("_:") {
+        Some(b) => BlankNode {
+            id: b,
+        }
+        .into(),
+        None => NamedNode {
+            iri: id,
+        }
+        .into(),
+    }
+}
 
+fn from_boolean<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a> {
     let predicate = NamedNode {
         iri: in_predicate,
     };
@@ -26,17 +39,13 @@ fn from_boolean<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a
     };
 
     Triple {
-        subject: subject.into(),
+        subject: subject_node(id),
         predicate,
         object: obj.into(),
     }
 }
 
 fn from_integer<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a> {
-    let subject = NamedNode {
-        iri: id,
-    };
-
     let predicate = NamedNode {
         iri: in_predicate,
     };
@@ -49,40 +58,30 @@ fn from_integer<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a
     };
 
     Triple {
-        subject: subject.into(),
+        subject: subject_node(id),
         predicate,
         object: obj.into(),
     }
 }
 
-fn from_decimal<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a> {
-    let subject = NamedNode {
-        iri: id,
-    };
-
+fn from_decimal<'a>(id: &'a str, in_predicate: &'a str, v: &'a str, datatype: NamedNode<'a>) -> Triple<'a> {
     let predicate = NamedNode {
         iri: in_predicate,
     };
 
     let obj = Literal::Typed {
         value: v,
-        datatype: NamedNode {
-            iri: "xsd:decimal",
-        },
+        datatype,
     };
 
     Triple {
-        subject: subject.into(),
+        subject: subject_node(id),
         predicate,
         object: obj.into(),
     }
 }
 
 fn from_datetime<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a> {
-    let subject = NamedNode {
-        iri: id,
-    };
-
     let predicate = NamedNode {
         iri: in_predicate,
     };
@@ -95,37 +94,25 @@ fn from_datetime<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'
     };
 
     Triple {
-        subject: subject.into(),
+        subject: subject_node(id),
         predicate,
         object: obj.into(),
     }
 }
 
 fn from_uri<'a>(id: &'a str, in_predicate: &'a str, v: &'a str) -> Triple<'a> {
-    let subject = NamedNode {
-        iri: id,
-    };
-
     let predicate = NamedNode {
         iri: in_predicate,
     };
 
-    let obj = NamedNode {
-        iri: v,
-    };
-
     Triple {
-        subject: subject.into(),
+        subject: subject_node(id),
         predicate,
-        object: obj.into(),
+        object: subject_node(v).into(),
     }
 }
 
 fn from_string<'a>(id: &'a str, in_predicate: &'a str, s: &'a str, l: Lang) -> Triple<'a> {
-    let subject = NamedNode {
-        iri: id,
-    };
-
     let predicate = NamedNode {
         iri: in_predicate,
     };
@@ -145,20 +132,72 @@ fn from_string<'a>(id: &'a str, in_predicate: &'a str, s: &'a str, l: Lang) -> T
     };
 
     Triple {
-        subject: subject.into(),
+        subject: subject_node(id),
         predicate,
         object: obj.into(),
     }
 }
 
-fn format_resources(subject: &str, predicate: &str, resources: &[Resource], formatter: &mut TurtleFormatterWithPrefixes<Vec<u8>>) -> Result<(), io::Error> {
+/// Lexical value and datatype of the integer literal
+pub fn integer_literal(v: i64) -> (String, NamedNode<'static>) {
+    (
+        v.to_string(),
+        NamedNode {
+            iri: "xsd:integer",
+        },
+    )
+}
+
+/// Lexical value and datatype of the datetime literal from unix time in seconds, `None` if out of range
+pub fn datetime_literal(secs: i64) -> Option<(String, NamedNode<'static>)> {
+    datetime_secs_to_xsd(secs).map(|v| {
+        (
+            v,
+            NamedNode {
+                iri: "xsd:dateTime",
+            },
+        )
+    })
+}
+
+/// Lexical value and datatype of the decimal literal, the value in the `E` notation is `xsd:double`
+pub fn decimal_literal(mantissa: i64, exponent: i64) -> (String, NamedNode<'static>) {
+    (
+        format_decimal(mantissa, exponent),
+        NamedNode {
+            iri: if is_e_notation(mantissa, exponent) {
+                "xsd:double"
+            } else {
+                "xsd:decimal"
+            },
+        },
+    )
+}
+
+/// Lexical value and datatype of the binary literal
+pub fn binary_literal(data: &[u8], encoding: BinaryEncoding) -> (String, NamedNode<'static>) {
+    (
+        encoding.encode(data),
+        NamedNode {
+            iri: encoding.datatype(),
+        },
+    )
+}
+
+fn format_resources<F: TriplesFormatter<Error = io::Error>>(
+    subject: &str,
+    predicate: &str,
+    resources: &[Resource],
+    binary: Option<BinaryEncoding>,
+    formatter: &mut F,
+) -> Result<(), io::Error> {
     for r in resources {
         match r.rtype {
             DataType::Boolean => {
                 formatter.format(&from_boolean(subject, &predicate, &r.get_bool().to_string()))?;
             }
             DataType::Integer => {
-                formatter.format(&from_integer(subject, &predicate, &r.get_int().to_string()))?;
+                formatter.format(&from_integer(subject, &predicate, &integer_literal(r.get_int()).0))?;
             }
             DataType::Uri => {
                 formatter.format(&from_uri(subject, &predicate, &r.get_uri()))?;
@@ -167,15 +206,33 @@ fn format_resources(subject: &str, predicate: &str, resources: &[Resource], form
                 formatter.format(&from_string(subject, &predicate, r.get_str(), r.get_lang()))?;
             }
             DataType::Datetime => {
-                formatter.format(&from_datetime(subject, &predicate, &format!("{:?}", &Utc.timestamp(r.get_datetime(), 0))))?;
+                if let Some((dt, _)) = datetime_literal(r.get_datetime()) {
+                    formatter.format(&from_datetime(subject, &predicate, &dt))?;
+                } else {
+                    error!("datetime {} is out of range, predicate={}, id={}", r.get_datetime(), predicate, subject);
+                }
             }
             DataType::Decimal => {
                 let (m, e) = r.get_num();
-                let c = exponent_to_scale(&m, &e);
-                let d = Decimal::new(c.0, c.1);
-                formatter.format(&from_decimal(subject, &predicate, &format!("{:?}", d.to_string())))?;
+                let (value, datatype) = decimal_literal(m, e);
+                formatter.format(&from_decimal(subject, &predicate, &value, datatype))?;
+            }
+            DataType::Binary => {
+                if let Some(enc) = binary {
+                    let (value, datatype) = binary_literal(r.get_binary(), enc);
+                    formatter.format(&Triple {
+                        subject: subject_node(subject),
+                        predicate: NamedNode {
+                            iri: predicate,
+                        },
+                        object: Literal::Typed {
+                            value: &value,
+                            datatype,
+                        }
+                        .into(),
+                    })?;
+                }
             }
-            _ => {}
         }
     }
     Ok(())
@@ -198,7 +255,7 @@ fn collect_prefix(v: &str, all_prefixes: &HashMap<String, String>, used_prefixes
     }
 }
 
-fn extract_prefixes(indvs: &[Individual], all_prefixes: &HashMap<String, String>) -> HashMap<String, String> {
+pub(crate) fn extract_prefixes(indvs: &[Individual], all_prefixes: &HashMap<String, String>) -> HashMap<String, String> {
     let mut used_prefixes = HashMap::new();
 
     collect_prefix("xsd:", &all_prefixes, &mut used_prefixes);
@@ -219,23 +276,421 @@ fn extract_prefixes(indvs: &[Individual], all_prefixes: &HashMap<String, String>
 }
 
 pub fn to_turtle(indvs: &[Individual], all_prefixes: &mut HashMap<String, String>) -> Result<Vec<u8>, io::Error> {
+    to_turtle_with_style(indvs, all_prefixes, TurtleStyle::default())
+}
+
+pub fn to_turtle_with_style(indvs: &[Individual], all_prefixes: &mut HashMap<String, String>, style: TurtleStyle) -> Result<Vec<u8>, io::Error> {
+    write_turtle(indvs, all_prefixes, style, Vec::default())
+}
+
+fn write_turtle<W: io::Write>(indvs: &[Individual], all_prefixes: &HashMap<String, String>, style: TurtleStyle, w: W) -> Result<W, io::Error> {
     let used_prefixes = extract_prefixes(&indvs, all_prefixes);
-    let mut formatter = TurtleFormatterWithPrefixes::new(Vec::default(), &used_prefixes);
+    let binary = style.binary_encoding;
+    let mut formatter = TurtleFormatterWithPrefixes::new_with_style(w, &used_prefixes, style);
 
     for indv in indvs.iter() {
-        for (predicate, resources) in &indv.obj.resources {
-            if predicate == "rdf:type" {
-                format_resources(&indv.get_id(), predicate, resources, &mut formatter)?;
-                break;
-            }
+        format_individual_with_binaries(indv, binary, &mut formatter)?;
+    }
+
+    formatter.finish()
+}
+
+/// `io::Write` over a `fmt::Write`, the bytes of a char split between the writes are kept until it is complete
+struct FmtWriter<'a, F: fmt::Write> {
+    inner: &'a mut F,
+    pending: Vec<u8>,
+}
+
+impl<'a, F: fmt::Write> io::Write for FmtWriter<'a, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(s) => s.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let s = std::str::from_utf8(&self.pending[..valid]).unwrap_or_default();
+        self.inner.write_str(s).map_err(|_| io::Error::other("fail write to fmt::Write"))?;
+        self.pending.drain(..valid);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the individuals as turtle to a `fmt::Write`, e.g. a `String` or the `fmt::Formatter` of a `Display` impl
+pub fn format_turtle<F: fmt::Write>(indvs: &[Individual], all_prefixes: &HashMap<String, String>, style: TurtleStyle, f: &mut F) -> fmt::Result {
+    let w = FmtWriter {
+        inner: f,
+        pending: vec![],
+    };
+    match write_turtle(indvs, all_prefixes, style, w) {
+        Ok(w) if w.pending.is_empty() => Ok(()),
+        _ => Err(fmt::Error),
+    }
+}
+
+/// Triples of the individual, `rdf:type` goes first, the container members `rdf:_N` go last ordered by N
+pub(crate) fn format_individual<F: TriplesFormatter<Error = io::Error>>(indv: &Individual, formatter: &mut F) -> Result<(), io::Error> {
+    format_individual_with_binaries(indv, None, formatter)
+}
+
+/// Triples of the individual, with the encoding the binary values are included
+fn format_individual_with_binaries<F: TriplesFormatter<Error = io::Error>>(
+    indv: &Individual,
+    binary: Option<BinaryEncoding>,
+    formatter: &mut F,
+) -> Result<(), io::Error> {
+    if let Some(resources) = indv.obj.resources.get("rdf:type") {
+        format_resources(indv.get_id(), "rdf:type", resources, binary, formatter)?;
+    }
+    let mut members = vec![];
+    for (predicate, resources) in &indv.obj.resources {
+        if predicate == "rdf:type" || predicate == "v-s:updateCounter" {
+            continue;
         }
-        for (predicate, resources) in &indv.obj.resources {
-            if predicate == "rdf:type" || predicate == "v-s:updateCounter" {
-                continue;
+        if let Some(n) = predicate.strip_prefix("rdf:_").and_then(container_index) {
+            members.push((n, predicate, resources));
+            continue;
+        }
+        format_resources(indv.get_id(), predicate, resources, binary, formatter)?;
+    }
+    members.sort_by_key(|(n, _, _)| *n);
+    for (_, predicate, resources) in members {
+        format_resources(indv.get_id(), predicate, resources, binary, formatter)?;
+    }
+    Ok(())
+}
+
+/// Passes the triples through to the inner formatter counting them
+struct CountingFormatter<'a, F: TriplesFormatter<Error = io::Error>> {
+    inner: &'a mut F,
+    count: usize,
+}
+
+impl<'a, F: TriplesFormatter<Error = io::Error>> TriplesFormatter for CountingFormatter<'a, F> {
+    type Error = io::Error;
+
+    fn format(&mut self, triple: &Triple<'_>) -> Result<(), io::Error> {
+        self.inner.format(triple)?;
+        self.count += 1;
+        Ok(())
+    }
+}
+
+/// Passes the triples through to the inner formatter, the strings tagged with other languages are dropped
+struct LangFormatter<'a, F: TriplesFormatter<Error = io::Error>> {
+    inner: &'a mut F,
+    lang: &'a str,
+}
+
+impl<'a, F: TriplesFormatter<Error = io::Error>> TriplesFormatter for LangFormatter<'a, F> {
+    type Error = io::Error;
+
+    fn format(&mut self, triple: &Triple<'_>) -> Result<(), io::Error> {
+        if let Term::Literal(Literal::LanguageTaggedString {
+            language,
+            ..
+        }) = triple.object
+        {
+            if !language.eq_ignore_ascii_case(self.lang) {
+                return Ok(());
             }
-            format_resources(&indv.get_id(), predicate, resources, &mut formatter)?;
         }
+        self.inner.format(triple)
     }
+}
 
-    formatter.finish()
+/// Writes the triples of individual to the formatter and records it in the manifest
+pub fn write_individual<F: TriplesFormatter<Error = io::Error>>(indv: &Individual, formatter: &mut F, manifest: &mut ExportManifest) -> Result<(), io::Error> {
+    let mut counting = CountingFormatter {
+        inner: formatter,
+        count: 0,
+    };
+    format_individual(indv, &mut counting)?;
+    manifest.record(indv, counting.count);
+    Ok(())
+}
+
+/// As `write_individual`, of the strings only the ones in the language `lang` and the ones without a language are written
+pub fn write_individual_lang<F: TriplesFormatter<Error = io::Error>>(
+    indv: &Individual,
+    formatter: &mut F,
+    manifest: &mut ExportManifest,
+    lang: &str,
+) -> Result<(), io::Error> {
+    let mut counting = CountingFormatter {
+        inner: formatter,
+        count: 0,
+    };
+    format_individual(
+        indv,
+        &mut LangFormatter {
+            inner: &mut counting,
+            lang,
+        },
+    )?;
+    manifest.record(indv, counting.count);
+    Ok(())
+}
+
+/// Writes every individual to its own file `<slug>.ttl` in the directory, the directory is created if missing.
+/// Repeated slugs get the suffixes `-1`, `-2`, ... in the order of individuals. Returns the count of written files.
+pub fn write_individuals_to_dir(inds: &[Individual], dir: &Path, prefixes: &PrefixMap, slug: impl Fn(&str) -> String) -> io::Result<usize> {
+    fs::create_dir_all(dir)?;
+
+    let mut all_prefixes: HashMap<String, String> = prefixes.as_map().iter().map(|(p, ns)| (p.to_owned() + ":", ns.to_owned())).collect();
+    let mut used_names = HashSet::new();
+    let mut count = 0;
+    for indv in inds.iter() {
+        let base = slug(indv.get_id());
+        let mut name = base.clone();
+        let mut n = 0;
+        while !used_names.insert(name.clone()) {
+            n += 1;
+            name = format!("{}-{}", base, n);
+        }
+
+        let ttl = to_turtle(std::slice::from_ref(indv), &mut all_prefixes)?;
+        fs::write(dir.join(name + ".ttl"), ttl)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::turtle_formatters_with_prefixes::PredicateFilter;
+    use rio_api::parser::TriplesParser;
+    use rio_turtle::{TurtleError, TurtleParser};
+    use std::convert::TryFrom;
+
+    /// The prefixes as `to_turtle` takes them, with the trailing `:`
+    fn all_prefixes() -> HashMap<String, String> {
+        let mut res = HashMap::new();
+        res.insert("d:".to_owned(), "http://example.org/d#".to_owned());
+        res.insert("v-s:".to_owned(), "http://example.org/v-s#".to_owned());
+        res.insert("rdf:".to_owned(), "http://www.w3.org/1999/02/22-rdf-syntax-ns#".to_owned());
+        res.insert("rdfs:".to_owned(), "http://www.w3.org/2000/01/rdf-schema#".to_owned());
+        res.insert("xsd:".to_owned(), "http://www.w3.org/2001/XMLSchema#".to_owned());
+        res
+    }
+
+    /// Triples of the turtle text in the N-Triples form, sorted
+    fn reparse(text: &[u8]) -> Vec<String> {
+        let mut res = vec![];
+        TurtleParser::new(text, None)
+            .parse_all(&mut |t| -> Result<(), TurtleError> {
+                res.push(t.to_string());
+                Ok(())
+            })
+            .unwrap_or_else(|e| panic!("{}\n{}", e, String::from_utf8_lossy(text)));
+        res.sort();
+        res
+    }
+
+    fn document(uri: &str) -> Individual {
+        let mut indv = Individual::default();
+        indv.set_id(uri);
+        indv.add_uri("rdf:type", "v-s:Document");
+        indv.add_string("rdfs:label", "label", Lang::EN);
+        indv.add_integer("v-s:updateCounter", 3);
+        indv.add_integer("v-s:count", 1);
+        indv.add_integer("v-s:count", 2);
+        indv
+    }
+
+    fn predicates_of(triples: &[String]) -> HashSet<String> {
+        triples.iter().map(|t| t.split(' ').nth(1).unwrap_or_default().to_owned()).collect()
+    }
+
+    #[test]
+    fn predicate_blocklist_and_allowlist() {
+        let indvs = [document("d:a"), document("d:b")];
+
+        let style = TurtleStyle {
+            predicate_filter: PredicateFilter::Exclude(["v-s:updateCounter".to_owned(), "rdfs:label".to_owned()].iter().cloned().collect()),
+            ..TurtleStyle::default()
+        };
+        let out = to_turtle_with_style(&indvs, &mut all_prefixes(), style).unwrap();
+        let triples = reparse(&out);
+        assert_eq!(triples.len(), 6);
+        let expected: HashSet<String> = ["<http://www.w3.org/1999/02/22-rdf-syntax-ns#type>", "<http://example.org/v-s#count>"].iter().map(|p| p.to_string()).collect();
+        assert_eq!(predicates_of(&triples), expected);
+
+        let style = TurtleStyle {
+            predicate_filter: PredicateFilter::Only(["v-s:count".to_owned()].iter().cloned().collect()),
+            ..TurtleStyle::default()
+        };
+        let out = to_turtle_with_style(&indvs, &mut all_prefixes(), style).unwrap();
+        let triples = reparse(&out);
+        assert_eq!(triples.len(), 4);
+        assert_eq!(predicates_of(&triples).len(), 1);
+        assert!(!String::from_utf8(out).unwrap().contains("; ."));
+    }
+
+    #[test]
+    fn typed_literal_helpers() {
+        let (value, datatype) = integer_literal(-42);
+        assert_eq!((value.as_str(), datatype.iri), ("-42", "xsd:integer"));
+
+        let (value, datatype) = datetime_literal(86_400).unwrap();
+        assert_eq!((value.as_str(), datatype.iri), ("1970-01-02T00:00:00Z", "xsd:dateTime"));
+        assert!(datetime_literal(i64::MAX).is_none());
+
+        let (value, datatype) = decimal_literal(-150, -2);
+        assert_eq!((value.as_str(), datatype.iri), ("-1.50", "xsd:decimal"));
+        assert_eq!(decimal_literal(15, 1).0, "150");
+        let (value, datatype) = decimal_literal(15, 2000);
+        assert_eq!((value.as_str(), datatype.iri), ("15E2000", "xsd:double"));
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_decimal_d("v-s:amount", 15, 2000);
+        let out = String::from_utf8(to_turtle(&[indv], &mut all_prefixes()).unwrap()).unwrap();
+        assert!(out.contains("v-s:amount \"15E2000\"^^xsd:double"), "{}", out);
+        assert_eq!(reparse(out.as_bytes()).len(), 1);
+
+        let (value, datatype) = binary_literal(&[0xde, 0xad], BinaryEncoding::Hex);
+        assert_eq!((value.as_str(), datatype.iri), ("DEAD", "xsd:hexBinary"));
+        let (value, datatype) = binary_literal(&[0xde, 0xad], BinaryEncoding::Base64);
+        assert_eq!((value.as_str(), datatype.iri), ("3q0=", "xsd:base64Binary"));
+    }
+
+    #[test]
+    fn individuals_to_dir_with_slug_collision() {
+        let dir = std::env::temp_dir().join(format!("v-onto-ttl-{}", std::process::id())).join("out");
+        let _ = fs::remove_dir_all(&dir);
+        let prefixes = PrefixMap::from_map(&all_prefixes().into_iter().map(|(p, ns)| (p.trim_end_matches(':').to_owned(), ns)).collect());
+        let slug = |uri: &str| uri.split(':').next().unwrap_or_default().to_owned();
+
+        let indvs = vec![document("d:a"), document("d:b"), document("v-s:c")];
+        assert_eq!(write_individuals_to_dir(&indvs, &dir, &prefixes, slug).unwrap(), 3);
+
+        let mut names: Vec<String> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name().into_string().unwrap()).collect();
+        names.sort();
+        assert_eq!(names, vec!["d-1.ttl", "d.ttl", "v-s.ttl"]);
+        let first = reparse(&fs::read(dir.join("d.ttl")).unwrap());
+        assert!(first.iter().all(|t| t.starts_with("<http://example.org/d#a>")), "{:?}", first);
+        let second = reparse(&fs::read(dir.join("d-1.ttl")).unwrap());
+        assert!(second.iter().all(|t| t.starts_with("<http://example.org/d#b>")), "{:?}", second);
+        // the values of v-s:updateCounter are not written
+        assert_eq!(first.len(), 4);
+
+        fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn manifest_counts_match_the_exported_individuals() {
+        let mut person = Individual::default();
+        person.set_id("d:p");
+        person.add_uri("rdf:type", "v-s:Person");
+        person.add_uri("rdf:type", "v-s:Document");
+        let indvs = [document("d:a"), document("d:b"), person];
+
+        let prefixes: HashMap<String, String> = all_prefixes().into_iter().map(|(p, ns)| (p.trim_end_matches(':').to_owned(), ns)).collect();
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes);
+        let mut manifest = ExportManifest::new();
+        for indv in indvs.iter() {
+            write_individual(indv, &mut formatter, &mut manifest).unwrap();
+        }
+        let triples = reparse(&formatter.finish().unwrap());
+
+        assert_eq!(manifest.subjects(), &["d:a".to_owned(), "d:b".to_owned(), "d:p".to_owned()]);
+        assert_eq!(manifest.class_count("v-s:Document"), 3);
+        assert_eq!(manifest.class_count("v-s:Person"), 1);
+        assert_eq!(manifest.class_count("v-s:Absent"), 0);
+        assert_eq!(manifest.triple_count(), triples.len());
+        assert_eq!(manifest.triple_count(), 10);
+
+        let json = manifest.to_json();
+        assert_eq!(json["subjects"], serde_json::json!(["d:a", "d:b", "d:p"]));
+        assert_eq!(json["classes"]["v-s:Document"], 3);
+        assert_eq!(json["triples"], 10);
+    }
+
+    #[test]
+    fn triple_count_matches_the_written_triples() {
+        let mut indv = document("d:a");
+        indv.add_binary("v-s:data", vec![1, 2]);
+        indv.add_datetime("v-s:created", 0);
+        indv.add_datetime("v-s:created", i64::MAX);
+        indv.add_decimal_d("v-s:amount", 15, -1);
+        indv.add_bool("v-s:deleted", false);
+        indv.add_uri("v-s:author", "_:b0");
+
+        let prefixes: HashMap<String, String> = all_prefixes().into_iter().map(|(p, ns)| (p.trim_end_matches(':').to_owned(), ns)).collect();
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes);
+        let mut manifest = ExportManifest::new();
+        write_individual(&indv, &mut formatter, &mut manifest).unwrap();
+        let triples = reparse(&formatter.finish().unwrap());
+
+        assert_eq!(indv.triple_count(), manifest.triple_count());
+        assert_eq!(indv.triple_count(), triples.len());
+        assert_eq!(indv.triple_count(), 8);
+    }
+
+    #[test]
+    fn blank_subject_is_written_as_blank_node() {
+        let mut src = Individual::default();
+        src.set_id("_:b0");
+        src.add_string("rdfs:label", "label", Lang::EN);
+        src.add_uri("v-s:parent", "d:p");
+        let mut msgpack = vec![];
+        crate::individual2msgpack::to_msgpack(&src, &mut msgpack).unwrap();
+        let mut cbor = vec![];
+        crate::individual2cbor::to_cbor(&src, &mut cbor).unwrap();
+
+        for data in [msgpack, cbor] {
+            let indv = Individual::try_from(data.as_slice()).unwrap();
+            assert!(indv.subject_is_blank());
+            let triples = reparse(&to_turtle(&[indv], &mut all_prefixes()).unwrap());
+            assert_eq!(triples.len(), 2);
+            assert!(triples.iter().all(|t| t.starts_with("_:")), "{:?}", triples);
+        }
+        assert!(!document("d:a").subject_is_blank());
+    }
+
+    /// An individual displayed as turtle
+    struct AsTurtle<'a>(&'a Individual);
+
+    impl<'a> fmt::Display for AsTurtle<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            format_turtle(std::slice::from_ref(self.0), &all_prefixes(), TurtleStyle::default(), f)
+        }
+    }
+
+    #[test]
+    fn display_of_individual_as_turtle() {
+        let mut indv = document("d:a");
+        indv.add_string("rdfs:label", "документ", Lang::RU);
+        let shown = AsTurtle(&indv).to_string();
+        assert_eq!(shown.as_bytes(), to_turtle(&[indv], &mut all_prefixes()).unwrap().as_slice());
+        assert!(shown.contains("\"документ\"@ru"), "{}", shown);
+        assert_eq!(reparse(shown.as_bytes()).len(), 5);
+    }
+
+    #[test]
+    fn multilingual_individual_written_in_en() {
+        let mut indv = document("d:a");
+        indv.add_string("rdfs:label", "документ", Lang::RU);
+        indv.add_string("v-s:comment", "no language", Lang::NONE);
+        indv.add_string("v-s:comment", "только по-русски", Lang::RU);
+
+        let prefixes: HashMap<String, String> = all_prefixes().into_iter().map(|(p, ns)| (p.trim_end_matches(':').to_owned(), ns)).collect();
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes);
+        let mut manifest = ExportManifest::new();
+        write_individual_lang(&indv, &mut formatter, &mut manifest, "en").unwrap();
+        let triples = reparse(&formatter.finish().unwrap());
+
+        let literals: Vec<&str> = triples.iter().filter_map(|t| t.split_once(" \"").map(|(_, o)| o)).collect();
+        assert_eq!(triples.len(), 5, "{:?}", triples);
+        assert!(literals.contains(&"label\"@en ."));
+        assert!(literals.contains(&"no language\" ."));
+        assert!(!triples.iter().any(|t| t.contains("@ru")));
+        assert!(triples.iter().any(|t| t.contains("v-s#Document>")));
+        assert_eq!(manifest.triple_count(), triples.len());
+    }
 }
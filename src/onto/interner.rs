@@ -0,0 +1,59 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Pool of predicate names for bulk parsing: the parser takes the name of a predicate from the pool
+/// instead of decoding it into a new string for every individual. The pool only grows, one pool is
+/// meant for one batch of individuals of the same ontology.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// The pooled copy of the string, it is allocated only on the first call with it
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(v) = self.strings.get(s) {
+            return v.clone();
+        }
+        let v: Arc<str> = Arc::from(s);
+        self.strings.insert(v.clone());
+        v
+    }
+
+    /// The pooled copy of the bytes as a string, the sequences which are not valid UTF-8 are replaced
+    pub fn intern_bytes(&mut self, b: &[u8]) -> Arc<str> {
+        match std::str::from_utf8(b) {
+            Ok(s) => self.intern(s),
+            Err(_) => self.intern(&String::from_utf8_lossy(b)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_returns_the_same_allocation() {
+        let mut pool = Interner::new();
+        let a = pool.intern("rdf:type");
+        let b = pool.intern_bytes(b"rdf:type");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(pool.len(), 1);
+
+        assert_eq!(&*pool.intern_bytes(b"v-s:\xffname"), "v-s:\u{fffd}name");
+        assert_eq!(pool.len(), 2);
+    }
+}
@@ -1,10 +1,54 @@
-use crate::datatype::{DataType, Lang};
-use crate::individual::Individual;
+use crate::datatype::{DataType, EpochUnit, Lang};
+use crate::individual::{datetime_from_str, Individual};
+use crate::parser::ParseError;
 use serde_json::value::Value as JSONValue;
 use serde_json::Map;
 
+/// Rules of the datatype of the values without the field [type], a bare json value is the same as `{"data": value}`.
+/// Booleans are read as boolean, numbers as integer or decimal, strings as string or datetime.
+/// The values with the field [type] are read as typed. The rules apply only to `parse_json_to_individual_with_coercion`,
+/// the other functions read the canonical json, where a value without the field [type] is an error.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct JsonCoercion {
+    /// Unit of the numeric values of datetime
+    pub unit: EpochUnit,
+    /// A string in one of the forms of `Individual::add_datetime_from_str` is read as datetime
+    pub detect_datetime: bool,
+    /// A number without the fraction is read as integer, otherwise as decimal
+    pub integral_as_integer: bool,
+    /// A predicate with an empty array of values is kept as present without values, by default it is dropped
+    pub keep_empty_predicates: bool,
+}
+
+impl Default for JsonCoercion {
+    fn default() -> Self {
+        JsonCoercion {
+            unit: EpochUnit::Seconds,
+            detect_datetime: false,
+            integral_as_integer: true,
+            keep_empty_predicates: false,
+        }
+    }
+}
+
 pub fn parse_json_to_individual(src: &JSONValue, dest: &mut Individual) -> bool {
+    parse_json_to_individual_with_unit(src, dest, EpochUnit::Seconds)
+}
+
+/// The numeric values of datetime are read in the given unit
+pub fn parse_json_to_individual_with_unit(src: &JSONValue, dest: &mut Individual, unit: EpochUnit) -> bool {
+    json_to_individual(src, dest, unit, None)
+}
+
+/// The datatypes of the values without the field [type] are inferred by the rules
+pub fn parse_json_to_individual_with_coercion(src: &JSONValue, dest: &mut Individual, coercion: &JsonCoercion) -> bool {
+    json_to_individual(src, dest, coercion.unit, Some(coercion))
+}
+
+/// Without the rules of coercion every value must be an object with the fields [type] and [data]
+fn json_to_individual(src: &JSONValue, dest: &mut Individual, unit: EpochUnit, coercion: Option<&JsonCoercion>) -> bool {
     let mut res = true;
+    let keep_empty_predicates = coercion.is_some_and(|c| c.keep_empty_predicates);
 
     if let Some(props) = src.as_object() {
         for (key, value) in props.iter() {
@@ -16,7 +60,9 @@ pub fn parse_json_to_individual(src: &JSONValue, dest: &mut Individual) -> bool
                     res = false;
                 }
             } else if let Some(values) = value.as_array() {
-                if !json_to_predicate(key, values, dest) {
+                if values.is_empty() && keep_empty_predicates {
+                    dest.obj.resources.entry(key.to_owned()).or_default();
+                } else if !json_to_predicate(key, values, dest, unit, coercion) {
                     res = false;
                 }
             } else {
@@ -29,6 +75,49 @@ pub fn parse_json_to_individual(src: &JSONValue, dest: &mut Individual) -> bool
     res
 }
 
+/// Individuals of a single json object or of an array of objects, the first malformed element aborts the conversion
+pub fn individuals_from_json(value: &JSONValue) -> Result<Vec<Individual>, ParseError> {
+    let to_individual = |src: &JSONValue| -> Option<Individual> {
+        let mut indv = Individual::default();
+        if src.is_object() && parse_json_to_individual(src, &mut indv) {
+            Some(indv)
+        } else {
+            None
+        }
+    };
+
+    match value {
+        JSONValue::Array(elements) => {
+            let mut res = Vec::with_capacity(elements.len());
+            for (idx, el) in elements.iter().enumerate() {
+                match to_individual(el) {
+                    Some(indv) => res.push(indv),
+                    None => return Err(ParseError::Invalid(format!("json: malformed individual at index {}", idx))),
+                }
+            }
+            Ok(res)
+        }
+        JSONValue::Object(_) => match to_individual(value) {
+            Some(indv) => Ok(vec![indv]),
+            None => Err(ParseError::Invalid("json: malformed individual".to_owned())),
+        },
+        _ => Err(ParseError::Invalid("json: expected an object or an array of objects".to_owned())),
+    }
+}
+
+/// Individuals of the json text, see `individuals_from_json`. A text which ends before the json is complete
+/// is `ParseError::Truncated`, so a stream reader can wait for more input, any other malformed json is `Invalid`.
+pub fn individuals_from_json_slice(data: &[u8]) -> Result<Vec<Individual>, ParseError> {
+    if data.iter().all(|b| b.is_ascii_whitespace()) {
+        return Err(ParseError::Empty);
+    }
+    match serde_json::from_slice(data) {
+        Ok(v) => individuals_from_json(&v),
+        Err(e) if e.is_eof() => Err(ParseError::Truncated),
+        Err(e) => Err(ParseError::Invalid(format!("json: {}", e))),
+    }
+}
+
 fn get_datatype_from_json(val: Option<&JSONValue>) -> Result<DataType, String> {
     if val.is_none() {
         return Err("not content field type".to_owned());
@@ -72,79 +161,195 @@ fn add_string(v: &Map<String, JSONValue>, vdata: &JSONValue, predicate: &str, de
     }
 }
 
-fn json_to_predicate(predicate: &str, values: &[JSONValue], dest: &mut Individual) -> bool {
+/// Datatype of the untyped value by the rules of coercion
+fn infer_datatype(vdata: &JSONValue, coercion: &JsonCoercion) -> Result<DataType, String> {
+    match vdata {
+        JSONValue::Bool(_) => Ok(DataType::Boolean),
+        JSONValue::Number(n) if coercion.integral_as_integer && n.is_i64() => Ok(DataType::Integer),
+        JSONValue::Number(_) => Ok(DataType::Decimal),
+        JSONValue::String(s) if coercion.detect_datetime && datetime_from_str(s).is_some() => Ok(DataType::Datetime),
+        JSONValue::String(_) => Ok(DataType::String),
+        _ => Err("can not infer the datatype of value".to_owned()),
+    }
+}
+
+fn json_to_predicate(predicate: &str, values: &[JSONValue], dest: &mut Individual, unit: EpochUnit, coercion: Option<&JsonCoercion>) -> bool {
     let mut res = true;
+    let empty = Map::new();
     for val in values {
-        if let Some(v) = val.as_object() {
-            let vdata = v.get("data");
-            if vdata.is_none() {
-                error!("json->individual: predicate [{}], value must contain [data]", predicate);
+        // a bare value is the data of a value without the type
+        let (v, vdata) = match (val.as_object(), coercion) {
+            (Some(v), _) => (v, v.get("data")),
+            (None, Some(_)) => (&empty, Some(val)),
+            (None, None) => {
+                error!("json->individual: predicate [{}], value must be an object", predicate);
                 res = false;
                 continue;
             }
-            let ptype = get_datatype_from_json(v.get("type"));
-            if ptype.is_err() {
-                error!("json->individual: predicate [{}], invalid value", predicate);
-                res = false;
-                continue;
-            }
-            let vdata = vdata.unwrap();
+        };
+        if vdata.is_none() {
+            error!("json->individual: predicate [{}], value must contain [data]", predicate);
+            res = false;
+            continue;
+        }
+        let ptype = match (v.get("type"), coercion) {
+            (None, Some(c)) => infer_datatype(vdata.unwrap_or(&JSONValue::Null), c),
+            (t, _) => get_datatype_from_json(t),
+        };
+        if ptype.is_err() {
+            error!("json->individual: predicate [{}], invalid value", predicate);
+            res = false;
+            continue;
+        }
+        let vdata = vdata.unwrap();
 
-            match ptype.unwrap() {
-                DataType::Uri => {
-                    if let Some(v) = vdata.as_str() {
-                        dest.add_uri(predicate, v);
-                    }
+        match ptype.unwrap() {
+            DataType::Uri => {
+                if let Some(v) = vdata.as_str() {
+                    dest.add_uri(predicate, v);
                 }
-                DataType::String => add_string(v, vdata, predicate, dest),
+            }
+            DataType::String => add_string(v, vdata, predicate, dest),
 
-                DataType::Integer => {
+            DataType::Integer => {
+                if let Some(v) = vdata.as_i64() {
+                    dest.add_integer(predicate, v);
+                }
+            }
+            DataType::Datetime => {
+                if vdata.is_number() {
                     if let Some(v) = vdata.as_i64() {
-                        dest.add_integer(predicate, v);
+                        dest.add_datetime(predicate, unit.to_seconds(v));
                     }
-                }
-                DataType::Datetime => {
-                    if vdata.is_number() {
-                        if let Some(v) = vdata.as_i64() {
-                            dest.add_datetime(predicate, v);
-                        }
-                    } else if vdata.is_string() {
-                        if let Some(v) = vdata.as_str() {
-                            dest.add_datetime_from_str(predicate, v);
-                        }
+                } else if vdata.is_string() {
+                    if let Some(v) = vdata.as_str() {
+                        dest.add_datetime_from_str(predicate, v);
                     }
                 }
-                DataType::Decimal => {
-                    if vdata.is_f64() {
-                        if let Some(v) = vdata.as_f64() {
-                            dest.add_decimal_from_f64(predicate, v);
-                        }
-                    } else if vdata.is_number() {
-                        if let Some(v) = vdata.as_i64() {
-                            dest.add_decimal_from_i64(predicate, v);
-                        }
-                    } else if vdata.is_string() {
-                        if let Some(v) = vdata.as_str() {
-                            dest.add_decimal_from_str(predicate, v);
-                        }
+            }
+            DataType::Decimal => {
+                if vdata.is_f64() {
+                    if let Some(v) = vdata.as_f64() {
+                        dest.add_decimal_from_f64(predicate, v);
                     }
-                }
-                DataType::Boolean => {
-                    if let Some(v) = vdata.as_bool() {
-                        dest.add_bool(predicate, v);
+                } else if vdata.is_number() {
+                    if let Some(v) = vdata.as_i64() {
+                        dest.add_decimal_from_i64(predicate, v);
                     }
-                }
-                DataType::Binary => {
+                } else if vdata.is_string() {
                     if let Some(v) = vdata.as_str() {
-                        dest.add_binary(predicate, v.as_bytes().to_vec());
+                        dest.add_decimal_from_str(predicate, v);
                     }
                 }
             }
-        } else {
-            error!("json->individual: value for predicate [{}] must contain map", predicate);
-            res = false;
+            DataType::Boolean => {
+                if let Some(v) = vdata.as_bool() {
+                    dest.add_bool(predicate, v);
+                }
+            }
+            DataType::Binary => {
+                if let Some(v) = vdata.as_str() {
+                    dest.add_binary(predicate, v.as_bytes().to_vec());
+                }
+            }
         }
     }
 
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn untyped_values_are_rejected_by_default() {
+        let src = json!({"@": "d:a", "v-s:count": [1], "v-s:created": [{"data": "2020-01-02T03:04:05Z"}]});
+        let mut indv = Individual::default();
+        assert!(!parse_json_to_individual(&src, &mut indv));
+        assert_eq!(indv.get_first_integer("v-s:count"), None);
+        assert!(individuals_from_json(&src).is_err());
+
+        let typed = json!({"@": "d:a", "v-s:count": [{"type": "Integer", "data": 1}]});
+        let mut indv = Individual::default();
+        assert!(parse_json_to_individual(&typed, &mut indv));
+        assert_eq!(indv.get_first_integer("v-s:count"), Some(1));
+    }
+
+    #[test]
+    fn coercion_toggles_datetime_detection() {
+        let src = json!({"@": "d:a", "v-s:count": [1], "v-s:amount": [1.5], "v-s:created": ["2020-01-02T03:04:05Z"]});
+
+        let mut indv = Individual::default();
+        assert!(parse_json_to_individual_with_coercion(&src, &mut indv, &JsonCoercion::default()));
+        assert_eq!(indv.get_first_integer("v-s:count"), Some(1));
+        assert_eq!(indv.get_first_number("v-s:amount"), Some((15, -1)));
+        assert_eq!(indv.get_first_literal("v-s:created"), Some("2020-01-02T03:04:05Z".to_owned()));
+        assert_eq!(indv.get_first_datetime("v-s:created"), None);
+
+        let coercion = JsonCoercion {
+            detect_datetime: true,
+            ..JsonCoercion::default()
+        };
+        let mut indv = Individual::default();
+        assert!(parse_json_to_individual_with_coercion(&src, &mut indv, &coercion));
+        assert_eq!(indv.get_first_datetime("v-s:created"), Some(1_577_934_245));
+        assert_eq!(indv.get_first_literal("v-s:created"), None);
+    }
+
+    #[test]
+    fn numeric_datetime_in_millis() {
+        let src = json!({"@": "d:a", "v-s:created": [{"type": "Datetime", "data": 1_600_000_000_123_i64}]});
+        let mut indv = Individual::default();
+        assert!(parse_json_to_individual_with_unit(&src, &mut indv, EpochUnit::Millis));
+        assert_eq!(indv.get_first_datetime("v-s:created"), Some(1_600_000_000));
+    }
+
+    #[test]
+    fn individuals_of_object_and_array() {
+        let single = json!({"@": "d:a", "v-s:count": [{"type": "Integer", "data": 1}]});
+        let mut res = individuals_from_json(&single).unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].get_id(), "d:a");
+        assert_eq!(res[0].get_first_integer("v-s:count"), Some(1));
+
+        let valid = json!([
+            {"@": "d:a", "v-s:count": [{"type": "Integer", "data": 1}]},
+            {"@": "d:b", "rdf:type": [{"type": "Uri", "data": "v-s:Document"}]}
+        ]);
+        let ids: Vec<String> = individuals_from_json(&valid).unwrap().iter().map(|i| i.get_id().to_owned()).collect();
+        assert_eq!(ids, vec!["d:a", "d:b"]);
+
+        let mixed = json!([
+            {"@": "d:a", "v-s:count": [{"type": "Integer", "data": 1}]},
+            {"@": "d:b", "v-s:count": [{"type": "Unknown", "data": 1}]},
+            "d:c"
+        ]);
+        match individuals_from_json(&mixed) {
+            Err(ParseError::Invalid(e)) => assert!(e.contains("index 1"), "{}", e),
+            other => panic!("unexpected {:?}", other.map(|v| v.len())),
+        }
+        let not_object = json!([{"@": "d:a"}, 42]);
+        match individuals_from_json(&not_object) {
+            Err(ParseError::Invalid(e)) => assert!(e.contains("index 1"), "{}", e),
+            other => panic!("unexpected {:?}", other.map(|v| v.len())),
+        }
+        assert!(individuals_from_json(&json!("d:a")).is_err());
+        assert!(individuals_from_json(&json!([])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated_and_invalid_json_slices() {
+        let full = br#"{"@": "d:a", "v-s:count": [{"type": "Integer", "data": 1}]}"#;
+        let mut res = individuals_from_json_slice(full).unwrap();
+        assert_eq!(res[0].get_first_integer("v-s:count"), Some(1));
+
+        for end in [1, 10, full.len() - 1] {
+            assert!(matches!(individuals_from_json_slice(&full[..end]), Err(ParseError::Truncated)), "{}", end);
+        }
+        assert!(matches!(individuals_from_json_slice(br#"{"@": "d:a",, }"#), Err(ParseError::Invalid(_))));
+        assert!(matches!(individuals_from_json_slice(br#"{"@": "d:a"} }"#), Err(ParseError::Invalid(_))));
+        assert!(matches!(individuals_from_json_slice(b" \n"), Err(ParseError::Empty)));
+    }
+}
@@ -2,15 +2,29 @@
 extern crate log;
 
 pub mod cbor2individual;
+pub mod class_index;
+pub mod dataset_profiler;
 pub mod datatype;
+pub mod export_manifest;
 pub mod individual;
+pub mod individual2cbor;
 pub mod individual2json;
 pub mod individual2msgpack;
+pub mod individual2nquads;
+pub mod individual2rdfxml;
 pub mod individual2turtle;
+pub mod interner;
 pub mod json2individual;
 pub mod msgpack2individual;
 pub mod onto;
 pub mod onto_index;
 pub mod parser;
+pub mod prefix_map;
 pub mod resource;
-mod turtle_formatters_with_prefixes;
+pub mod sorted_reader;
+pub mod sparql_update_formatter;
+#[cfg(feature = "tar")]
+pub mod tar2individual;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod turtle_formatters_with_prefixes;
@@ -1,53 +1,122 @@
 extern crate rmp as msgpack;
 use crate::datatype::*;
 use crate::individual::*;
+use crate::interner::Interner;
 use crate::parser::*;
 use crate::resource::*;
 use msgpack::decode::*;
 use msgpack::Marker;
+use std::borrow::Cow;
 use std::io::Cursor;
 
-pub fn parse_msgpack(raw: &mut RawObj) -> Result<String, i8> {
-    if raw.data.is_empty() || raw.raw_type != RawType::Msgpack {
-        return Err(-1);
+const MSGPACK_NIL: u8 = 0xc0;
+const MSGPACK_BIN: [u8; 3] = [0xc4, 0xc5, 0xc6];
+
+pub fn parse_msgpack(raw: &mut RawObj) -> Result<String, ParseError> {
+    if raw.data.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    if raw.raw_type != RawType::Msgpack {
+        return Err(ParseError::Invalid("expected msgpack".to_owned()));
     }
 
     let mut cur = Cursor::new(raw.data.as_slice());
+    let uri = read_msgpack_uri(&mut cur)?;
+    raw.top_level_len = 2;
 
-    if let Ok(Marker::FixArray(size)) = read_marker(&mut cur) {
-        if size != 2 {
-            return Err(-1);
+    match read_map_len(&mut cur) {
+        Ok(size) => {
+            raw.len_predicates = size;
+            raw.cur = cur.position();
+            Ok(uri)
         }
-    } else {
-        return Err(-1);
+        Err(e) => Err(ParseError::Invalid(format!("msgpack: fail read predicates, err={:?}", e))),
     }
+}
 
-    // read individual URI
-    let uri = match read_string_from_msgpack(&mut cur) {
-        Ok(p) => p,
-        Err(_) => return Err(-1),
+/// The uri of msgpack data, the predicates are not read
+pub fn peek_msgpack_uri(data: &[u8]) -> Result<String, ParseError> {
+    peek_msgpack_uri_borrowed(data).map(Cow::into_owned)
+}
+
+/// The uri of msgpack data borrowed from it, it is copied only if it is not valid UTF-8
+pub fn peek_msgpack_uri_borrowed(data: &[u8]) -> Result<Cow<'_, str>, ParseError> {
+    if data.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    let mut cur = Cursor::new(data);
+    read_msgpack_header(&mut cur)?;
+
+    let m_pos = cur.position();
+    let size = match read_marker(&mut cur) {
+        Ok(Marker::Null) => return Ok(Cow::Borrowed("")),
+        Ok(Marker::FixStr(_)) | Ok(Marker::Str8) | Ok(Marker::Str16) | Ok(Marker::Str32) => {
+            cur.set_position(m_pos);
+            read_str_len(&mut cur).map_err(|e| ParseError::Invalid(format!("msgpack: fail read uri len, err={:?}", e)))?
+        }
+        _ => return Err(ParseError::Invalid("msgpack: fail read uri".to_owned())),
     };
 
-    if let Ok(size) = read_map_len(&mut cur) {
-        raw.len_predicates = size as u32;
-        raw.cur = cur.position();
-        Ok(uri)
-    } else {
-        Err(-1)
+    let start = cur.position() as usize;
+    match data[start..].get(..size as usize) {
+        Some(uri) => Ok(String::from_utf8_lossy(uri)),
+        None => Err(ParseError::Invalid(format!("msgpack: uri len={} is out of data", size))),
+    }
+}
+
+fn read_msgpack_header(cur: &mut Cursor<&[u8]>) -> Result<(), ParseError> {
+    match read_marker(cur) {
+        Ok(Marker::FixArray(2)) => Ok(()),
+        Ok(marker) => Err(ParseError::Invalid(format!("msgpack: unexpected header marker={:?}", marker))),
+        Err(e) => Err(ParseError::Invalid(format!("msgpack: fail read header, err={:?}", e))),
     }
 }
 
+fn read_msgpack_uri(cur: &mut Cursor<&[u8]>) -> Result<String, ParseError> {
+    read_msgpack_header(cur)?;
+
+    // read individual URI
+    match read_string_from_msgpack(cur) {
+        Ok(p) => Ok(p),
+        Err(_) => Err(ParseError::Invalid("msgpack: fail read uri".to_owned())),
+    }
+}
+
+/// A `nil` in the array of values is skipped, a `nil` instead of the array means the predicate is absent
 pub fn parse_msgpack_to_predicate(expect_predicate: &str, iraw: &mut Individual) -> Result<(), String> {
+    parse_msgpack_to_predicate_interned(expect_predicate, iraw, None)
+}
+
+/// Same as `parse_msgpack_to_predicate`, the names of the predicates are taken from the pool if it is given
+pub fn parse_msgpack_to_predicate_interned(expect_predicate: &str, iraw: &mut Individual, mut interner: Option<&mut Interner>) -> Result<(), String> {
     if iraw.raw.cur >= iraw.raw.data.len() as u64 {
         return Err("fail position of cursor".to_owned());
     }
 
     let mut is_found = false;
+    let bin_policy = iraw.raw.bin_policy;
     let mut cur = Cursor::new(iraw.raw.data.as_slice());
     cur.set_position(iraw.raw.cur);
 
     for i in iraw.raw.cur_predicates..iraw.raw.len_predicates {
-        let predicate = match read_string_from_msgpack(&mut cur) {
+        let (pooled, owned);
+        let read = match interner.as_deref_mut() {
+            Some(pool) => match read_str_bytes_from_msgpack(&mut cur) {
+                Ok(b) => {
+                    pooled = pool.intern_bytes(b);
+                    Ok(&*pooled)
+                }
+                Err(e) => Err(e),
+            },
+            None => match read_string_from_msgpack(&mut cur) {
+                Ok(p) => {
+                    owned = p;
+                    Ok(owned.as_str())
+                }
+                Err(e) => Err(e),
+            },
+        };
+        let predicate = match read {
             Ok(p) => p,
             Err(e) => {
                 iraw.raw.cur = cur.position();
@@ -59,13 +128,25 @@ pub fn parse_msgpack_to_predicate(expect_predicate: &str, iraw: &mut Individual)
             }
         };
 
-        iraw.raw.cur_predicates = i;
+        iraw.raw.cur_predicates = i + 1;
 
         if predicate == expect_predicate {
             is_found = true;
         }
 
-        match read_array_len(&mut cur) {
+        // nil instead of the array of values: the predicate is absent
+        let is_nil = iraw.raw.data.get(cur.position() as usize) == Some(&MSGPACK_NIL);
+        let values_len = if is_nil {
+            cur.set_position(cur.position() + 1);
+            Ok(0)
+        } else {
+            read_array_len(&mut cur)
+        };
+
+        match values_len {
+            Ok(0) if iraw.raw.keep_empty_predicates && !is_nil => {
+                iraw.obj.values_entry(predicate);
+            }
             Ok(size) => {
                 for _i_values in 0..size {
                     match read_marker(&mut cur) {
@@ -82,35 +163,49 @@ pub fn parse_msgpack_to_predicate(expect_predicate: &str, iraw: &mut Individual)
                                     return Err("parsing type".to_owned());
                                 }
 
+                                let is_bin = is_bin_at(&cur);
+
                                 if size == 2 {
-                                    if v_type == DataType::Boolean as u8 {
+                                    if is_bin && v_type == DataType::Binary as u8 {
+                                        // the tag settles the kind, the policy is not applied
+                                        if let Value::Binary(res) = read_bin_from_msgpack(&mut cur, MsgpackBinPolicy::Binary)? {
+                                            iraw.obj.add_binary(predicate, res);
+                                        }
+                                    } else if is_bin && v_type == DataType::String as u8 {
+                                        match read_bin_from_msgpack(&mut cur, bin_policy)? {
+                                            Value::Str(res, _) => iraw.obj.add_string(predicate, &res, Lang::NONE),
+                                            Value::Binary(res) => iraw.obj.add_binary(predicate, res),
+                                            _ => {}
+                                        }
+                                    } else if v_type == DataType::Boolean as u8 {
                                         match read_bool(&mut cur) {
-                                            Ok(res) => iraw.obj.add_bool(&predicate, res),
+                                            Ok(res) => iraw.obj.add_bool(predicate, res),
                                             Err(e) => return Err(format!("value: expected {}, err={:?}", v_type, e)),
                                         }
                                     } else if v_type == DataType::Datetime as u8 {
                                         match read_int(&mut cur) {
-                                            Ok(res) => iraw.obj.add_datetime(&predicate, res),
+                                            Ok(res) => iraw.obj.add_datetime(predicate, res),
                                             Err(e) => return Err(format!("value: expected {}, err={:?}", v_type, e)),
                                         }
                                     } else if v_type == DataType::Integer as u8 {
                                         match read_int(&mut cur) {
-                                            Ok(res) => iraw.obj.add_integer(&predicate, res),
+                                            Ok(res) => iraw.obj.add_integer(predicate, res),
                                             Err(e) => return Err(format!("value: expected {}, err={:?}", v_type, e)),
                                         }
                                     } else if v_type == DataType::Uri as u8 {
                                         match read_string_from_msgpack(&mut cur) {
-                                            Ok(res) => iraw.obj.add_uri(&predicate, &res),
+                                            Ok(res) => iraw.obj.add_uri(predicate, &res),
                                             Err(e) => return Err(format!("value: expected {}, err={:?}", v_type, e)),
                                         }
                                     } else if v_type == DataType::Binary as u8 {
-                                        let values = iraw.obj.resources.entry(predicate.to_owned()).or_default();
-                                        if !read_raw_into_resources(&mut cur, values) {
+                                        let mut values = vec![];
+                                        if !read_raw_into_resources(&mut cur, &mut values) {
                                             return Err("value: fail read raw".to_owned());
                                         }
+                                        iraw.obj.values_entry(predicate).append(&mut values);
                                     } else if v_type == DataType::String as u8 {
                                         match read_string_from_msgpack(&mut cur) {
-                                            Ok(res) => iraw.obj.add_string(&predicate, &res, Lang::NONE),
+                                            Ok(res) => iraw.obj.add_string(predicate, &res, Lang::NONE),
                                             Err(e) => return Err(format!("value: expected {}, err={:?}", v_type, e)),
                                         };
                                     } else {
@@ -120,13 +215,24 @@ pub fn parse_msgpack_to_predicate(expect_predicate: &str, iraw: &mut Individual)
                                     if v_type == DataType::Decimal as u8 {
                                         match read_int(&mut cur) {
                                             Ok(mantissa) => match read_int(&mut cur) {
-                                                Ok(exponent) => iraw.obj.add_decimal_d(&predicate, mantissa, exponent),
+                                                Ok(exponent) => iraw.obj.add_decimal_d(predicate, mantissa, exponent),
                                                 Err(e) => {
                                                     return Err(format!("value: fail read exponent, err={:?}", e));
                                                 }
                                             },
                                             Err(e) => return Err(format!("value: fail read mantissa, err={:?}", e)),
                                         }
+                                    } else if is_bin && v_type == DataType::String as u8 {
+                                        let value = read_bin_from_msgpack(&mut cur, bin_policy)?;
+                                        let lang = match read_int(&mut cur) {
+                                            Ok(res) => Lang::new_from_i64(res),
+                                            Err(e) => return Err(format!("value: fail read lang, err={:?}", e)),
+                                        };
+                                        match value {
+                                            Value::Str(res, _) => iraw.obj.add_string(predicate, &res, lang),
+                                            Value::Binary(res) => iraw.obj.add_binary(predicate, res),
+                                            _ => {}
+                                        }
                                     } else if v_type == DataType::String as u8 {
                                         match read_string_from_msgpack(&mut cur) {
                                             Ok(res) => {
@@ -138,13 +244,15 @@ pub fn parse_msgpack_to_predicate(expect_predicate: &str, iraw: &mut Individual)
                                                     }
                                                 }
 
-                                                iraw.obj.add_string(&predicate, &res, lang);
+                                                iraw.obj.add_string(predicate, &res, lang);
                                             }
                                             Err(e) => return Err(format!("value: expected {}, err={:?}", v_type, e)),
                                         }
                                     }
                                 }
                             }
+                            // nil in the array of values is skipped
+                            Marker::Null => {}
                             marker => return Err(format!("parsing values: unexpected marker={:?}", marker)),
                         },
                         Err(e) => return Err(format!("parsing values: err={:?}", e)),
@@ -160,10 +268,51 @@ pub fn parse_msgpack_to_predicate(expect_predicate: &str, iraw: &mut Individual)
         }
     }
 
-    iraw.raw.cur = cur.position();
+    // all predicates are read, the rest of the buffer is not a part of the individual
+    iraw.raw.cur = iraw.raw.data.len() as u64;
     Ok(())
 }
 
+/// The next predicate of the raw data, the cursor is not moved
+pub(crate) fn peek_msgpack_predicate(raw: &RawObj) -> Option<String> {
+    if raw.cur_predicates >= raw.len_predicates {
+        return None;
+    }
+    let mut cur = Cursor::new(raw.data.as_slice());
+    cur.set_position(raw.cur);
+    read_string_from_msgpack(&mut cur).ok()
+}
+
+fn is_enough_data(cur: &Cursor<&[u8]>, size: u32) -> bool {
+    let rest = (cur.get_ref().len() as u64).saturating_sub(cur.position());
+    u64::from(size) <= rest
+}
+
+fn is_bin_at(cur: &Cursor<&[u8]>) -> bool {
+    cur.get_ref().get(cur.position() as usize).is_some_and(|m| MSGPACK_BIN.contains(m))
+}
+
+/// Reads a msgpack `bin`, its bytes are a string or a binary by the policy
+fn read_bin_from_msgpack(cur: &mut Cursor<&[u8]>, policy: MsgpackBinPolicy) -> Result<Value, String> {
+    let size = read_bin_len(cur).map_err(|e| format!("value: fail read bin len, err={:?}", e))?;
+    if !is_enough_data(cur, size) {
+        return Err(format!("value: bin len={} is out of data", size));
+    }
+
+    let start = cur.position() as usize;
+    let buf = &cur.get_ref()[start..start + size as usize];
+    cur.set_position(cur.position() + u64::from(size));
+
+    Ok(match policy {
+        MsgpackBinPolicy::Binary => Value::Binary(buf.to_vec()),
+        MsgpackBinPolicy::TryUtf8 => match std::str::from_utf8(buf) {
+            Ok(s) => Value::Str(s.to_owned(), Lang::NONE),
+            Err(_) => Value::Binary(buf.to_vec()),
+        },
+        MsgpackBinPolicy::LossyString => Value::Str(String::from_utf8_lossy(buf).into_owned(), Lang::NONE),
+    })
+}
+
 fn read_raw_into_resources(cur: &mut Cursor<&[u8]>, values: &mut Vec<Resource>) -> bool {
     let m_pos = cur.position();
     let size: u32;
@@ -192,6 +341,10 @@ fn read_raw_into_resources(cur: &mut Cursor<&[u8]>, values: &mut Vec<Resource>)
         return false;
     }
 
+    if !is_enough_data(cur, size) {
+        return false;
+    }
+
     let mut out = vec![0u8; size as usize];
 
     match read_str(cur, &mut out) {
@@ -199,6 +352,7 @@ fn read_raw_into_resources(cur: &mut Cursor<&[u8]>, values: &mut Vec<Resource>)
             values.push(Resource {
                 rtype: DataType::Binary,
                 order: 0,
+                source: 0,
                 value: Value::Binary(v.as_bytes().to_vec()),
             });
             true
@@ -208,6 +362,7 @@ fn read_raw_into_resources(cur: &mut Cursor<&[u8]>, values: &mut Vec<Resource>)
                 values.push(Resource {
                     rtype: DataType::Binary,
                     order: 0,
+                    source: 0,
                     value: Value::Binary(buf.to_vec()),
                 });
                 return true;
@@ -217,6 +372,42 @@ fn read_raw_into_resources(cur: &mut Cursor<&[u8]>, values: &mut Vec<Resource>)
     }
 }
 
+/// The bytes of the string borrowed from the data, `nil` is an empty string. Errors as of `read_string_from_msgpack`
+fn read_str_bytes_from_msgpack<'a>(cur: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], i64> {
+    let m_pos = cur.position();
+    let size = match read_marker(cur) {
+        Ok(Marker::Null) => return Ok(&[]),
+        Ok(Marker::FixStr(_)) | Ok(Marker::Str8) | Ok(Marker::Str16) | Ok(Marker::Str32) => {
+            cur.set_position(m_pos);
+            match read_str_len(cur) {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("fail read str len , err={:?}", e);
+                    return Err(-1);
+                }
+            }
+        }
+        Ok(marker) => {
+            error!("marker={:?}", marker);
+            return Err(-1);
+        }
+        Err(_) => return Err(-2),
+    };
+
+    let data: &'a [u8] = cur.get_ref();
+    let start = cur.position() as usize;
+    match data.get(start..).and_then(|d| d.get(..size as usize)) {
+        Some(b) => {
+            cur.set_position((start + b.len()) as u64);
+            Ok(b)
+        }
+        None => {
+            error!("fail read str, len={} is out of data", size);
+            Err(-1)
+        }
+    }
+}
+
 fn read_string_from_msgpack(cur: &mut Cursor<&[u8]>) -> Result<String, i64> {
     let m_pos = cur.position();
     let size: u32;
@@ -251,6 +442,11 @@ fn read_string_from_msgpack(cur: &mut Cursor<&[u8]>) -> Result<String, i64> {
         return Err(-2);
     }
 
+    if !is_enough_data(cur, size) {
+        error!("fail read str, len={} is out of data", size);
+        return Err(-1);
+    }
+
     let mut out = vec![0u8; size as usize];
     match read_str(cur, &mut out) {
         Ok(v) => Ok(v.to_string()),
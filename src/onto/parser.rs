@@ -1,8 +1,14 @@
 use crate::cbor2individual::*;
 use crate::individual::*;
+use crate::interner::Interner;
 use crate::msgpack2individual::*;
+use crate::resource::Resource;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum RawType {
     Cbor,
     Json,
@@ -10,49 +16,899 @@ pub enum RawType {
     Unknown,
 }
 
-pub fn parse_to_predicate(expect_predicate: &str, iraw: &mut Individual) -> bool {
-    if iraw.raw.raw_type == RawType::Msgpack {
-        if let Err(e) = parse_msgpack_to_predicate(expect_predicate, iraw) {
-            if !e.is_empty() {
-                error!("parse for [{}], err={}", expect_predicate, e);
-            }
-            return false;
+/// Marker of the encoding of the raw data: msgpack is an array [uri, predicates],
+/// cbor is a map of predicates with the uri under `@`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct FormatMarker {
+    pub raw_type: RawType,
+    pub arity: u32,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ParseError {
+    /// the raw buffer is empty
+    Empty,
+    /// the raw buffer is not a well-formed individual
+    Invalid(String),
+    /// the values are nested deeper than the limit
+    DepthExceeded(usize),
+    /// the data is well-formed so far but ends in the middle of a value, more input may complete it
+    Truncated,
+}
+
+/// The default limit of nested values, see `RawObj::max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// Handling of a cbor value with a tag the parser does not know
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum UnknownTagPolicy {
+    /// the parsing fails with the tag number
+    #[default]
+    Strict,
+    /// the tagged value is skipped
+    SkipValue,
+    /// a text, an integer or a boolean under the tag is added as a string
+    AsString,
+}
+
+/// Interpretation of a msgpack `bin` of a value tagged as a string, the encoders of different generations
+/// wrote the strings and the binaries with either `str` or `bin`. A `bin` tagged as a binary is always a binary.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub enum MsgpackBinPolicy {
+    /// the bytes are added as a binary
+    Binary,
+    /// valid UTF-8 is added as a string, the rest as a binary
+    #[default]
+    TryUtf8,
+    /// the bytes are added as a string, invalid sequences are replaced by U+FFFD
+    LossyString,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty raw data"),
+            ParseError::Invalid(e) => write!(f, "invalid raw data: {}", e),
+            ParseError::DepthExceeded(max) => write!(f, "values are nested deeper than {}", max),
+            ParseError::Truncated => write!(f, "truncated data"),
         }
-        return true;
-    } else if iraw.raw.raw_type == RawType::Cbor {
-        return parse_cbor_to_predicate(expect_predicate, iraw);
     }
+}
+
+/// Parses the predicates up to the expected one, `false` if it is not found or the data is malformed.
+/// The parse errors are logged only with the feature `log-parse-errors`, use `try_parse_to_predicate` to get them.
+pub fn parse_to_predicate(expect_predicate: &str, iraw: &mut Individual) -> bool {
+    let res = try_parse_to_predicate(expect_predicate, iraw);
+    #[cfg(feature = "log-parse-errors")]
+    if let Err(e) = &res {
+        error!("parse for [{}], err={}", expect_predicate, e);
+    }
+    res.unwrap_or(false)
+}
 
-    false
+/// Parses the predicates up to the expected one, `Ok(false)` if there is nothing more to parse
+pub fn try_parse_to_predicate(expect_predicate: &str, iraw: &mut Individual) -> Result<bool, ParseError> {
+    match iraw.raw.raw_type {
+        RawType::Msgpack => match parse_msgpack_to_predicate(expect_predicate, iraw) {
+            Ok(()) => Ok(true),
+            Err(e) if e.is_empty() => Ok(false),
+            Err(e) => Err(ParseError::Invalid(e)),
+        },
+        RawType::Cbor => parse_cbor_to_predicate(expect_predicate, iraw),
+        _ => Ok(false),
+    }
 }
 
 const MSGPACK_MAGIC_HEADER: u8 = 146;
 
-pub fn parse_raw(iraw: &mut Individual) -> Result<(), i8> {
-    if iraw.raw.data.is_empty() {
-        return Err(-1);
+pub fn detect_raw_type(data: &[u8]) -> RawType {
+    match data.first() {
+        Some(&MSGPACK_MAGIC_HEADER) => RawType::Msgpack,
+        Some(_) => RawType::Cbor,
+        None => RawType::Unknown,
     }
+}
 
-    let traw: &[u8] = iraw.raw.data.as_slice();
+/// The uri of raw msgpack, cbor or json data, decoded without the predicates
+pub fn peek_uri(data: &[u8]) -> Result<String, ParseError> {
+    peek_uri_borrowed(data).map(Cow::into_owned)
+}
 
-    if traw[0] == MSGPACK_MAGIC_HEADER {
-        iraw.raw.raw_type = RawType::Msgpack;
-    } else {
-        iraw.raw.raw_type = RawType::Cbor;
+/// The uri of raw msgpack, cbor or json data borrowed from it, it is copied only if it is a msgpack string
+/// which is not valid UTF-8 or a json string with escapes, a cbor uri which is not valid UTF-8 is an error. The data is only read, so it may be a memory-mapped file.
+pub fn peek_uri_borrowed(data: &[u8]) -> Result<Cow<'_, str>, ParseError> {
+    #[derive(Deserialize)]
+    struct JsonSubject<'a> {
+        #[serde(rename = "@", borrow)]
+        uri: Cow<'a, str>,
     }
 
-    let res = if iraw.raw.raw_type == RawType::Msgpack {
-        parse_msgpack(&mut iraw.raw)
-    } else if iraw.raw.raw_type == RawType::Cbor {
-        parse_cbor(&mut iraw.raw)
-    } else {
-        Err(-1)
+    match data.iter().find(|b| !b.is_ascii_whitespace()) {
+        None => Err(ParseError::Empty),
+        Some(b'{') => match serde_json::from_slice::<JsonSubject>(data) {
+            Ok(s) => Ok(s.uri),
+            Err(e) if e.is_eof() => Err(ParseError::Truncated),
+            Err(e) => Err(ParseError::Invalid(format!("json: fail read uri, err={}", e))),
+        },
+        Some(_) => match detect_raw_type(data) {
+            RawType::Msgpack => peek_msgpack_uri_borrowed(data),
+            _ => peek_cbor_uri_borrowed(data),
+        },
+    }
+}
+
+/// Reads the header of the raw data, the predicates are parsed lazily.
+/// Malformed data of any kind is reported as `ParseError`, never as a panic.
+pub fn parse_raw(iraw: &mut Individual) -> Result<(), ParseError> {
+    iraw.raw.raw_type = detect_raw_type(&iraw.raw.data);
+
+    let uri = match iraw.raw.raw_type {
+        RawType::Msgpack => parse_msgpack(&mut iraw.raw)?,
+        RawType::Cbor => parse_cbor(&mut iraw.raw)?,
+        _ => return Err(ParseError::Empty),
     };
 
-    if let Ok(uri) = res {
-        iraw.obj.uri = uri;
-        return Ok(());
+    iraw.obj.uri = uri;
+    // the count of predicates comes from the data, a predicate takes at least one byte
+    let hint = (iraw.raw.len_predicates as usize).min(iraw.raw.data.len());
+    iraw.obj.resources.reserve(hint.saturating_sub(iraw.obj.resources.len()));
+    Ok(())
+}
+
+/// Reads the raw data and parses all predicates, the names of the msgpack predicates are taken from the pool:
+/// parsing a batch of individuals with one pool decodes every predicate name once. The result equals `parse_raw`
+/// followed by `try_parse_all`.
+pub fn parse_raw_interned(iraw: &mut Individual, interner: &mut Interner) -> Result<(), ParseError> {
+    parse_raw(iraw)?;
+    if iraw.raw.raw_type != RawType::Msgpack {
+        return iraw.try_parse_all();
+    }
+
+    while iraw.raw.cur < iraw.raw.data.len() as u64 {
+        match parse_msgpack_to_predicate_interned("?", iraw, Some(interner)) {
+            Ok(()) => {}
+            Err(e) if e.is_empty() => break,
+            Err(e) => return Err(ParseError::Invalid(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the raw data and parses all predicates, the visitor is called for every predicate as soon as it is decoded,
+/// with the values decoded from it. A predicate repeated in the data visits every time with its new values.
+pub fn parse_raw_with_visitor(iraw: &mut Individual, visitor: &mut dyn FnMut(&str, &[Resource])) -> Result<(), ParseError> {
+    parse_raw(iraw)?;
+
+    loop {
+        let next = match iraw.raw.raw_type {
+            RawType::Msgpack => peek_msgpack_predicate(&iraw.raw),
+            RawType::Cbor => peek_cbor_predicate(&iraw.raw),
+            _ => None,
+        };
+        // the end of the data, or malformed data which is reported by the regular parsing
+        let predicate = match next {
+            Some(p) => p,
+            None => return iraw.try_parse_all(),
+        };
+
+        let before = iraw.value_count(&predicate);
+        let pos = iraw.raw.cur;
+        try_parse_to_predicate(&predicate, iraw)?;
+        let values = iraw.obj.resources.get(&predicate).map_or(&[][..], |v| &v[before.min(v.len())..]);
+        visitor(&predicate, values);
+
+        if iraw.raw.cur <= pos {
+            return iraw.try_parse_all();
+        }
+    }
+}
+
+/// Parses an individual framed by the 4-byte big-endian length of its raw data, all predicates are parsed.
+/// Returns the individual and the count of consumed bytes, the length prefix included.
+pub fn parse_framed(data: &[u8]) -> Result<(Individual, usize), ParseError> {
+    if data.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    if data.len() < 4 {
+        return Err(ParseError::Invalid(format!("frame: fail read length, available={}", data.len())));
+    }
+
+    let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    match data[4..].get(..len) {
+        Some(payload) => Ok((Individual::try_from(payload)?, 4 + len)),
+        None => Err(ParseError::Invalid(format!("frame: length={} is out of data, available={}", len, data.len() - 4))),
+    }
+}
+
+/// How the values of a predicate present in both individuals are merged
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ValuePolicy {
+    /// the values of the source are added after the values of the target
+    Append,
+    /// union of the values, the values of the source already present in the target are skipped
+    KeepBoth,
+    /// the values of the target are kept
+    PreferSelf,
+    /// the values of the source replace the values of the target
+    PreferOther,
+}
+
+/// What to do when the uri of the source differs from the uri of the target
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum UriPolicy {
+    KeepTarget,
+    TakeSource,
+    Reject,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct MergePolicy {
+    pub values: ValuePolicy,
+    pub uri: UriPolicy,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy {
+            values: ValuePolicy::Append,
+            uri: UriPolicy::Reject,
+        }
+    }
+}
+
+/// How `Individual::redact` replaces the values of the listed predicates
+#[derive(PartialEq, Debug, Clone)]
+pub enum RedactMode {
+    /// the predicate is removed
+    Remove,
+    /// every value is replaced by an empty string
+    Blank,
+    /// every value is replaced by the hex string of HMAC-SHA256 of the value with the salt as the key,
+    /// the salt must be kept secret: a low-entropy value can be recovered by brute force with the known salt
+    Hash(String),
+}
+
+/// Parses the raw data and merges its predicates into the target individual.
+/// An empty uri of the target is always taken from the source. Corrupt data is an error, nothing is merged.
+pub fn parse_raw_into(data: &[u8], target: &mut Individual, on_conflict: MergePolicy) -> Result<(), ParseError> {
+    let mut src = Individual::new_raw(RawObj::new(data.to_vec()));
+    parse_raw(&mut src)?;
+    src.try_parse_all()?;
+
+    if target.merge(&src, on_conflict) {
+        Ok(())
+    } else {
+        Err(ParseError::Invalid(format!("uri [{}] does not match target [{}]", src.obj.uri, target.obj.uri)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatype::DataType;
+    use crate::individual2msgpack::to_msgpack;
+
+    #[test]
+    fn parse_raw_into_rejects_truncated_msgpack() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_integer("v-s:count", 42);
+        src.add_uri("rdf:type", "v-s:Document");
+        let mut data = vec![];
+        to_msgpack(&src, &mut data).unwrap();
+        data.truncate(data.len() - 3);
+
+        let mut target = Individual::default();
+        target.set_id("d:a");
+        assert!(parse_raw_into(&data, &mut target, MergePolicy::default()).is_err());
+        assert!(target.get_obj().resources.is_empty());
+    }
+
+    #[test]
+    fn parse_raw_into_rejects_truncated_cbor() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_integer("v-s:count", 42);
+        src.add_uri("rdf:type", "v-s:Document");
+        src.add_decimal_d("v-s:amount", 15, -1);
+        let mut data = vec![];
+        crate::individual2cbor::to_cbor(&src, &mut data).unwrap();
+
+        for end in 0..data.len() {
+            let mut target = Individual::default();
+            target.set_id("d:a");
+            assert!(parse_raw_into(&data[..end], &mut target, MergePolicy::default()).is_err(), "len={}", end);
+            assert!(target.get_obj().resources.is_empty());
+        }
+        let mut target = Individual::default();
+        target.set_id("d:a");
+        parse_raw_into(&data, &mut target, MergePolicy::default()).unwrap();
+        assert_eq!(target.get_first_integer("v-s:count"), Some(42));
+    }
+
+    #[test]
+    fn truncated_cbor_is_an_error_at_every_length() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_uri("rdf:type", "v-s:Document");
+        src.add_string("rdfs:label", "label", crate::datatype::Lang::EN);
+        src.add_integer("v-s:count", 42);
+        src.add_integer("v-s:count", -70_000);
+        src.add_datetime("v-s:created", 1_600_000_000);
+        src.add_decimal_d("v-s:amount", -150, -2);
+        src.add_bool("v-s:deleted", true);
+        let mut data = vec![];
+        crate::individual2cbor::to_cbor(&src, &mut data).unwrap();
+        assert!(Individual::try_from(data.as_slice()).is_ok());
+
+        for end in 0..data.len() {
+            assert!(Individual::try_from(&data[..end]).is_err(), "len={}", end);
+        }
+    }
+
+    #[test]
+    fn msgpack_nil_among_values_is_skipped() {
+        use rmp::encode::*;
+
+        // ["d:a", { "v-s:count": [[Integer, 1], nil, [Integer, 2]], "v-s:gone": nil }]
+        let mut data = vec![];
+        write_array_len(&mut data, 2).unwrap();
+        write_str(&mut data, "d:a").unwrap();
+        write_map_len(&mut data, 2).unwrap();
+        write_str(&mut data, "v-s:count").unwrap();
+        write_array_len(&mut data, 3).unwrap();
+        for value in [Some(1), None, Some(2)] {
+            match value {
+                Some(i) => {
+                    write_array_len(&mut data, 2).unwrap();
+                    write_u8(&mut data, DataType::Integer as u8).unwrap();
+                    write_sint(&mut data, i).unwrap();
+                }
+                None => write_nil(&mut data).unwrap(),
+            }
+        }
+        write_str(&mut data, "v-s:gone").unwrap();
+        write_nil(&mut data).unwrap();
+
+        let mut indv = Individual::new_raw(RawObj::new(data));
+        parse_raw(&mut indv).unwrap();
+        indv.try_parse_all().unwrap();
+        assert_eq!(indv.get_id(), "d:a");
+        let counts: Vec<i64> = indv.get_resources("v-s:count").unwrap().iter().map(|r| r.get_int()).collect();
+        assert_eq!(counts, vec![1, 2]);
+        assert!(!indv.get_obj().get_resources().contains_key("v-s:gone"));
+    }
+
+    #[test]
+    fn msgpack_bin_of_valid_and_invalid_utf8() {
+        use rmp::encode::*;
+
+        // ["d:a", { "v-s:text": [[String, bin "текст", EN]], "v-s:data": [[String, bin ff fe]] }]
+        let mut data = vec![];
+        write_array_len(&mut data, 2).unwrap();
+        write_str(&mut data, "d:a").unwrap();
+        write_map_len(&mut data, 2).unwrap();
+        write_str(&mut data, "v-s:text").unwrap();
+        write_array_len(&mut data, 1).unwrap();
+        write_array_len(&mut data, 3).unwrap();
+        write_u8(&mut data, DataType::String as u8).unwrap();
+        write_bin(&mut data, "текст".as_bytes()).unwrap();
+        write_u8(&mut data, 2).unwrap();
+        write_str(&mut data, "v-s:data").unwrap();
+        write_array_len(&mut data, 1).unwrap();
+        write_array_len(&mut data, 2).unwrap();
+        write_u8(&mut data, DataType::String as u8).unwrap();
+        write_bin(&mut data, &[0xff, 0xfe]).unwrap();
+
+        let parse = |policy: MsgpackBinPolicy| {
+            let mut raw = RawObj::new(data.clone());
+            raw.bin_policy = policy;
+            let mut indv = Individual::new_raw(raw);
+            parse_raw(&mut indv).unwrap();
+            indv.try_parse_all().unwrap();
+            indv
+        };
+        let first = |indv: &Individual, predicate: &str| indv.values(predicate).next().unwrap().get_copy();
+
+        // the default policy tries UTF-8 and falls back to binary
+        assert_eq!(RawObj::new(vec![]).bin_policy, MsgpackBinPolicy::TryUtf8);
+        let indv = parse(MsgpackBinPolicy::TryUtf8);
+        let text = first(&indv, "v-s:text");
+        assert_eq!(text.rtype, DataType::String);
+        assert_eq!(text.get_str(), "текст");
+        assert_eq!(text.get_lang(), crate::datatype::Lang::EN);
+        let bytes = first(&indv, "v-s:data");
+        assert_eq!(bytes.rtype, DataType::Binary);
+        assert_eq!(bytes.get_binary(), &[0xff, 0xfe]);
+
+        let indv = parse(MsgpackBinPolicy::Binary);
+        assert_eq!(first(&indv, "v-s:text").get_binary(), "текст".as_bytes());
+        let indv = parse(MsgpackBinPolicy::LossyString);
+        assert_eq!(first(&indv, "v-s:data").get_str(), "\u{fffd}\u{fffd}");
+    }
+
+    #[test]
+    fn msgpack_bin_tagged_as_binary_under_every_policy() {
+        use rmp::encode::*;
+
+        // ["d:a", { "v-s:data": [[Binary, bin "hello"]] }]
+        let mut data = vec![];
+        write_array_len(&mut data, 2).unwrap();
+        write_str(&mut data, "d:a").unwrap();
+        write_map_len(&mut data, 1).unwrap();
+        write_str(&mut data, "v-s:data").unwrap();
+        write_array_len(&mut data, 1).unwrap();
+        write_array_len(&mut data, 2).unwrap();
+        write_u8(&mut data, DataType::Binary as u8).unwrap();
+        write_bin(&mut data, b"hello").unwrap();
+
+        for policy in [MsgpackBinPolicy::Binary, MsgpackBinPolicy::TryUtf8, MsgpackBinPolicy::LossyString] {
+            let mut raw = RawObj::new(data.clone());
+            raw.bin_policy = policy;
+            let mut indv = Individual::new_raw(raw);
+            parse_raw(&mut indv).unwrap();
+            indv.try_parse_all().unwrap();
+            let values: Vec<(DataType, Vec<u8>)> = indv.values("v-s:data").map(|r| (r.rtype.clone(), r.get_binary().to_vec())).collect();
+            assert_eq!(values, vec![(DataType::Binary, b"hello".to_vec())], "{:?}", policy);
+        }
+    }
+
+    /// cbor text of up to 23 bytes
+    fn cbor_text(s: &str) -> Vec<u8> {
+        let mut res = vec![0x60 | s.len() as u8];
+        res.extend_from_slice(s.as_bytes());
+        res
+    }
+
+    #[test]
+    fn cbor_indefinite_length_maps_and_arrays() {
+        let parse = |data: Vec<u8>| {
+            let mut indv = Individual::new_raw(RawObj::new(data));
+            parse_raw(&mut indv)?;
+            indv.try_parse_all()?;
+            Ok::<_, ParseError>(indv)
+        };
+        let counts = |indv: &mut Individual| indv.get_resources("v-s:count").map(|v| v.iter().map(|r| r.get_int()).collect::<Vec<i64>>());
+
+        // {_ "@": "d:a", "v-s:count": [_ 1, 2], "rdf:type": [32("v-s:Document")] }
+        let mut data = vec![0xbf];
+        data.extend(cbor_text("@"));
+        data.extend(cbor_text("d:a"));
+        data.extend(cbor_text("v-s:count"));
+        data.extend([0x9f, 0x01, 0x02, 0xff]);
+        data.extend(cbor_text("rdf:type"));
+        data.extend([0x81, 0xd8, 32]);
+        data.extend(cbor_text("v-s:Document"));
+        data.push(0xff);
+        let mut indv = parse(data.clone()).unwrap();
+        assert_eq!(indv.get_id(), "d:a");
+        assert_eq!(counts(&mut indv), Some(vec![1, 2]));
+        assert_eq!(indv.get_first_literal("rdf:type"), Some("v-s:Document".to_owned()));
+
+        // without the break stop code of the map
+        data.pop();
+        assert!(matches!(parse(data), Err(ParseError::Invalid(_))));
+
+        // { "@": "d:a", "v-s:count": [_ 1, [_ 2], 3], "v-s:empty": [_ ] }
+        let mut data = vec![0xa3];
+        data.extend(cbor_text("@"));
+        data.extend(cbor_text("d:a"));
+        data.extend(cbor_text("v-s:count"));
+        data.extend([0x9f, 0x01, 0x9f, 0x02, 0xff, 0x03, 0xff]);
+        data.extend(cbor_text("v-s:empty"));
+        data.extend([0x9f, 0xff]);
+        let mut indv = parse(data).unwrap();
+        assert_eq!(counts(&mut indv), Some(vec![1, 2, 3]));
+        assert!(indv.get_resources("v-s:empty").is_none());
+    }
+
+    #[test]
+    fn cbor_nesting_deeper_than_max_depth() {
+        // { "@": "d:a", "v-s:count": [[[[1]]]] }, the array of values is on the level 0, the integer on the level 4
+        let mut data = vec![0xa2];
+        data.extend(cbor_text("@"));
+        data.extend(cbor_text("d:a"));
+        data.extend(cbor_text("v-s:count"));
+        data.extend([0x81, 0x81, 0x81, 0x81, 0x01]);
+        let parse = |max_depth: usize| {
+            let mut raw = RawObj::new(data.clone());
+            raw.max_depth = max_depth;
+            let mut indv = Individual::new_raw(raw);
+            parse_raw(&mut indv)?;
+            indv.try_parse_all().map(|_| indv)
+        };
+
+        assert!(matches!(parse(3), Err(ParseError::DepthExceeded(3))));
+        let mut indv = parse(4).unwrap();
+        assert_eq!(indv.get_first_integer("v-s:count"), Some(1));
+        assert!(parse(DEFAULT_MAX_DEPTH).is_ok());
+    }
+
+    #[test]
+    fn empty_value_array_dropped_or_kept() {
+        // { "@": "d:a", "v-s:tags": [], "v-s:count": [1] } in both formats, the cbor one with an indefinite empty array too
+        let mut cbor = vec![0xa3];
+        for item in ["@", "d:a", "v-s:tags"].iter() {
+            cbor.extend(cbor_text(item));
+        }
+        cbor.push(0x80);
+        cbor.extend(cbor_text("v-s:count"));
+        cbor.extend([0x81, 0x01]);
+        let mut cbor_indefinite = cbor.clone();
+        cbor_indefinite.splice(16..17, [0x9f, 0xff].iter().cloned());
+        let mut msgpack = vec![0x92, 0xa3, b'd', b':', b'a', 0x82, 0xa8];
+        msgpack.extend_from_slice(b"v-s:tags");
+        msgpack.extend([0x90, 0xa9]);
+        msgpack.extend_from_slice(b"v-s:count");
+        msgpack.extend([0x91, 0x92, DataType::Integer as u8, 0x01]);
+
+        let parse = |data: &[u8], keep_empty_predicates: bool| {
+            let mut raw = RawObj::new(data.to_vec());
+            raw.keep_empty_predicates = keep_empty_predicates;
+            let mut indv = Individual::new_raw(raw);
+            parse_raw(&mut indv).unwrap();
+            indv.try_parse_all().unwrap();
+            indv
+        };
+
+        for data in [&cbor, &cbor_indefinite, &msgpack].iter() {
+            let mut dropped = parse(data, false);
+            assert!(!dropped.get_obj().get_resources().contains_key("v-s:tags"));
+            assert_eq!(dropped.get_first_integer("v-s:count"), Some(1));
+
+            let mut kept = parse(data, true);
+            assert_eq!(kept.get_obj().get_resources().get("v-s:tags").map(|v| v.len()), Some(0));
+            assert_eq!(kept.get_first_integer("v-s:count"), Some(1));
+
+            // the kept predicate is written as an empty array and survives the round-trip
+            let mut out = vec![];
+            to_msgpack(&kept, &mut out).unwrap();
+            assert!(parse(&out, true).get_obj().get_resources().contains_key("v-s:tags"));
+            let mut out = vec![];
+            crate::individual2cbor::to_cbor(&kept, &mut out).unwrap();
+            assert!(parse(&out, true).get_obj().get_resources().contains_key("v-s:tags"));
+        }
+    }
+
+    #[test]
+    fn visitor_sees_every_parsed_predicate() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_uri("rdf:type", "v-s:Document");
+        src.add_integer("v-s:count", 1);
+        src.add_integer("v-s:count", 2);
+        src.add_string("rdfs:label", "a", crate::datatype::Lang::EN);
+        let mut msgpack = vec![];
+        to_msgpack(&src, &mut msgpack).unwrap();
+        let mut cbor = vec![];
+        crate::individual2cbor::to_cbor(&src, &mut cbor).unwrap();
+
+        for data in [msgpack, cbor] {
+            let mut visited: std::collections::HashMap<String, Vec<Resource>> = Default::default();
+            let mut indv = Individual::new_raw(RawObj::new(data));
+            parse_raw_with_visitor(&mut indv, &mut |predicate, values| {
+                visited.entry(predicate.to_owned()).or_default().extend(values.iter().map(Resource::get_copy));
+            })
+            .unwrap();
+            assert_eq!(&visited, indv.get_obj().get_resources());
+            assert_eq!(indv.get_obj().get_resources(), src.get_obj().get_resources());
+        }
+    }
+
+    #[test]
+    fn peek_uri_of_every_format() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_integer("v-s:count", 1);
+        let mut msgpack = vec![];
+        to_msgpack(&src, &mut msgpack).unwrap();
+        let mut cbor = vec![];
+        crate::individual2cbor::to_cbor(&src, &mut cbor).unwrap();
+        let json = br#" {"v-s:count": [{"type": "Integer", "data": 1}], "@": "d:a"}"#.to_vec();
+
+        for data in [msgpack, cbor, json] {
+            assert_eq!(peek_uri(&data).unwrap(), "d:a");
+            assert!(matches!(peek_uri_borrowed(&data), Ok(Cow::Borrowed("d:a"))));
+        }
+        assert!(matches!(peek_uri(br#"{"@": "d:"#), Err(ParseError::Truncated)));
+        assert!(matches!(peek_uri(b"  "), Err(ParseError::Empty)));
+    }
+
+    /// ["d:a", {}] and { "@": "d:a" }, as the data of a memory-mapped file
+    static MSGPACK: [u8; 6] = [0x92, 0xa3, b'd', b':', b'a', 0x80];
+    static CBOR: [u8; 7] = [0xa1, 0x61, b'@', 0x63, b'd', b':', b'a'];
+
+    #[test]
+    fn uri_borrowed_from_static_data() {
+        let uris: Vec<Cow<'static, str>> = [&MSGPACK[..], &CBOR[..]].iter().map(|data| peek_uri_borrowed(data).unwrap()).collect();
+        for (uri, data) in uris.iter().zip([&MSGPACK[..], &CBOR[..]].iter()) {
+            match uri {
+                Cow::Borrowed(s) => assert!(data.as_ptr_range().contains(&s.as_ptr())),
+                Cow::Owned(_) => panic!("the uri is copied"),
+            }
+            let mut indv = Individual::new_raw(RawObj::new(data.to_vec()));
+            parse_raw(&mut indv).unwrap();
+            assert_eq!(indv.get_id(), uri);
+        }
+
+        // not valid UTF-8, the msgpack uri is copied, the cbor one is an error
+        let data: &'static [u8] = &[0x92, 0xa2, b'd', 0xff, 0x80];
+        assert!(matches!(peek_uri_borrowed(data), Ok(Cow::Owned(_))));
+        let data: &'static [u8] = &[0xa1, 0x61, b'@', 0x62, b'd', 0xff];
+        assert!(matches!(peek_uri_borrowed(data), Err(ParseError::Invalid(_))));
+        assert!(Individual::try_from(data).is_err());
+    }
+
+    #[test]
+    fn cbor_uri_must_be_text() {
+        // { "@": 3 } followed by "abc", and the uri as the bytes "abc"
+        for data in [&[0xa1, 0x61, b'@', 0x03, b'a', b'b', b'c'][..], &[0xa1, 0x61, b'@', 0x43, b'a', b'b', b'c'][..]] {
+            assert!(matches!(peek_uri(data), Err(ParseError::Invalid(_))));
+            assert!(Individual::try_from(data).is_err());
+        }
+        assert_eq!(peek_uri(&[0xa1, 0x61, b'@', 0x63, b'a', b'b', b'c']).unwrap(), "abc");
     }
 
-    Err(-1)
+    #[test]
+    fn framed_buffers() {
+        let mut stream = vec![];
+        for (uri, count) in [("d:a", 1), ("d:b", 2)] {
+            let mut src = Individual::default();
+            src.set_id(uri);
+            src.add_integer("v-s:count", count);
+            let mut data = vec![];
+            to_msgpack(&src, &mut data).unwrap();
+            stream.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            stream.extend(data);
+        }
+
+        let (mut first, consumed) = parse_framed(&stream).unwrap();
+        assert_eq!(first.get_id(), "d:a");
+        assert_eq!(first.get_first_integer("v-s:count"), Some(1));
+        let (mut second, rest) = parse_framed(&stream[consumed..]).unwrap();
+        assert_eq!(second.get_id(), "d:b");
+        assert_eq!(second.get_first_integer("v-s:count"), Some(2));
+        assert_eq!(consumed + rest, stream.len());
+
+        // the length overruns the buffer
+        match parse_framed(&stream[..consumed - 1]) {
+            Err(ParseError::Invalid(e)) => assert!(e.contains("out of data"), "{}", e),
+            other => panic!("unexpected {:?}", other.map(|(_, n)| n)),
+        }
+        assert!(matches!(parse_framed(&[0, 0]), Err(ParseError::Invalid(_))));
+        assert!(matches!(parse_framed(&[]), Err(ParseError::Empty)));
+    }
+
+    #[test]
+    fn cbor_unknown_tag_under_every_policy() {
+        // { "@": "d:a", "v-s:n": [99("x"), 99(5), 1] }
+        let mut data = vec![0xa2];
+        data.extend(cbor_text("@"));
+        data.extend(cbor_text("d:a"));
+        data.extend(cbor_text("v-s:n"));
+        data.extend([0x83, 0xd8, 99, 0x61, b'x', 0xd8, 99, 0x05, 0x01]);
+        let parse = |policy: UnknownTagPolicy| {
+            let mut raw = RawObj::new(data.clone());
+            raw.unknown_tags = policy;
+            let mut indv = Individual::new_raw(raw);
+            parse_raw(&mut indv)?;
+            indv.try_parse_all()?;
+            Ok::<_, ParseError>(indv.get_resources("v-s:n").unwrap_or_default().iter().map(|r| (r.rtype.clone(), r.value_as_str())).collect::<Vec<_>>())
+        };
+
+        match parse(UnknownTagPolicy::default()) {
+            Err(ParseError::Invalid(e)) => assert!(e.contains("99"), "{}", e),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(parse(UnknownTagPolicy::SkipValue).unwrap(), vec![(DataType::Integer, "1".to_owned())]);
+        assert_eq!(
+            parse(UnknownTagPolicy::AsString).unwrap(),
+            vec![(DataType::String, "x".to_owned()), (DataType::String, "5".to_owned()), (DataType::Integer, "1".to_owned())]
+        );
+    }
+
+    fn parse_into(target: &mut Individual, policy: MergePolicy) -> Result<(), ParseError> {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_integer("v-s:count", 2);
+        src.add_uri("v-s:parent", "d:p");
+        let mut data = vec![];
+        to_msgpack(&src, &mut data).unwrap();
+        parse_raw_into(&data, target, policy)
+    }
+
+    fn target(uri: &str) -> Individual {
+        let mut target = Individual::default();
+        target.set_id(uri);
+        target.add_integer("v-s:count", 1);
+        target
+    }
+
+    #[test]
+    fn parse_raw_into_appends_or_replaces_overlapping_predicate() {
+        let mut appended = target("d:a");
+        parse_into(&mut appended, MergePolicy::default()).unwrap();
+        assert_eq!(appended.get_obj().get_resources()["v-s:count"].iter().map(|r| r.get_int()).collect::<Vec<i64>>(), vec![1, 2]);
+        assert_eq!(appended.get_first_literal("v-s:parent"), Some("d:p".to_owned()));
+
+        let mut replaced = target("d:a");
+        let policy = MergePolicy {
+            values: ValuePolicy::PreferOther,
+            ..MergePolicy::default()
+        };
+        parse_into(&mut replaced, policy).unwrap();
+        assert_eq!(replaced.get_obj().get_resources()["v-s:count"].iter().map(|r| r.get_int()).collect::<Vec<i64>>(), vec![2]);
+        assert_eq!(replaced.get_first_literal("v-s:parent"), Some("d:p".to_owned()));
+    }
+
+    #[test]
+    fn parse_raw_into_with_other_uri() {
+        let mut rejected = target("d:b");
+        assert!(parse_into(&mut rejected, MergePolicy::default()).is_err());
+        assert_eq!(rejected.get_first_integer("v-s:count"), Some(1));
+        assert_eq!(rejected.get_first_literal("v-s:parent"), None);
+
+        let mut taken = target("d:b");
+        let policy = MergePolicy {
+            uri: UriPolicy::TakeSource,
+            ..MergePolicy::default()
+        };
+        parse_into(&mut taken, policy).unwrap();
+        assert_eq!(taken.get_id(), "d:a");
+
+        let mut empty = Individual::default();
+        parse_into(&mut empty, MergePolicy::default()).unwrap();
+        assert_eq!(empty.get_id(), "d:a");
+    }
+
+    #[test]
+    fn format_version_of_known_fixtures() {
+        let fixtures: [(&[u8], FormatMarker); 3] = [
+            // [ "d:a", {} ]
+            (
+                &[0x92, 0xa3, b'd', b':', b'a', 0x80],
+                FormatMarker {
+                    raw_type: RawType::Msgpack,
+                    arity: 2,
+                },
+            ),
+            // { "@": "d:a", "v-s:n": [1] }
+            (
+                &[0xa2, 0x61, b'@', 0x63, b'd', b':', b'a', 0x65, b'v', b'-', b's', b':', b'n', 0x81, 0x01],
+                FormatMarker {
+                    raw_type: RawType::Cbor,
+                    arity: 2,
+                },
+            ),
+            // the same with an indefinite-length map
+            (
+                &[0xbf, 0x61, b'@', 0x63, b'd', b':', b'a', 0x65, b'v', b'-', b's', b':', b'n', 0x81, 0x01, 0xff],
+                FormatMarker {
+                    raw_type: RawType::Cbor,
+                    arity: 0,
+                },
+            ),
+        ];
+        for (data, marker) in fixtures.iter() {
+            let mut indv = Individual::new_raw(RawObj::new(data.to_vec()));
+            parse_raw(&mut indv).unwrap();
+            assert_eq!(indv.format_version(), *marker);
+            assert_eq!(indv.get_id(), "d:a");
+        }
+        assert_eq!(Individual::default().format_version().arity, 0);
+    }
+
+    #[test]
+    fn predicate_parse_failure_is_returned_without_a_logger() {
+        // { "@": "d:a", "v-s:n": [ 99(1) ] }, the tag is unknown
+        let data = vec![0xa2, 0x61, b'@', 0x63, b'd', b':', b'a', 0x65, b'v', b'-', b's', b':', b'n', 0x81, 0xd8, 99, 0x01];
+        let mut indv = Individual::new_raw(RawObj::new(data.clone()));
+        parse_raw(&mut indv).unwrap();
+        match try_parse_to_predicate("v-s:n", &mut indv) {
+            Err(ParseError::Invalid(e)) => assert!(e.contains("v-s:n"), "{}", e),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(!try_parse_to_predicate("v-s:n", &mut indv).unwrap());
+
+        let mut indv = Individual::new_raw(RawObj::new(data.clone()));
+        parse_raw(&mut indv).unwrap();
+        assert!(!parse_to_predicate("v-s:n", &mut indv));
+
+        let mut indv = Individual::new_raw(RawObj::new(data));
+        parse_raw(&mut indv).unwrap();
+        assert!(matches!(indv.try_parse_all(), Err(ParseError::Invalid(_))));
+    }
+
+    /// xorshift64, the sequence is the same on every run
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    fn parse_fully(data: Vec<u8>) -> Result<(), ParseError> {
+        let mut indv = Individual::new_raw(RawObj::new(data));
+        parse_raw(&mut indv)?;
+        indv.try_parse_all()
+    }
+
+    #[test]
+    fn parse_raw_never_panics_on_random_data() {
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_uri("rdf:type", "v-s:Document");
+        src.add_string("rdfs:label", "документ", crate::datatype::Lang::RU);
+        src.add_integer("v-s:count", -70_000);
+        src.add_decimal_d("v-s:amount", 150, -2);
+        src.add_datetime("v-s:created", 1_600_000_000);
+        src.add_bool("v-s:deleted", true);
+        let mut msgpack = vec![];
+        to_msgpack(&src, &mut msgpack).unwrap();
+        let mut cbor = vec![];
+        crate::individual2cbor::to_cbor(&src, &mut cbor).unwrap();
+        assert!(parse_fully(msgpack.clone()).is_ok());
+        assert!(parse_fully(cbor.clone()).is_ok());
+
+        let mut state = 0x9e37_79b9_7f4a_7c15;
+        for n in 0..5000 {
+            let data = match n % 3 {
+                // random bytes, a half of them with the msgpack header
+                0 => {
+                    let len = next_random(&mut state) as usize % 64;
+                    let mut data: Vec<u8> = (0..len).map(|_| next_random(&mut state) as u8).collect();
+                    if n % 2 == 0 && !data.is_empty() {
+                        data[0] = MSGPACK_MAGIC_HEADER;
+                    }
+                    data
+                }
+                // valid data truncated at a random position
+                1 => {
+                    let valid = if n % 2 == 0 { &msgpack } else { &cbor };
+                    valid[..next_random(&mut state) as usize % valid.len()].to_vec()
+                }
+                // valid data with random bytes replaced
+                _ => {
+                    let mut data = if n % 2 == 0 { msgpack.clone() } else { cbor.clone() };
+                    for _ in 0..1 + next_random(&mut state) % 4 {
+                        let pos = next_random(&mut state) as usize % data.len();
+                        data[pos] = next_random(&mut state) as u8;
+                    }
+                    data
+                }
+            };
+            let _ = parse_fully(data);
+        }
+    }
+
+    #[test]
+    fn parse_raw_interned_equals_parse_raw() {
+        let mut pool = Interner::new();
+        for n in 0..3 {
+            let mut src = Individual::default();
+            src.set_id(&format!("d:a{}", n));
+            src.add_uri("rdf:type", "v-s:Document");
+            src.add_integer("v-s:count", n);
+            src.add_integer("v-s:count", n + 1);
+            src.add_string("rdfs:label", "документ", crate::datatype::Lang::RU);
+            src.add_binary("v-s:data", vec![0, 0xff]);
+            let mut data = vec![];
+            to_msgpack(&src, &mut data).unwrap();
+
+            let mut interned = Individual::new_raw(RawObj::new(data.clone()));
+            parse_raw_interned(&mut interned, &mut pool).unwrap();
+            let plain = Individual::try_from(data.as_slice()).unwrap();
+            assert_eq!(interned.get_id(), plain.get_id());
+            assert_eq!(interned.get_obj().get_resources(), plain.get_obj().get_resources());
+        }
+        assert_eq!(pool.len(), 4);
+
+        let mut truncated = vec![];
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_uri("rdf:type", "v-s:Document");
+        to_msgpack(&src, &mut truncated).unwrap();
+        truncated.truncate(truncated.len() - 3);
+        assert!(parse_raw_interned(&mut Individual::new_raw(RawObj::new(truncated)), &mut pool).is_err());
+    }
 }
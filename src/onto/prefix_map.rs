@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+/// Prefixes and their namespaces, used to compact full IRIs into prefixed names and back.
+/// The prefixes are kept without the trailing `:`.
+#[derive(Debug, Default, Clone)]
+pub struct PrefixMap {
+    prefixes: HashMap<String, String>,
+}
+
+impl PrefixMap {
+    pub fn new() -> Self {
+        PrefixMap::default()
+    }
+
+    /// Builds the map from pairs prefix -> namespace, overlapping namespaces are reported as warnings
+    pub fn from_map(map: &HashMap<String, String>) -> Self {
+        let mut p = PrefixMap::new();
+        for (prefix, namespace) in map.iter() {
+            p.add_prefix(prefix, namespace);
+        }
+        for (a, b) in p.check_overlaps() {
+            warn!("prefix map: namespace of [{}] is a prefix of the namespace of [{}]", a, b);
+        }
+        p
+    }
+
+    /// Declares the prefix, a redeclaration with another namespace replaces it as in Turtle (the later wins):
+    /// the compaction and expansion use the new namespace, the replaced one is returned and reported as a warning
+    pub fn add_prefix(&mut self, prefix: &str, namespace: &str) -> Option<String> {
+        let prefix = prefix.trim_end_matches(':');
+        match self.prefixes.insert(prefix.to_owned(), namespace.to_owned()) {
+            Some(prev) if prev != namespace => {
+                warn!("prefix map: [{}] is redeclared, <{}> is replaced by <{}>", prefix, prev, namespace);
+                Some(prev)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_namespace(&self, prefix: &str) -> Option<&str> {
+        self.prefixes.get(prefix.trim_end_matches(':')).map(|s| s.as_str())
+    }
+
+    pub fn as_map(&self) -> &HashMap<String, String> {
+        &self.prefixes
+    }
+
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// Compacts the full IRI to a prefixed name, the longest matching namespace wins.
+    /// Of several prefixes with the same namespace the shortest one is taken, then the lexicographically smallest.
+    pub fn compact(&self, iri: &str) -> Option<String> {
+        let mut found: Option<(&String, &String)> = None;
+        for (prefix, namespace) in self.prefixes.iter() {
+            if !iri.starts_with(namespace.as_str()) {
+                continue;
+            }
+            let is_better = found.is_none_or(|(p, ns)| namespace.len() > ns.len() || (namespace.len() == ns.len() && (prefix.len(), prefix) < (p.len(), p)));
+            if is_better {
+                found = Some((prefix, namespace));
+            }
+        }
+        found.map(|(prefix, namespace)| format!("{}:{}", prefix, &iri[namespace.len()..]))
+    }
+
+    /// Expands the prefixed name to the full IRI
+    pub fn expand(&self, name: &str) -> Option<String> {
+        let pos = name.find(':')?;
+        self.prefixes.get(&name[..pos]).map(|namespace| format!("{}{}", namespace, &name[pos + 1..]))
+    }
+
+    /// Pairs of prefixes (a, b) where the namespace of `a` is a prefix of the namespace of `b`,
+    /// such sets of prefixes are ambiguous for compaction
+    pub fn check_overlaps(&self) -> Vec<(String, String)> {
+        let mut res = vec![];
+        for (a, a_ns) in self.prefixes.iter() {
+            for (b, b_ns) in self.prefixes.iter() {
+                if a != b && b_ns.starts_with(a_ns.as_str()) && (a_ns.len() < b_ns.len() || a < b) {
+                    res.push((a.to_owned(), b.to_owned()));
+                }
+            }
+        }
+        res.sort();
+        res
+    }
+}
+
+/// Percent-decodes the IRI for display, the result must not be serialized.
+/// The reserved characters of RFC 3986, `%`, the control characters and sequences which are not valid UTF-8 stay encoded.
+pub fn decode_iri_for_display(iri: &str) -> String {
+    let bytes = iri.as_bytes();
+    let mut res = String::with_capacity(iri.len());
+    // consecutive encoded non-ASCII bytes, decoded together as UTF-8, and the start of their source text
+    let mut run: Vec<u8> = vec![];
+    let mut run_start = 0;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let decoded = if bytes[i] == b'%' && i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit() && bytes[i + 2].is_ascii_hexdigit() {
+            u8::from_str_radix(&iri[i + 1..i + 3], 16).ok()
+        } else {
+            None
+        };
+        match decoded {
+            Some(b) if !b.is_ascii() => {
+                if run.is_empty() {
+                    run_start = i;
+                }
+                run.push(b);
+                i += 3;
+                continue;
+            }
+            _ => flush_run(&mut res, &mut run, &iri[run_start..i]),
+        }
+        match decoded {
+            Some(b) if !(b.is_ascii_control() || b"%:/?#[]@!$&'()*+,;=".contains(&b)) => {
+                res.push(b as char);
+                i += 3;
+            }
+            _ => {
+                let c = iri[i..].chars().next().unwrap_or_default();
+                res.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    flush_run(&mut res, &mut run, &iri[run_start..]);
+    res
+}
+
+fn flush_run(res: &mut String, run: &mut Vec<u8>, source: &str) {
+    if run.is_empty() {
+        return;
+    }
+    match std::str::from_utf8(run) {
+        Ok(s) => res.push_str(s),
+        Err(_) => res.push_str(source),
+    }
+    run.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_namespaces() {
+        let mut map = PrefixMap::new();
+        map.add_prefix("ex:", "http://ex.org/");
+        map.add_prefix("sub", "http://ex.org/sub/");
+        map.add_prefix("other", "http://other.org/");
+        assert_eq!(map.check_overlaps(), vec![("ex".to_owned(), "sub".to_owned())]);
+        assert_eq!(map.compact("http://ex.org/sub/a").as_deref(), Some("sub:a"));
+        assert_eq!(map.compact("http://ex.org/a").as_deref(), Some("ex:a"));
+
+        map.add_prefix("same", "http://other.org/");
+        assert_eq!(map.check_overlaps(), vec![("ex".to_owned(), "sub".to_owned()), ("other".to_owned(), "same".to_owned())]);
+    }
+
+    #[test]
+    fn tie_break_of_prefixes_with_one_namespace() {
+        let mut map = PrefixMap::new();
+        map.add_prefix("vs", "http://example.org/v-s#");
+        map.add_prefix("v-s", "http://example.org/v-s#");
+        map.add_prefix("va", "http://example.org/v-s#");
+        assert_eq!(map.compact("http://example.org/v-s#name").as_deref(), Some("va:name"));
+
+        map.add_prefix("v", "http://example.org/v-s#");
+        for _ in 0..3 {
+            assert_eq!(map.compact("http://example.org/v-s#name").as_deref(), Some("v:name"));
+        }
+    }
+
+    #[test]
+    fn iri_decoded_for_display() {
+        assert_eq!(decode_iri_for_display("http://ex.org/a%20b"), "http://ex.org/a b");
+        assert_eq!(decode_iri_for_display("http://ex.org/%D0%B4%D0%BE%D0%BA"), "http://ex.org/док");
+        // the structural characters, a broken sequence and an invalid UTF-8 sequence stay encoded
+        assert_eq!(decode_iri_for_display("http://ex.org/a%2Fb%3Fc%23d"), "http://ex.org/a%2Fb%3Fc%23d");
+        assert_eq!(decode_iri_for_display("http://ex.org/a%2"), "http://ex.org/a%2");
+        assert_eq!(decode_iri_for_display("http://ex.org/%D0%20"), "http://ex.org/%D0 ");
+        assert_eq!(decode_iri_for_display("d:a"), "d:a");
+    }
+
+    #[test]
+    fn redeclared_prefix_replaces_namespace() {
+        let mut map = PrefixMap::new();
+        assert_eq!(map.add_prefix("d", "http://example.org/old#"), None);
+        assert_eq!(map.add_prefix("d:", "http://example.org/old#"), None);
+        assert_eq!(map.add_prefix("d", "http://example.org/new#").as_deref(), Some("http://example.org/old#"));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get_namespace("d"), Some("http://example.org/new#"));
+        assert_eq!(map.compact("http://example.org/new#a").as_deref(), Some("d:a"));
+        assert_eq!(map.compact("http://example.org/old#a"), None);
+        assert_eq!(map.expand("d:a").as_deref(), Some("http://example.org/new#a"));
+    }
+}
@@ -1,5 +1,6 @@
-use crate::datatype::{DataType, Lang};
+use crate::datatype::{compare_decimals, datetime_secs_to_xsd, format_decimal, is_e_notation, normalize_decimal, DataType, Lang};
 use derivative::Derivative;
+use std::cmp::Ordering;
 
 #[derive(Debug, PartialEq, Clone)]
 
@@ -20,6 +21,9 @@ pub struct Resource {
     pub value: Value,
     #[derivative(PartialEq = "ignore")]
     pub order: u16,
+    /// Id of the source of the value, 0 is unknown. In-memory only: it is not serialized and is ignored by the comparison
+    #[derivative(PartialEq = "ignore")]
+    pub source: u16,
 }
 
 impl Resource {
@@ -27,6 +31,7 @@ impl Resource {
         Resource {
             rtype: DataType::Boolean,
             order: 0,
+            source: 0,
             value: Value::Bool(data),
         }
     }
@@ -35,14 +40,24 @@ impl Resource {
         Resource {
             rtype: DataType::Uri,
             order: 0,
+            source: 0,
             value: Value::Uri(data.to_owned()),
         }
     }
 
+    /// Equality of values, decimals are compared by their numeric value (`1.50` equals `1.5`)
+    pub fn is_same_value(&self, other: &Resource) -> bool {
+        match (&self.value, &other.value) {
+            (Value::Num(m1, e1), Value::Num(m2, e2)) => normalize_decimal(*m1, *e1) == normalize_decimal(*m2, *e2),
+            _ => self == other,
+        }
+    }
+
     pub fn get_copy(&self) -> Self {
         Resource {
             rtype: self.rtype.clone(),
             order: self.order,
+            source: self.source,
             value: self.value.clone(),
         }
     }
@@ -111,6 +126,94 @@ impl Resource {
         }
     }
 
+    pub fn as_uri(&self) -> Option<&str> {
+        if let Value::Uri(s) = &self.value {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    /// The string and its language, `None` for a string without the language
+    pub fn as_string(&self) -> Option<(&str, Option<&str>)> {
+        match &self.value {
+            Value::Str(s, Lang::NONE) => Some((s, None)),
+            Value::Str(s, l) => Some((s, Some(l.to_string()))),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        if let Value::Int(t) = self.value {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Value::Bool(t) = self.value {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_datetime(&self) -> Option<i64> {
+        if let Value::Datetime(t) = self.value {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    /// The mantissa and the exponent of the decimal
+    pub fn as_decimal(&self) -> Option<(i64, i64)> {
+        if let Value::Num(m, e) = self.value {
+            Some((m, e))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_binary(&self) -> Option<&[u8]> {
+        if let Value::Binary(v) = &self.value {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Canonical string form of the value: uri as is, decimal in the precise notation,
+    /// datetime as ISO-8601, binary as base64
+    pub fn value_as_str(&self) -> String {
+        match &self.value {
+            Value::Int(i) => i.to_string(),
+            Value::Str(s, _) => s.to_owned(),
+            Value::Uri(s) => s.to_owned(),
+            Value::Bool(b) => b.to_string(),
+            Value::Num(m, e) => format_decimal(*m, *e),
+            Value::Binary(v) => encode_base64(v),
+            Value::Datetime(t) => datetime_secs_to_xsd(*t).unwrap_or_else(|| t.to_string()),
+        }
+    }
+
+    /// Full IRI of the datatype of the literal, a string with a language is `rdf:langString`, a decimal in the `E` notation
+    /// of `format_decimal` is `xsd:double`, `None` for an uri
+    pub fn datatype_iri(&self) -> Option<&str> {
+        match &self.value {
+            Value::Uri(_) => None,
+            Value::Str(_, Lang::NONE) => Some("http://www.w3.org/2001/XMLSchema#string"),
+            Value::Str(_, _) => Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#langString"),
+            Value::Int(_) => Some("http://www.w3.org/2001/XMLSchema#integer"),
+            Value::Bool(_) => Some("http://www.w3.org/2001/XMLSchema#boolean"),
+            Value::Num(m, e) if is_e_notation(*m, *e) => Some("http://www.w3.org/2001/XMLSchema#double"),
+            Value::Num(_, _) => Some("http://www.w3.org/2001/XMLSchema#decimal"),
+            Value::Binary(_) => Some("http://www.w3.org/2001/XMLSchema#base64Binary"),
+            Value::Datetime(_) => Some("http://www.w3.org/2001/XMLSchema#dateTime"),
+        }
+    }
+
     pub fn get_float(&self) -> f64 {
         if let Value::Num(m, e) = self.value {
             m as f64 * 10.0_f64.powf(e as f64)
@@ -120,6 +223,47 @@ impl Resource {
     }
 }
 
+/// Ordering of resources: by the datatype in the order of the `DataType` codes (uri, string, integer,
+/// datetime, decimal, boolean, binary), then by the language, then by the value. Numbers and datetimes
+/// are compared numerically, decimals of equal value by their representation (`1.5` before `1.50`),
+/// strings and uris lexicographically, binaries bytewise. The `order` of values is ignored.
+impl Ord for Resource {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let rank = |r: &Resource| (r.rtype.clone() as u8, r.get_lang() as u8);
+        rank(self).cmp(&rank(other)).then_with(|| match (&self.value, &other.value) {
+            (Value::Int(a), Value::Int(b)) | (Value::Datetime(a), Value::Datetime(b)) => a.cmp(b),
+            (Value::Num(m1, e1), Value::Num(m2, e2)) => {
+                compare_decimals(*m1, *e1, *m2, *e2).then_with(|| e2.cmp(e1))
+            }
+            (Value::Str(a, _), Value::Str(b, _)) | (Value::Uri(a), Value::Uri(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Binary(a), Value::Binary(b)) => a.cmp(b),
+            // the value does not match the datatype
+            (a, b) => value_rank(a).cmp(&value_rank(b)),
+        })
+    }
+}
+
+impl PartialOrd for Resource {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Eq for Resource {}
+
+fn value_rank(v: &Value) -> u8 {
+    match v {
+        Value::Uri(_) => 0,
+        Value::Str(_, _) => 1,
+        Value::Int(_) => 2,
+        Value::Datetime(_) => 3,
+        Value::Num(_, _) => 4,
+        Value::Bool(_) => 5,
+        Value::Binary(_) => 6,
+    }
+}
+
 impl From<Value> for i64 {
     fn from(v: Value) -> Self {
         if let Value::Int(t) = v {
@@ -139,3 +283,147 @@ impl From<Value> for bool {
         }
     }
 }
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Bytes of the base64 text, the padding is optional, whitespace is not allowed
+pub(crate) fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=');
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let v = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+        acc = (acc << 6) | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    // a single char left over can not encode a byte
+    if bits >= 6 {
+        return None;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(m: i64, e: i64) -> Resource {
+        Resource {
+            rtype: DataType::Decimal,
+            order: 0,
+            source: 0,
+            value: Value::Num(m, e),
+        }
+    }
+
+    #[test]
+    fn ord_of_decimals_out_of_decimal_range() {
+        let mut values = [decimal(1, 20), decimal(1, -29), decimal(-1, 20), decimal(0, 0), decimal(15, -1), decimal(150, -2)];
+        values.sort();
+        let nums: Vec<(i64, i64)> = values.iter().map(|r| r.get_num()).collect();
+        assert_eq!(nums, vec![(-1, 20), (0, 0), (1, -29), (15, -1), (150, -2), (1, 20)]);
+
+        assert_eq!(decimal(1, -29).cmp(&decimal(1, -28)), Ordering::Less);
+        assert_eq!(decimal(1, 20).cmp(&decimal(i64::MAX, 0)), Ordering::Greater);
+        assert_eq!(decimal(-1, 20).cmp(&decimal(i64::MIN, 0)), Ordering::Less);
+    }
+
+    #[test]
+    fn same_value_of_decimals_out_of_decimal_range() {
+        assert!(decimal(1, -29).is_same_value(&decimal(10, -30)));
+        assert!(decimal(1, 20).is_same_value(&decimal(100, 18)));
+        assert!(!decimal(1, -29).is_same_value(&decimal(1, -28)));
+        assert!(decimal(0, -40).is_same_value(&decimal(0, 5)));
+    }
+
+    fn resource(rtype: DataType, value: Value) -> Resource {
+        Resource {
+            rtype,
+            order: 0,
+            source: 0,
+            value,
+        }
+    }
+
+    #[test]
+    fn value_as_str_of_every_datatype() {
+        assert_eq!(resource(DataType::Uri, Value::Uri("d:a".to_owned())).value_as_str(), "d:a");
+        assert_eq!(resource(DataType::String, Value::Str("текст".to_owned(), Lang::RU)).value_as_str(), "текст");
+        assert_eq!(resource(DataType::Integer, Value::Int(-42)).value_as_str(), "-42");
+        assert_eq!(resource(DataType::Boolean, Value::Bool(true)).value_as_str(), "true");
+        assert_eq!(decimal(150, -2).value_as_str(), "1.50");
+        assert_eq!(decimal(-15, 2).value_as_str(), "-1500");
+        assert_eq!(resource(DataType::Datetime, Value::Datetime(0)).value_as_str(), "1970-01-01T00:00:00Z");
+        assert_eq!(resource(DataType::Datetime, Value::Datetime(i64::MAX)).value_as_str(), i64::MAX.to_string());
+        assert_eq!(resource(DataType::Binary, Value::Binary(b"any carnal pleas".to_vec())).value_as_str(), "YW55IGNhcm5hbCBwbGVhcw==");
+        assert_eq!(resource(DataType::Binary, Value::Binary(vec![])).value_as_str(), "");
+    }
+
+    #[test]
+    fn datatype_iri_of_every_variant() {
+        let xsd = |name: &str| Some(format!("http://www.w3.org/2001/XMLSchema#{}", name));
+        let iri = |r: Resource| r.datatype_iri().map(|s| s.to_owned());
+        assert_eq!(iri(Resource::new_uri("d:a")), None);
+        assert_eq!(iri(resource(DataType::String, Value::Str("a".to_owned(), Lang::NONE))), xsd("string"));
+        assert_eq!(
+            iri(resource(DataType::String, Value::Str("a".to_owned(), Lang::EN))).as_deref(),
+            Some("http://www.w3.org/1999/02/22-rdf-syntax-ns#langString")
+        );
+        assert_eq!(iri(resource(DataType::Integer, Value::Int(1))), xsd("integer"));
+        assert_eq!(iri(Resource::new_bool(true)), xsd("boolean"));
+        assert_eq!(iri(decimal(15, -1)), xsd("decimal"));
+        assert_eq!(iri(decimal(15, 2000)), xsd("double"));
+        assert_eq!(iri(resource(DataType::Binary, Value::Binary(vec![1]))), xsd("base64Binary"));
+        assert_eq!(iri(resource(DataType::Datetime, Value::Datetime(0))), xsd("dateTime"));
+    }
+
+    #[test]
+    fn typed_accessors_of_right_and_wrong_type() {
+        let uri = Resource::new_uri("d:a");
+        let plain = resource(DataType::String, Value::Str("a".to_owned(), Lang::NONE));
+        let ru = resource(DataType::String, Value::Str("а".to_owned(), Lang::RU));
+        let int = resource(DataType::Integer, Value::Int(-1));
+        let flag = Resource::new_bool(false);
+        let datetime = resource(DataType::Datetime, Value::Datetime(86_400));
+        let num = decimal(15, -1);
+        let binary = resource(DataType::Binary, Value::Binary(vec![0, 1]));
+
+        assert_eq!(uri.as_uri(), Some("d:a"));
+        assert_eq!(plain.as_string(), Some(("a", None)));
+        assert_eq!(ru.as_string(), Some(("а", Some("ru"))));
+        assert_eq!(int.as_integer(), Some(-1));
+        assert_eq!(flag.as_bool(), Some(false));
+        assert_eq!(datetime.as_datetime(), Some(86_400));
+        assert_eq!(num.as_decimal(), Some((15, -1)));
+        assert_eq!(binary.as_binary(), Some(&[0u8, 1][..]));
+
+        assert_eq!(plain.as_uri(), None);
+        assert_eq!(uri.as_string(), None);
+        assert_eq!(datetime.as_integer(), None);
+        assert_eq!(int.as_bool(), None);
+        assert_eq!(int.as_datetime(), None);
+        assert_eq!(int.as_decimal(), None);
+        assert_eq!(plain.as_binary(), None);
+    }
+}
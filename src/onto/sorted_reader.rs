@@ -0,0 +1,94 @@
+use crate::individual::Individual;
+use crate::parser::ParseError;
+use std::collections::VecDeque;
+
+/// Reads the individuals of the inner iterator in windows of up to `window` individuals and emits every window
+/// sorted by uri, individuals with equal uris keep the stream order. A window of `usize::MAX` sorts the whole stream.
+/// An error of the inner iterator ends the window, it is passed through before the individuals of the window.
+pub struct SortedIndividualReader<I: Iterator<Item = Result<Individual, ParseError>>> {
+    inner: I,
+    window: usize,
+    pending: VecDeque<Individual>,
+    is_finished: bool,
+}
+
+impl<I: Iterator<Item = Result<Individual, ParseError>>> SortedIndividualReader<I> {
+    /// A window of 0 is the same as 1, the stream order is kept
+    pub fn new(inner: I, window: usize) -> Self {
+        SortedIndividualReader {
+            inner,
+            window: window.max(1),
+            pending: VecDeque::new(),
+            is_finished: false,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Individual, ParseError>>> Iterator for SortedIndividualReader<I> {
+    type Item = Result<Individual, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(indv) = self.pending.pop_front() {
+            return Some(Ok(indv));
+        }
+        if self.is_finished {
+            return None;
+        }
+
+        let mut batch = vec![];
+        while batch.len() < self.window {
+            match self.inner.next() {
+                Some(Ok(indv)) => batch.push(indv),
+                Some(Err(e)) => {
+                    self.pending.extend(sort_by_uri(batch));
+                    return Some(Err(e));
+                }
+                None => {
+                    self.is_finished = true;
+                    break;
+                }
+            }
+        }
+        self.pending.extend(sort_by_uri(batch));
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+fn sort_by_uri(mut batch: Vec<Individual>) -> Vec<Individual> {
+    batch.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+    batch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(uris: &[&str]) -> Vec<Result<Individual, ParseError>> {
+        uris.iter()
+            .map(|uri| {
+                if uri.is_empty() {
+                    return Err(ParseError::Empty);
+                }
+                let mut indv = Individual::default();
+                indv.set_id(uri);
+                Ok(indv)
+            })
+            .collect()
+    }
+
+    fn read(uris: &[&str], window: usize) -> Vec<String> {
+        SortedIndividualReader::new(stream(uris).into_iter(), window).map(|r| r.map_or_else(|_| "err".to_owned(), |i| i.get_id().to_owned())).collect()
+    }
+
+    #[test]
+    fn sorted_emission_within_a_window() {
+        let uris = ["d:c", "d:a", "d:b", "d:f", "d:e", "d:d", "d:g"];
+        assert_eq!(read(&uris, 3), vec!["d:a", "d:b", "d:c", "d:d", "d:e", "d:f", "d:g"]);
+        assert_eq!(read(&uris, 2), vec!["d:a", "d:c", "d:b", "d:f", "d:d", "d:e", "d:g"]);
+        assert_eq!(read(&uris, usize::MAX), vec!["d:a", "d:b", "d:c", "d:d", "d:e", "d:f", "d:g"]);
+        assert_eq!(read(&uris, 0), uris.to_vec());
+
+        // the error ends the window and is passed through first
+        assert_eq!(read(&["d:b", "d:a", "", "d:d", "d:c"], 10), vec!["err", "d:a", "d:b", "d:c", "d:d"]);
+    }
+}
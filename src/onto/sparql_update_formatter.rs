@@ -0,0 +1,153 @@
+use crate::individual::{value_diff, Individual};
+use crate::individual2turtle::format_individual;
+use crate::turtle_formatters_with_prefixes::{TurtleFormatterWithPrefixes, TurtleStyle};
+use rio_api::formatter::TriplesFormatter;
+use rio_api::model::Triple;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+/// Writes the triples as a SPARQL Update request: `PREFIX` declarations
+/// and then the triples in Turtle syntax inside `INSERT DATA { ... }`.
+/// The removed triples of `write_delta` go to separate `DELETE DATA { ... }` operations.
+pub struct SparqlUpdateFormatter<W: Write> {
+    inner: TurtleFormatterWithPrefixes<W>,
+    is_opened: bool,
+}
+
+impl<W: Write> SparqlUpdateFormatter<W> {
+    pub fn new(write: W, prefixes: &HashMap<String, String>) -> Self {
+        SparqlUpdateFormatter::new_with_style(write, prefixes, TurtleStyle::default())
+    }
+
+    pub fn new_with_style(write: W, prefixes: &HashMap<String, String>, style: TurtleStyle) -> Self {
+        let mut inner = TurtleFormatterWithPrefixes::new_with_style(write, prefixes, style);
+        inner.is_sparql_prefixes = true;
+        SparqlUpdateFormatter {
+            inner,
+            is_opened: false,
+        }
+    }
+
+    fn open(&mut self) -> Result<(), io::Error> {
+        if !self.is_opened {
+            self.is_opened = true;
+            self.inner.write_pending_prefixes()?;
+            let eol = self.inner.eol();
+            write!(self.inner.get_mut_writer(), "INSERT DATA {{{}", eol)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the values removed from `old` as a `DELETE DATA` operation and formats the added values as usual.
+    /// `DELETE DATA` can not match blank nodes, removed values of a blank subject or with a blank object
+    /// are an `InvalidInput` error, nothing is written then.
+    pub fn write_delta(&mut self, old: &Individual, new: &Individual) -> Result<(), io::Error> {
+        let (removed, added) = value_diff(old, new);
+
+        if !removed.is_empty() {
+            let has_blank = removed.subject_is_blank() || removed.get_obj().get_resources().values().flatten().any(|r| r.get_uri().starts_with("_:"));
+            if has_blank {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("sparql update: removed values of [{}] refer to a blank node", old.get_id())));
+            }
+
+            self.inner.write_pending_prefixes()?;
+            let eol = self.inner.eol();
+            if self.is_opened {
+                self.inner.end_block()?;
+                write!(self.inner.get_mut_writer(), "}} ;{}", eol)?;
+                self.is_opened = false;
+            }
+            write!(self.inner.get_mut_writer(), "DELETE DATA {{{}", eol)?;
+            format_individual(&removed, &mut self.inner)?;
+            self.inner.end_block()?;
+            write!(self.inner.get_mut_writer(), "}} ;{}", eol)?;
+        }
+
+        format_individual(&added, self)
+    }
+
+    pub fn finish(mut self) -> Result<W, io::Error> {
+        self.open()?;
+        let eol = self.inner.eol();
+        let mut write = self.inner.finish()?;
+        write!(write, "}}{}", eol)?;
+        Ok(write)
+    }
+}
+
+impl<W: Write> TriplesFormatter for SparqlUpdateFormatter<W> {
+    type Error = io::Error;
+
+    fn format(&mut self, triple: &Triple<'_>) -> Result<(), io::Error> {
+        self.open()?;
+        self.inner.format(triple)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_delta_with_decimal_out_of_decimal_range() {
+        let mut old = Individual::default();
+        old.set_id("d:a");
+        old.add_decimal_d("v-s:amount", 1, -29);
+        old.add_decimal_d("v-s:amount", 5, 0);
+        let mut new = Individual::default();
+        new.set_id("d:a");
+        new.add_decimal_d("v-s:amount", 10, -30);
+
+        let mut formatter = SparqlUpdateFormatter::new(Vec::new(), &HashMap::new());
+        formatter.write_delta(&old, &new).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        assert!(out.contains("DELETE DATA"));
+        assert!(out.contains("v-s:amount \"5\"^^xsd:decimal"));
+        assert!(!out.contains("0.00000000000000000000000000001"));
+    }
+
+    #[test]
+    fn write_delta_rejects_removed_blank_nodes() {
+        let mut old = Individual::default();
+        old.set_id("d:a");
+        old.add_uri("v-s:author", "_:b0");
+        old.add_integer("v-s:count", 1);
+        let mut new = Individual::default();
+        new.set_id("d:a");
+        new.add_integer("v-s:count", 1);
+        let mut formatter = SparqlUpdateFormatter::new(Vec::new(), &HashMap::new());
+        assert_eq!(formatter.write_delta(&old, &new).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        let mut old = Individual::default();
+        old.set_id("_:b0");
+        old.add_string("v-s:name", "a", crate::datatype::Lang::NONE);
+        let mut new = Individual::default();
+        new.set_id("_:b0");
+        let mut formatter = SparqlUpdateFormatter::new(Vec::new(), &HashMap::new());
+        assert!(formatter.write_delta(&old, &new).is_err());
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        assert!(!out.contains("DELETE DATA"));
+
+        // added blank nodes are fine in INSERT DATA
+        let mut formatter = SparqlUpdateFormatter::new(Vec::new(), &HashMap::new());
+        formatter.write_delta(&new, &old).unwrap();
+        assert!(String::from_utf8(formatter.finish().unwrap()).unwrap().contains("INSERT DATA"));
+    }
+
+    #[test]
+    fn insert_data_with_sparql_prefixes() {
+        let mut prefixes = HashMap::new();
+        prefixes.insert("d".to_owned(), "http://example.org/d#".to_owned());
+        prefixes.insert("v-s".to_owned(), "http://example.org/v-s#".to_owned());
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_string("v-s:name", "a", crate::datatype::Lang::NONE);
+
+        let mut formatter = SparqlUpdateFormatter::new(Vec::new(), &prefixes);
+        format_individual(&indv, &mut formatter).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        assert_eq!(out, "PREFIX d: <http://example.org/d#>\nPREFIX v-s: <http://example.org/v-s#>\n\nINSERT DATA {\nd:a \n  v-s:name \"a\" .\n}\n");
+        assert!(!out.contains("@prefix"));
+    }
+}
@@ -0,0 +1,440 @@
+use crate::datatype::{decode_binary_literal, Lang};
+use crate::individual::*;
+use crate::json2individual::individuals_from_json_slice;
+use crate::parser::*;
+use crate::prefix_map::PrefixMap;
+use rio_api::model::{Literal, NamedOrBlankNode, Term, Triple};
+use rio_api::parser::TriplesParser;
+use rio_turtle::{TurtleError, TurtleParser};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::io::{ErrorKind, Read};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Format of an entry, by the extension of its name or else by its content
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum EntryFormat {
+    Turtle,
+    Json,
+    /// msgpack or cbor
+    Raw,
+}
+
+fn entry_format(name: &str, data: &[u8]) -> EntryFormat {
+    let ext = name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+    match ext.as_deref() {
+        Some("ttl") => EntryFormat::Turtle,
+        Some("json") => EntryFormat::Json,
+        Some("msgpack") | Some("cbor") => EntryFormat::Raw,
+        _ => {
+            let content = data.iter().position(|b| !b.is_ascii_whitespace()).map_or(&data[..0], |pos| &data[pos..]);
+            if content.starts_with(b"{") || content.starts_with(b"[") {
+                EntryFormat::Json
+            } else if [&b"@prefix"[..], b"@base", b"PREFIX", b"BASE", b"#"].iter().any(|s| content.starts_with(s)) {
+                EntryFormat::Turtle
+            } else {
+                EntryFormat::Raw
+            }
+        }
+    }
+}
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+enum TurtleEntryError {
+    Syntax(TurtleError),
+    Value(String),
+}
+
+impl From<TurtleError> for TurtleEntryError {
+    fn from(e: TurtleError) -> Self {
+        TurtleEntryError::Syntax(e)
+    }
+}
+
+/// Prefixes of the `@prefix` and `PREFIX` declarations of the turtle document
+fn declared_prefixes(data: &[u8]) -> PrefixMap {
+    let mut prefixes = PrefixMap::new();
+    for line in String::from_utf8_lossy(data).lines() {
+        let line = line.trim_start();
+        let rest = match line.strip_prefix("@prefix").or_else(|| line.get(..6).filter(|s| s.eq_ignore_ascii_case("prefix")).map(|_| &line[6..])) {
+            Some(rest) => rest.trim_start(),
+            None => continue,
+        };
+        if let (Some((prefix, _)), Some(start), Some(end)) = (rest.split_once(':'), rest.find('<'), rest.find('>')) {
+            if start < end && !prefix.contains(char::is_whitespace) {
+                prefixes.add_prefix(prefix, &rest[start + 1..end]);
+            }
+        }
+    }
+    prefixes
+}
+
+/// Adds the object of the triple as a value of the predicate, the literal is checked against its datatype
+fn add_turtle_object(indv: &mut Individual, predicate: &str, object: &Term, name: &dyn Fn(&str) -> String) -> Result<(), String> {
+    let invalid = |value: &str, datatype: &str| format!("value [{}] of [{}] is not a valid {}", value, predicate, datatype);
+    match object {
+        Term::NamedNode(n) => indv.add_uri(predicate, &name(n.iri)),
+        Term::BlankNode(b) => indv.add_uri(predicate, &format!("_:{}", b.id)),
+        Term::Literal(Literal::Simple {
+            value,
+        }) => indv.add_string(predicate, value, Lang::NONE),
+        Term::Literal(Literal::LanguageTaggedString {
+            value,
+            language,
+        }) => indv.add_string(predicate, value, Lang::new_from_str(&language.to_ascii_lowercase())),
+        Term::Literal(Literal::Typed {
+            value,
+            datatype,
+        }) => match datatype.iri.strip_prefix(XSD).unwrap_or(datatype.iri) {
+            "integer" | "int" | "long" | "short" | "byte" | "nonNegativeInteger" | "positiveInteger" => {
+                indv.add_integer(predicate, value.parse().map_err(|_| invalid(value, datatype.iri))?)
+            }
+            "decimal" => {
+                let d = Individual::from_pairs_typed("", &[(predicate, value, "decimal")]).map_err(|_| invalid(value, datatype.iri))?;
+                let (mantissa, exponent) = d.values(predicate).next().map_or((0, 0), |r| r.get_num());
+                indv.add_decimal_d(predicate, mantissa, exponent);
+            }
+            "boolean" => match *value {
+                "true" | "1" => indv.add_bool(predicate, true),
+                "false" | "0" => indv.add_bool(predicate, false),
+                _ => return Err(invalid(value, datatype.iri)),
+            },
+            "dateTime" | "date" => indv.add_datetime(predicate, datetime_from_str(value).ok_or_else(|| invalid(value, datatype.iri))?),
+            "base64Binary" | "hexBinary" => indv.add_binary(predicate, decode_binary_literal(value, datatype.iri).ok_or_else(|| invalid(value, datatype.iri))?),
+            _ => indv.add_string(predicate, value, Lang::NONE),
+        },
+    }
+    Ok(())
+}
+
+/// Individuals of the turtle document, one per subject in the order of the first triple of it.
+/// The IRIs are compacted by the prefixes declared in the document, the blank nodes get the prefix `_:`.
+fn individuals_from_turtle_slice(data: &[u8]) -> Result<Vec<Individual>, ParseError> {
+    let prefixes = declared_prefixes(data);
+    let name = |iri: &str| prefixes.compact(iri).unwrap_or_else(|| iri.to_owned());
+
+    let mut inds: Vec<Individual> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut parser = TurtleParser::new(data, None);
+    let res = parser.parse_all(&mut |t: Triple| -> Result<(), TurtleEntryError> {
+        let subject = match t.subject {
+            NamedOrBlankNode::NamedNode(n) => name(n.iri),
+            NamedOrBlankNode::BlankNode(b) => format!("_:{}", b.id),
+        };
+        let pos = *positions.entry(subject.clone()).or_insert_with(|| {
+            let mut indv = Individual::default();
+            indv.set_id(&subject);
+            inds.push(indv);
+            inds.len() - 1
+        });
+        add_turtle_object(&mut inds[pos], &name(t.predicate.iri), &t.object, &name).map_err(TurtleEntryError::Value)
+    });
+
+    match res {
+        Ok(()) => Ok(inds),
+        Err(TurtleEntryError::Syntax(e)) => Err(ParseError::Invalid(format!("turtle: {}", e))),
+        Err(TurtleEntryError::Value(e)) => Err(ParseError::Invalid(format!("turtle: {}", e))),
+    }
+}
+
+fn parse_entry(name: &str, data: &[u8]) -> Result<Vec<Individual>, ParseError> {
+    let res = match entry_format(name, data) {
+        EntryFormat::Json => individuals_from_json_slice(data),
+        EntryFormat::Turtle => individuals_from_turtle_slice(data),
+        EntryFormat::Raw => Individual::try_from(data).map(|indv| vec![indv]),
+    };
+    res.map_err(|e| match e {
+        ParseError::Invalid(e) => ParseError::Invalid(format!("tar: entry [{}], {}", name, e)),
+        e => ParseError::Invalid(format!("tar: entry [{}], {}", name, e)),
+    })
+}
+
+/// Text of a header field up to the first NUL
+fn field_str(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn field_octal(field: &[u8]) -> Option<u64> {
+    let s = field_str(field);
+    let s = s.trim_matches(|c| c == ' ' || c == '\0');
+    if s.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(s, 8).ok()
+}
+
+/// The `path` record of the PAX extended header, the records are `<len> <key>=<value>\n`
+fn pax_path(data: &[u8]) -> Option<String> {
+    let mut rest = data;
+    let mut path = None;
+    while !rest.is_empty() {
+        let space = rest.iter().position(|b| *b == b' ')?;
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..len)?.strip_suffix(b"\n")?;
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+        rest = &rest[len..];
+    }
+    path
+}
+
+fn fail_read(e: impl std::fmt::Display) -> ParseError {
+    ParseError::Invalid(format!("tar: fail read, err={}", e))
+}
+
+struct TarIndividuals<R: Read> {
+    read: R,
+    pending: VecDeque<Individual>,
+    is_finished: bool,
+}
+
+impl<R: Read> TarIndividuals<R> {
+    /// The header block, `None` at the end of the archive
+    fn read_header(&mut self) -> Result<Option<[u8; BLOCK_SIZE]>, ParseError> {
+        let mut header = [0u8; BLOCK_SIZE];
+        let mut len = 0;
+        while len < BLOCK_SIZE {
+            match self.read.read(&mut header[len..]) {
+                Ok(0) if len == 0 => return Ok(None),
+                Ok(0) => return Err(ParseError::Invalid("tar: truncated header".to_owned())),
+                Ok(n) => len += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(fail_read(e)),
+            }
+        }
+        // the archive ends with zero blocks
+        if header.iter().all(|b| *b == 0) {
+            return Ok(None);
+        }
+
+        let checksum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if (148..156).contains(&i) {
+                    b' ' as u64
+                } else {
+                    *b as u64
+                }
+            })
+            .sum();
+        if field_octal(&header[148..156]) != Some(checksum) {
+            return Err(ParseError::Invalid("tar: wrong header checksum".to_owned()));
+        }
+        Ok(Some(header))
+    }
+
+    /// Data of the entry, the padding up to the block is skipped
+    fn read_data(&mut self, size: u64) -> Result<Vec<u8>, ParseError> {
+        let mut data = vec![];
+        (&mut self.read).take(size).read_to_end(&mut data).map_err(fail_read)?;
+        if (data.len() as u64) < size {
+            return Err(ParseError::Invalid("tar: truncated entry".to_owned()));
+        }
+        let padding = (BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+        if std::io::copy(&mut (&mut self.read).take(padding), &mut std::io::sink()).map_err(fail_read)? < padding {
+            return Err(ParseError::Invalid("tar: truncated entry".to_owned()));
+        }
+        Ok(data)
+    }
+
+    /// The name and the data of the next regular file, `None` at the end of the archive
+    fn next_file(&mut self) -> Result<Option<(String, Vec<u8>)>, ParseError> {
+        let mut long_name = None;
+        loop {
+            let header = match self.read_header()? {
+                Some(h) => h,
+                None => return Ok(None),
+            };
+            let size = field_octal(&header[124..136]).ok_or_else(|| ParseError::Invalid("tar: wrong entry size".to_owned()))?;
+            let data = self.read_data(size)?;
+
+            match header[156] {
+                b'0' | b'\0' | b'7' => {
+                    let name = match long_name.take() {
+                        Some(name) => name,
+                        None if &header[257..262] == b"ustar" && header[345] != 0 => format!("{}/{}", field_str(&header[345..500]), field_str(&header[..100])),
+                        None => field_str(&header[..100]),
+                    };
+                    return Ok(Some((name, data)));
+                }
+                // the name of the next entry, GNU extension
+                b'L' => long_name = Some(field_str(&data)),
+                // the extended header of the next entry, POSIX.1-2001
+                b'x' => long_name = pax_path(&data).or(long_name),
+                // the global extended header does not name an entry
+                b'g' => {}
+                // directories and links are skipped
+                _ => long_name = None,
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for TarIndividuals<R> {
+    type Item = Result<Individual, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(indv) = self.pending.pop_front() {
+                return Some(Ok(indv));
+            }
+            if self.is_finished {
+                return None;
+            }
+            match self.next_file() {
+                Ok(Some((name, data))) => match parse_entry(&name, &data) {
+                    Ok(inds) => self.pending.extend(inds),
+                    Err(e) => return Some(Err(e)),
+                },
+                Ok(None) => self.is_finished = true,
+                Err(e) => {
+                    self.is_finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// Individuals of the files of the tar archive, json, msgpack and cbor are detected by the extension of the file
+/// or else by its content, a json file may hold an array of individuals, a turtle file gives an individual per subject.
+/// A malformed file is reported and the reading goes on, a malformed archive ends the iteration with the error.
+pub fn read_individuals_from_tar<R: Read>(r: R) -> impl Iterator<Item = Result<Individual, ParseError>> {
+    TarIndividuals {
+        read: r,
+        pending: VecDeque::new(),
+        is_finished: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, data: &[u8], flag: u8) -> Vec<u8> {
+        let mut header = [0u8; BLOCK_SIZE];
+        header[..name.len().min(100)].copy_from_slice(&name.as_bytes()[..name.len().min(100)]);
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        header[136..148].copy_from_slice(b"00000000000\0");
+        header[156] = flag;
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u64 = header.iter().map(|b| *b as u64).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+        let mut res = header.to_vec();
+        res.extend_from_slice(data);
+        res.resize(res.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE, 0);
+        res
+    }
+
+    fn archive(entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut res = entries.concat();
+        res.extend_from_slice(&[0u8; BLOCK_SIZE * 2]);
+        res
+    }
+
+    #[test]
+    fn turtle_entry_gives_an_individual_per_subject() {
+        let ttl = b"@prefix v-s: <http://semantic-machines.com/veda/veda-schema/> .
+@prefix rdfs: <http://www.w3.org/2000/01/rdf-schema#> .
+@prefix xsd: <http://www.w3.org/2001/XMLSchema#> .
+
+v-s:Doc1 rdfs:label \"Document\"@en, \"plain\" ;
+  v-s:count 42 ;
+  v-s:amount 1.50 ;
+  v-s:author [ rdfs:label \"Author\" ] .
+v-s:Doc2 v-s:valid \"true\"^^xsd:boolean .
+";
+        let data = archive(&[entry("onto/docs.ttl", ttl, b'0')]);
+        let mut inds: Vec<Individual> = read_individuals_from_tar(data.as_slice()).collect::<Result<_, _>>().unwrap();
+        assert_eq!(inds.len(), 3);
+
+        let doc1 = &mut inds[0];
+        assert_eq!(doc1.get_id(), "v-s:Doc1");
+        assert_eq!(doc1.get_literals("rdfs:label"), Some(vec!["Document".to_owned(), "plain".to_owned()]));
+        assert_eq!(doc1.get_first_integer("v-s:count"), Some(42));
+        assert_eq!(doc1.values("v-s:amount").next().map(|r| r.get_num()), Some((150, -2)));
+        let author = doc1.get_first_literal("v-s:author").unwrap();
+        assert!(author.starts_with("_:"));
+
+        assert_eq!(inds[1].get_id(), author);
+        assert_eq!(inds[2].get_id(), "v-s:Doc2");
+        assert_eq!(inds[2].get_first_bool("v-s:valid"), Some(true));
+    }
+
+    #[test]
+    fn long_names_of_gnu_and_pax_headers() {
+        let indv = |uri: &str| format!("{{\"@\": \"{}\", \"rdf:type\": [{{\"type\": \"Uri\", \"data\": \"v-s:Document\"}}]}}", uri);
+        let gnu_name = format!("{}/gnu.json", "g".repeat(120));
+        let pax_name = format!("{}/pax.ttl", "p".repeat(120));
+        let pax_record = |key: &str, value: &str| {
+            let body = format!(" {}={}\n", key, value);
+            // the length counts its own digits
+            let mut len = body.len() + 1;
+            while (len.to_string().len() + body.len()) != len {
+                len += 1;
+            }
+            format!("{}{}", len, body)
+        };
+        let pax = format!("{}{}", pax_record("mtime", "0"), pax_record("path", &pax_name));
+
+        let data = archive(&[
+            entry("././@LongLink", gnu_name.as_bytes(), b'L'),
+            entry(&gnu_name[..100], indv("d:gnu").as_bytes(), b'0'),
+            entry("PaxHeaders/pax", pax.as_bytes(), b'x'),
+            // turtle without prefixes is known only by the extension of the full name
+            entry(&pax_name[..100], b"<http://ex.org/d> <http://ex.org/p> \"v\" .\n", b'0'),
+        ]);
+        let res: Vec<Result<Individual, ParseError>> = read_individuals_from_tar(data.as_slice()).collect();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].as_ref().unwrap().get_id(), "d:gnu");
+        assert_eq!(res[1].as_ref().unwrap().get_id(), "http://ex.org/d");
+    }
+
+    #[test]
+    fn invalid_turtle_entry_is_reported() {
+        let data = archive(&[entry("a.ttl", b"@prefix v-s: <http://x/> .\nv-s:a v-s:b \"x\"^^<http://www.w3.org/2001/XMLSchema#integer> .\n", b'0')]);
+        let res: Vec<Result<Individual, ParseError>> = read_individuals_from_tar(data.as_slice()).collect();
+        assert_eq!(res.len(), 1);
+        assert!(res[0].is_err());
+    }
+
+    #[test]
+    fn binary_roundtrip_of_every_encoding() {
+        use crate::datatype::{BinaryEncoding, DataType};
+        use crate::individual2turtle::to_turtle_with_style;
+        use crate::turtle_formatters_with_prefixes::TurtleStyle;
+        use std::collections::HashMap;
+
+        let mut src = Individual::default();
+        src.set_id("d:a");
+        src.add_binary("v-s:data", vec![0, 0xde, 0xad, 0xff]);
+        let mut prefixes = HashMap::new();
+        prefixes.insert("d:".to_owned(), "http://example.org/d#".to_owned());
+        prefixes.insert("v-s:".to_owned(), "http://example.org/v-s#".to_owned());
+        prefixes.insert("xsd:".to_owned(), "http://www.w3.org/2001/XMLSchema#".to_owned());
+
+        for (encoding, datatype) in [(BinaryEncoding::Base64, "xsd:base64Binary"), (BinaryEncoding::Hex, "xsd:hexBinary")] {
+            let style = TurtleStyle {
+                binary_encoding: Some(encoding),
+                ..TurtleStyle::default()
+            };
+            let ttl = to_turtle_with_style(std::slice::from_ref(&src), &mut prefixes, style).unwrap();
+            assert!(String::from_utf8_lossy(&ttl).contains(datatype));
+
+            let mut inds = individuals_from_turtle_slice(&ttl).unwrap();
+            assert_eq!(inds.len(), 1);
+            let values = inds[0].get_resources("v-s:data").unwrap();
+            assert_eq!(values[0].rtype, DataType::Binary);
+            assert_eq!(values[0].get_binary(), &[0, 0xde, 0xad, 0xff]);
+        }
+    }
+}
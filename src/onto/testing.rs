@@ -0,0 +1,120 @@
+use crate::datatype::DataType;
+use crate::individual::Individual;
+use crate::individual2cbor::to_cbor;
+use crate::individual2msgpack::to_msgpack;
+use crate::parser::detect_raw_type;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+
+/// Serializes the individual to msgpack and to cbor, parses them back and panics with the list of differing
+/// predicates on mismatch. The cbor format has no binary values, cbor is not checked for an individual with them.
+pub fn assert_roundtrip(ind: &Individual) {
+    let mut raw = vec![];
+    if let Err(e) = to_msgpack(ind, &mut raw) {
+        panic!("roundtrip [{}]: fail serialize to msgpack, err={:?}", ind.get_id(), e);
+    }
+    assert_parsed_equal(ind, &raw, "msgpack");
+
+    let has_binary = ind.get_obj().get_resources().values().flatten().any(|r| r.rtype == DataType::Binary);
+    if !has_binary {
+        let mut raw = vec![];
+        if let Err(e) = to_cbor(ind, &mut raw) {
+            panic!("roundtrip [{}]: fail serialize to cbor, err={:?}", ind.get_id(), e);
+        }
+        assert_parsed_equal(ind, &raw, "cbor");
+    }
+}
+
+fn assert_parsed_equal(ind: &Individual, raw: &[u8], format: &str) {
+    let parsed = match Individual::try_from(raw) {
+        Ok(v) => v,
+        Err(e) => panic!("roundtrip [{}]: fail parse {}, err={}", ind.get_id(), format, e),
+    };
+
+    if let Some(diff) = diff(ind, &parsed) {
+        panic!("roundtrip [{}]: {} mismatch\n{}", ind.get_id(), format, diff);
+    }
+}
+
+/// Parses the raw data with all predicates, panics with the detected format, the length
+/// and a hex dump of the first 32 bytes of the data on error
+pub fn must_parse(bytes: &[u8]) -> Individual {
+    match Individual::try_from(bytes) {
+        Ok(v) => v,
+        Err(e) => {
+            let dump: Vec<String> = bytes.iter().take(32).map(|b| format!("{:02x}", b)).collect();
+            panic!("must_parse: {}, raw type={:?}, len={}, data={}", e, detect_raw_type(bytes), bytes.len(), dump.join(" "));
+        }
+    }
+}
+
+fn diff(expected: &Individual, actual: &Individual) -> Option<String> {
+    let mut res = String::new();
+    if expected.get_id() != actual.get_id() {
+        res.push_str(&format!("uri: expected [{}], got [{}]\n", expected.get_id(), actual.get_id()));
+    }
+
+    let a = expected.get_obj().get_resources();
+    let b = actual.get_obj().get_resources();
+    let predicates: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+    for predicate in predicates {
+        let (va, vb) = (a.get(predicate), b.get(predicate));
+        if va != vb {
+            res.push_str(&format!("{}: expected {:?}, got {:?}\n", predicate, va, vb));
+        }
+    }
+
+    if res.is_empty() {
+        None
+    } else {
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datatype::Lang;
+
+    #[test]
+    fn roundtrip_of_every_datatype() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_uri("rdf:type", "v-s:Document");
+        indv.add_string("rdfs:label", "документ", Lang::RU);
+        indv.add_string("rdfs:label", "document", Lang::EN);
+        indv.add_string("v-s:comment", "plain", Lang::NONE);
+        indv.add_string("v-s:comment", &"long".repeat(100), Lang::NONE);
+        indv.add_string("v-s:comment", &"longer".repeat(20_000), Lang::NONE);
+        indv.add_integer("v-s:count", 42);
+        indv.add_integer("v-s:count", -42);
+        indv.add_integer("v-s:count", i64::MIN + 1);
+        indv.add_integer("v-s:count", 70_000);
+        indv.add_datetime("v-s:created", 1_600_000_000);
+        indv.add_datetime("v-s:created", -86_400);
+        indv.add_decimal_d("v-s:amount", 150, -2);
+        indv.add_decimal_d("v-s:amount", -15, 3);
+        indv.add_bool("v-s:deleted", false);
+        assert_roundtrip(&indv);
+
+        let mut raw = vec![];
+        to_cbor(&indv, &mut raw).unwrap();
+        assert_eq!(detect_raw_type(&raw), crate::parser::RawType::Cbor);
+    }
+
+    #[test]
+    fn roundtrip_with_binary_skips_cbor() {
+        let mut indv = Individual::default();
+        indv.set_id("d:a");
+        indv.add_binary("v-s:data", vec![0, 1, 0xff]);
+        assert_roundtrip(&indv);
+        assert!(to_cbor(&indv, &mut vec![]).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "raw type=Cbor, len=3, data=a1 61 40")]
+    fn must_parse_of_malformed_buffer_panics_with_hex_dump() {
+        // { "@": ... } without the uri
+        must_parse(&[0xa1, 0x61, b'@']);
+    }
+}
@@ -1,8 +1,10 @@
+use crate::datatype::BinaryEncoding;
+use chrono::{TimeZone, Utc};
 use rio_api::formatter::TriplesFormatter;
 use rio_api::model::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io;
-use std::io::Write;
+use std::io::{Error, ErrorKind, Write};
 
 #[derive(Copy, Clone)]
 enum NamedOrBlankNodeType {
@@ -10,86 +12,923 @@ enum NamedOrBlankNodeType {
     BlankNode,
 }
 
-impl NamedOrBlankNodeType {
-    fn with_value<'a>(&self, value: &'a str) -> NamedOrBlankNode<'a> {
+/// Owned copy of a term, the triples are kept in this form in the buffered mode
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum OwnedTerm {
+    NamedNode(String),
+    BlankNode(String),
+    Simple(String),
+    LanguageTaggedString(String, String),
+    Typed(String, String),
+}
+
+impl OwnedTerm {
+    fn from_subject(n: &NamedOrBlankNode) -> Self {
+        match n {
+            NamedOrBlankNode::NamedNode(n) => OwnedTerm::NamedNode(n.iri.to_owned()),
+            NamedOrBlankNode::BlankNode(n) => OwnedTerm::BlankNode(n.id.to_owned()),
+        }
+    }
+
+    fn from_term(t: &Term) -> Self {
+        match t {
+            Term::NamedNode(n) => OwnedTerm::NamedNode(n.iri.to_owned()),
+            Term::BlankNode(n) => OwnedTerm::BlankNode(n.id.to_owned()),
+            Term::Literal(Literal::Simple {
+                value,
+            }) => OwnedTerm::Simple((*value).to_owned()),
+            Term::Literal(Literal::LanguageTaggedString {
+                value,
+                language,
+            }) => OwnedTerm::LanguageTaggedString((*value).to_owned(), (*language).to_owned()),
+            Term::Literal(Literal::Typed {
+                value,
+                datatype,
+            }) => OwnedTerm::Typed((*value).to_owned(), datatype.iri.to_owned()),
+        }
+    }
+
+    fn as_term(&self) -> Term<'_> {
         match self {
-            NamedOrBlankNodeType::NamedNode => NamedNode {
-                iri: value,
+            OwnedTerm::NamedNode(iri) => NamedNode {
+                iri,
+            }
+            .into(),
+            OwnedTerm::BlankNode(id) => BlankNode {
+                id,
             }
             .into(),
-            NamedOrBlankNodeType::BlankNode => BlankNode {
-                id: value,
+            OwnedTerm::Simple(value) => Literal::Simple {
+                value,
+            }
+            .into(),
+            OwnedTerm::LanguageTaggedString(value, language) => Literal::LanguageTaggedString {
+                value,
+                language,
+            }
+            .into(),
+            OwnedTerm::Typed(value, datatype) => Literal::Typed {
+                value,
+                datatype: NamedNode {
+                    iri: datatype,
+                },
             }
             .into(),
         }
     }
 }
 
+/// Triples of one subject, collected in the buffered mode
+struct SubjectBlock {
+    subject: OwnedTerm,
+    predicates: Vec<(String, Vec<OwnedTerm>)>,
+}
+
 //////////////////////////////////////////////////////////////////////////////////////
 
+/// Metadata of an export, written as a comment block at the top of a dump
+pub struct ExportMeta {
+    pub tool_version: String,
+    /// unix time, in seconds
+    pub timestamp: i64,
+    pub source_count: usize,
+}
+
+/// Selection of the predicates written by the formatter
+#[derive(Default, Clone)]
+pub enum PredicateFilter {
+    #[default]
+    All,
+    /// triples with these predicates are dropped
+    Exclude(HashSet<String>),
+    /// only triples with these predicates are written
+    Only(HashSet<String>),
+}
+
+impl PredicateFilter {
+    pub fn is_allowed(&self, predicate: &str) -> bool {
+        match self {
+            PredicateFilter::All => true,
+            PredicateFilter::Exclude(set) => !set.contains(predicate),
+            PredicateFilter::Only(set) => set.contains(predicate),
+        }
+    }
+}
+
+/// The newline written by the formatter
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// Handling of the chars forbidden in XML 1.0 in the literals: the control chars other than tab, CR and LF
+/// (NUL included), U+FFFE and U+FFFF. Turtle accepts them, a strict consumer of RDF does not.
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub enum ForbiddenChars {
+    /// the chars are written as is
+    #[default]
+    Keep,
+    /// the chars are written as the `\uXXXX` escapes
+    Escape,
+    /// the formatting fails with `InvalidData`
+    Reject,
+}
+
+/// Options of the formatter output
+#[derive(Default, Clone)]
+pub struct TurtleStyle {
+    /// Prefixes declared first, in this order, the rest of the prefixes follows sorted alphabetically
+    pub prefix_order: Vec<String>,
+    /// Flush the underlying writer every time a subject block is completed
+    pub flush_per_subject: bool,
+    /// Buffer the triples until `finish` and write a blank node referenced only once
+    /// as a property list `[ ... ]` inside the object of the referencing triple.
+    /// Blank nodes of a reference cycle can not be inlined, they get the labels `_:b0`, `_:b1`, ...
+    pub inline_blank_nodes: bool,
+    /// With `inline_blank_nodes`, the limit of nested property lists: the blank nodes nested deeper
+    /// are written as separate subjects and referenced by their ids. 0 is no limit.
+    pub max_depth: usize,
+    pub predicate_filter: PredicateFilter,
+    /// Rewrite the blank node ids to document-local `_:b0`, `_:b1`, ... in the order of the first appearance
+    pub stable_blank_labels: bool,
+    /// Objects of a predicate which would make the line longer than this width are moved to a continuation line,
+    /// 0 disables the wrapping. Applies to the streaming mode, a single object longer than the width is never split.
+    pub max_line_width: usize,
+    /// Write integer, decimal and boolean literals as `"42"^^xsd:integer` instead of the shorthand `42`
+    pub always_explicit_datatype: bool,
+    /// Write every subject block on a single line, the blocks are separated by ` . `
+    pub compact: bool,
+    /// With `compact`, write the prefix declarations on the same line as the triples
+    pub compact_prefixes: bool,
+    /// Skip the triples with an empty predicate with a warning instead of failing with `InvalidInput`
+    pub tolerant: bool,
+    /// Every newline of the output, including the prefix block and the end written by `finish`
+    pub line_ending: LineEnding,
+    /// Write the predicate `rdf:type` as the keyword `a`, e.g. `[ a owl:Restriction ; owl:onProperty :x ]`
+    pub rdf_type_as_a: bool,
+    /// With `inline_blank_nodes`, the prefix block is written by `finish` and declares only the used prefixes
+    pub prune_unused_prefixes: bool,
+    /// With `inline_blank_nodes`, the predicates of every subject start with `rdf:type`, then `rdfs:label`,
+    /// the rest keeps the order of the triples
+    pub type_first: bool,
+    /// With `inline_blank_nodes`, the namespaces of the full IRIs (split at the last `#` or `/`) used at least
+    /// this many times get the prefixes `ns0`, `ns1`, ... and the IRIs are written as prefixed names,
+    /// the other full IRIs as `<...>`. 0 disables
+    pub discover_prefixes: usize,
+    /// A string with a newline is written as the long literal `"""..."""` if it is longer than this count of chars
+    /// or has at least 2 newlines, otherwise with the `\n` escapes. 0 always writes the escapes.
+    pub long_literal_threshold: usize,
+    /// The binary values are written as literals in this encoding by `to_turtle_with_style`, `None` skips them
+    pub binary_encoding: Option<BinaryEncoding>,
+    /// With `inline_blank_nodes`, the objects of every predicate are ordered by their kind: uris, blank nodes,
+    /// strings with a language sorted by the language, then the rest of literals, the order within a kind is kept
+    pub group_objects: bool,
+    /// The chars of literals forbidden in XML 1.0, `Keep` writes them as is
+    pub forbidden_chars: ForbiddenChars,
+}
+
 pub struct TurtleFormatterWithPrefixes<W: Write> {
     write: W,
+    style: TurtleStyle,
+    prefixes: HashMap<String, String>,
+    declared_prefixes: HashSet<String>,
+    used_prefixes: HashSet<String>,
+    is_prefixes_written: bool,
     current_subject: String,
     current_subject_type: Option<NamedOrBlankNodeType>,
     current_predicate: String,
+    buffer: Vec<(OwnedTerm, String, OwnedTerm)>,
+    blank_labels: HashMap<String, String>,
+    column: usize,
+    pub(crate) is_sparql_prefixes: bool,
 }
 
 impl<W: Write> TurtleFormatterWithPrefixes<W> {
-    /// Builds a new formatter from a `Write` implementation
+    /// Builds a new formatter from a `Write` implementation,
+    /// the prefixes are written before the first triple
     pub fn new(write: W, prefixes: &HashMap<String, String>) -> Self {
-        let mut f = TurtleFormatterWithPrefixes {
+        TurtleFormatterWithPrefixes::new_with_style(write, prefixes, TurtleStyle::default())
+    }
+
+    pub fn new_with_style(write: W, prefixes: &HashMap<String, String>, style: TurtleStyle) -> Self {
+        TurtleFormatterWithPrefixes {
             write,
+            style,
+            prefixes: prefixes.clone(),
+            declared_prefixes: prefixes.keys().cloned().collect(),
+            used_prefixes: HashSet::new(),
+            is_prefixes_written: false,
             current_subject: String::default(),
             current_subject_type: None,
             current_predicate: String::default(),
+            buffer: Vec::new(),
+            blank_labels: HashMap::new(),
+            column: 0,
+            is_sparql_prefixes: false,
+        }
+    }
+
+    pub(crate) fn get_mut_writer(&mut self) -> &mut W {
+        &mut self.write
+    }
+
+    fn is_buffered(&self) -> bool {
+        self.style.inline_blank_nodes
+    }
+
+    /// The declared prefixes used by the formatted triples, in subjects, predicates, objects and datatypes
+    pub fn used_prefixes(&self) -> &HashSet<String> {
+        &self.used_prefixes
+    }
+
+    fn note_used_prefix(&mut self, name: &str) {
+        if let Some((prefix, _)) = name.split_once(':') {
+            if self.declared_prefixes.contains(prefix) && !self.used_prefixes.contains(prefix) {
+                self.used_prefixes.insert(prefix.to_owned());
+            }
+        }
+    }
+
+    fn note_used_prefixes(&mut self, triple: &Triple<'_>) {
+        if let NamedOrBlankNode::NamedNode(n) = triple.subject {
+            self.note_used_prefix(n.iri);
+        }
+        if self.predicate_name(triple.predicate.iri) != "a" {
+            self.note_used_prefix(triple.predicate.iri);
+        }
+        match triple.object {
+            // `rdf:nil` is written as `()`
+            Term::NamedNode(n) if n.iri != "rdf:nil" => self.note_used_prefix(n.iri),
+            Term::Literal(Literal::Typed {
+                value,
+                datatype,
+            }) if self.style.always_explicit_datatype || !is_shorthand(value, datatype.iri) => self.note_used_prefix(datatype.iri),
+            _ => {}
+        }
+    }
+
+    fn is_prefixes_deferred(&self) -> bool {
+        self.is_buffered() && (self.style.prune_unused_prefixes || self.style.discover_prefixes > 0)
+    }
+
+    /// Declares the prefixes of the frequent namespaces of the buffered full IRIs and rewrites these IRIs
+    /// as prefixed names, the most used namespace gets `ns0`. The rest of the full IRIs is written as `<...>`,
+    /// `rdf:type`, `rdfs:label` and `rdf:nil` are never prefixed.
+    fn discover_prefixes(&mut self) {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (s, p, o) in self.buffer.iter() {
+            for iri in buffered_iris(s, p, o).filter(|iri| !WELL_KNOWN_IRIS.contains(iri)) {
+                if let Some((ns, _)) = split_namespace(iri) {
+                    *counts.entry(ns.to_owned()).or_default() += 1;
+                }
+            }
+        }
+
+        let known: HashSet<&String> = self.prefixes.values().collect();
+        let mut namespaces: Vec<(String, usize)> =
+            counts.into_iter().filter(|(ns, count)| *count >= self.style.discover_prefixes && !known.contains(ns)).collect();
+        namespaces.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut discovered: HashMap<String, String> = HashMap::new();
+        let mut n = 0;
+        for (ns, _) in namespaces {
+            while self.declared_prefixes.contains(&format!("ns{}", n)) {
+                n += 1;
+            }
+            let prefix = format!("ns{}", n);
+            self.prefixes.insert(prefix.clone(), ns.clone());
+            self.declared_prefixes.insert(prefix.clone());
+            self.used_prefixes.insert(prefix.clone());
+            discovered.insert(ns, prefix);
+        }
+
+        let compact = |iri: &mut String| {
+            // `rdf:nil` is written as `()`
+            if iri == RDF_NIL || !iri.contains("://") || iri.starts_with('<') {
+                return;
+            }
+            if let Some((ns, local)) = split_namespace(iri).filter(|_| !WELL_KNOWN_IRIS.contains(&iri.as_str())) {
+                if let Some(prefix) = discovered.get(ns) {
+                    *iri = format!("{}:{}", prefix, local);
+                    return;
+                }
+            }
+            *iri = format!("<{}>", iri);
         };
-        f.write_prefixes(prefixes).unwrap_or_default();
-        f
+        for (s, p, o) in self.buffer.iter_mut() {
+            if let OwnedTerm::NamedNode(iri) = s {
+                compact(iri);
+            }
+            // the predicate written as `a`
+            if !(self.style.rdf_type_as_a && p == RDF_TYPE) {
+                compact(p);
+            }
+            match o {
+                OwnedTerm::NamedNode(iri) | OwnedTerm::Typed(_, iri) => compact(iri),
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn eol(&self) -> &'static str {
+        self.style.line_ending.as_str()
+    }
+
+    /// Line break with the indent of the nesting depth, a space in the compact style
+    fn line_break(&self, depth: usize) -> String {
+        if self.style.compact {
+            " ".to_owned()
+        } else {
+            self.eol().to_owned() + &"  ".repeat(depth)
+        }
+    }
+
+    /// Separator between the subject and its first predicate
+    fn subject_start(&self) -> String {
+        if self.style.compact {
+            " ".to_owned()
+        } else {
+            format!(" {}  ", self.eol())
+        }
+    }
+
+    fn subject_end(&self) -> String {
+        if self.style.compact {
+            " . ".to_owned()
+        } else {
+            format!(" .{}{}", self.eol(), self.eol())
+        }
+    }
+
+    fn predicate_name<'a>(&self, iri: &'a str) -> &'a str {
+        if self.style.rdf_type_as_a && (iri == "rdf:type" || iri == RDF_TYPE) {
+            "a"
+        } else {
+            iri
+        }
+    }
+
+    /// The subject continues the open block only if it is of the same kind with the same value,
+    /// a blank node never continues the block of a named node with the equal string and vice versa
+    fn is_current_subject(&self, subject: &NamedOrBlankNode) -> bool {
+        match (self.current_subject_type, subject) {
+            (Some(NamedOrBlankNodeType::NamedNode), NamedOrBlankNode::NamedNode(n)) => n.iri == self.current_subject,
+            (Some(NamedOrBlankNodeType::BlankNode), NamedOrBlankNode::BlankNode(n)) => n.id == self.current_subject,
+            _ => false,
+        }
+    }
+
+    fn blank_label(&mut self, id: &str) -> String {
+        if !self.style.stable_blank_labels {
+            return id.to_owned();
+        }
+        let next = format!("b{}", self.blank_labels.len());
+        self.blank_labels.entry(id.to_owned()).or_insert(next).to_owned()
+    }
+
+    fn write_term(&mut self, t: OwnedTerm) -> Result<(), io::Error> {
+        match t {
+            OwnedTerm::BlankNode(id) => {
+                let label = self.blank_label(&id);
+                write!(self.write, "_:{}", label)
+            }
+            t => fmt_object(&t.as_term(), &self.style, &mut self.write),
+        }
     }
 
     pub fn write_prefixes(&mut self, prefixes: &HashMap<String, String>) -> Result<(), io::Error> {
-        let mut keys: Vec<&String> = prefixes.keys().collect();
-        keys.sort();
+        self.write_pending_prefixes()?;
+        self.declared_prefixes.extend(prefixes.keys().cloned());
+        self.write_prefix_block(prefixes)
+    }
+
+    pub(crate) fn write_pending_prefixes(&mut self) -> Result<(), io::Error> {
+        if !self.is_prefixes_written {
+            self.is_prefixes_written = true;
+            if self.is_buffered() && self.style.discover_prefixes > 0 {
+                self.discover_prefixes();
+            }
+            let mut prefixes = std::mem::take(&mut self.prefixes);
+            if self.is_buffered() && self.style.prune_unused_prefixes {
+                prefixes.retain(|prefix, _| self.used_prefixes.contains(prefix));
+            }
+            self.write_prefix_block(&prefixes)?;
+        }
+        Ok(())
+    }
+
+    fn write_prefix_block(&mut self, prefixes: &HashMap<String, String>) -> Result<(), io::Error> {
+        let mut keys: Vec<&String> = vec![];
+        for prefix in self.style.prefix_order.iter() {
+            if prefixes.contains_key(prefix) && !keys.contains(&prefix) {
+                keys.push(prefix);
+            }
+        }
+        let mut rest: Vec<&String> = prefixes.keys().filter(|k| !keys.contains(k)).collect();
+        rest.sort();
+        keys.append(&mut rest);
+
+        let is_inline = self.style.compact && self.style.compact_prefixes;
+        let eol = if is_inline {
+            " "
+        } else {
+            self.eol()
+        };
         for prefix in keys.iter() {
-            writeln!(self.write, "@prefix {}: <{}> .", prefix, prefixes.get(prefix.to_owned()).unwrap())?;
+            if self.is_sparql_prefixes {
+                write!(self.write, "PREFIX {}: <{}>{}", prefix, prefixes.get(prefix.to_owned()).unwrap(), eol)?;
+            } else {
+                write!(self.write, "@prefix {}: <{}> .{}", prefix, prefixes.get(prefix.to_owned()).unwrap(), eol)?;
+            }
+        }
+        if !is_inline {
+            write!(self.write, "{}", eol)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered triples and closes an open subject block
+    pub(crate) fn end_block(&mut self) -> Result<(), io::Error> {
+        if self.is_buffered() {
+            self.write_buffered()?;
+        }
+        if self.current_subject_type.is_some() {
+            write!(self.write, " .{}", self.eol())?;
+            self.current_subject_type = None;
+            self.current_subject.clear();
+            self.current_predicate.clear();
+        }
+        Ok(())
+    }
+
+    /// Writes a comment, every line of the text becomes a separate `#` line.
+    /// An open subject block is closed first.
+    pub fn write_comment(&mut self, comment: &str) -> Result<(), io::Error> {
+        if self.current_subject_type.is_some() {
+            write!(self.write, "{}", self.subject_end())?;
+            self.current_subject_type = None;
+            self.current_subject.clear();
+            self.current_predicate.clear();
+        }
+        for line in comment.lines() {
+            write!(self.write, "# {}{}", line, self.eol())?;
+        }
+        Ok(())
+    }
+
+    /// Writes the RDF-star statement about the quoted triple, `<< s p o >> predicate object .`, the quoted triple
+    /// itself is not asserted. The buffered triples and an open subject block are written first.
+    pub fn format_about_triple(&mut self, quoted: &Triple<'_>, predicate: NamedNode<'_>, object: &Term<'_>) -> Result<(), io::Error> {
+        self.close_pending()?;
+
+        self.note_used_prefixes(quoted);
+        let about = Triple {
+            subject: quoted.subject,
+            predicate,
+            object: *object,
+        };
+        self.note_used_prefixes(&about);
+
+        write!(self.write, "<< ")?;
+        self.write_subject(&quoted.subject)?;
+        write!(self.write, " {} ", self.predicate_name(quoted.predicate.iri))?;
+        self.write_object(&quoted.object)?;
+        write!(self.write, " >> {} ", self.predicate_name(predicate.iri))?;
+        self.write_object(object)?;
+        write!(self.write, "{}", self.subject_end())
+    }
+
+    /// Writes the N3 statement with the formula object, `subject predicate { s1 p1 o1 . s2 p2 o2 } .`,
+    /// the triples of the formula are not asserted. It is not turtle: the output is readable by N3 tools only.
+    /// The buffered triples and an open subject block are written first.
+    pub fn format_formula(&mut self, subject: NamedOrBlankNode<'_>, predicate: NamedNode<'_>, formula: &[Triple<'_>]) -> Result<(), io::Error> {
+        self.close_pending()?;
+
+        self.write_subject(&subject)?;
+        if let NamedOrBlankNode::NamedNode(n) = subject {
+            self.note_used_prefix(n.iri);
+        }
+        if self.predicate_name(predicate.iri) != "a" {
+            self.note_used_prefix(predicate.iri);
+        }
+        write!(self.write, " {} {{", self.predicate_name(predicate.iri))?;
+        for (idx, t) in formula.iter().enumerate() {
+            self.note_used_prefixes(t);
+            write!(
+                self.write,
+                "{}",
+                if idx == 0 {
+                    " "
+                } else {
+                    " . "
+                }
+            )?;
+            self.write_subject(&t.subject)?;
+            write!(self.write, " {} ", self.predicate_name(t.predicate.iri))?;
+            self.write_object(&t.object)?;
+        }
+        write!(self.write, " }}{}", self.subject_end())
+    }
+
+    /// Writes the buffered triples and closes an open subject block before a statement written on its own
+    fn close_pending(&mut self) -> Result<(), io::Error> {
+        self.write_pending_prefixes()?;
+        if self.is_buffered() {
+            self.write_buffered()?;
+        }
+        if self.current_subject_type.is_some() {
+            write!(self.write, "{}", self.subject_end())?;
+            self.current_subject_type = None;
+            self.current_subject.clear();
+            self.current_predicate.clear();
+        }
+        Ok(())
+    }
+
+    fn write_subject(&mut self, subject: &NamedOrBlankNode<'_>) -> Result<(), io::Error> {
+        match subject {
+            NamedOrBlankNode::NamedNode(n) => self.write.write_all(n.iri.as_bytes()),
+            NamedOrBlankNode::BlankNode(n) => {
+                let label = self.blank_label(n.id);
+                write!(self.write, "_:{}", label)
+            }
+        }
+    }
+
+    fn write_object(&mut self, object: &Term<'_>) -> Result<(), io::Error> {
+        match object {
+            Term::BlankNode(n) => {
+                let label = self.blank_label(n.id);
+                write!(self.write, "_:{}", label)
+            }
+            o => fmt_object(o, &self.style, &mut self.write),
+        }
+    }
+
+    /// `false` for a triple dropped by the predicate filter or skipped by the tolerant style
+    fn is_accepted(&self, triple: &Triple<'_>) -> Result<bool, io::Error> {
+        if triple.predicate.iri.is_empty() {
+            if self.style.tolerant {
+                warn!("turtle formatter: skip triple with empty predicate, subject={}", triple.subject);
+                return Ok(false);
+            }
+            return Err(Error::new(ErrorKind::InvalidInput, format!("empty predicate, subject={}", triple.subject)));
+        }
+        Ok(self.style.predicate_filter.is_allowed(triple.predicate.iri))
+    }
+
+    /// Writes the predicate and the object to the open subject block. Without an open block only the predicate
+    /// and the object are written, for a subject written by the caller, and the next calls continue this block.
+    /// Not supported in the buffered mode.
+    pub fn format_predicate(&mut self, predicate: NamedNode<'_>, object: &Term<'_>) -> Result<(), io::Error> {
+        if self.is_buffered() {
+            return Err(Error::new(ErrorKind::InvalidInput, "format_predicate is not supported in the buffered mode"));
+        }
+
+        let subject = self.current_subject.clone();
+        let triple = Triple {
+            subject: match self.current_subject_type {
+                Some(NamedOrBlankNodeType::BlankNode) => BlankNode {
+                    id: &subject,
+                }
+                .into(),
+                _ => NamedNode {
+                    iri: &subject,
+                }
+                .into(),
+            },
+            predicate,
+            object: *object,
+        };
+        if self.current_subject_type.is_some() {
+            return self.format(&triple);
         }
-        writeln!(self.write)?;
+
+        if !self.is_accepted(&triple)? {
+            return Ok(());
+        }
+        self.note_used_prefixes(&triple);
+        self.write_pending_prefixes()?;
+
+        let name = self.predicate_name(predicate.iri);
+        write!(self.write, "{} ", name)?;
+        self.column = 1 + name.chars().count();
+        let mut buf = Vec::new();
+        match object {
+            Term::BlankNode(n) => write!(buf, "_:{}", self.blank_label(n.id))?,
+            o => fmt_object(o, &self.style, &mut buf)?,
+        }
+        self.write.write_all(&buf)?;
+        self.column += String::from_utf8_lossy(&buf).chars().count();
+
+        // the implicit subject of the caller
+        self.current_subject_type = Some(NamedOrBlankNodeType::NamedNode);
+        self.current_subject.clear();
+        self.current_predicate.clear();
+        self.current_predicate.push_str(predicate.iri);
         Ok(())
     }
 
+    /// Writes the export metadata before the prefix declarations,
+    /// must be called before the first triple
+    pub fn write_header(&mut self, meta: &ExportMeta) -> Result<(), io::Error> {
+        if self.is_prefixes_written {
+            return Err(Error::new(ErrorKind::InvalidInput, "header must be written before prefixes and triples"));
+        }
+
+        let timestamp = match Utc.timestamp_opt(meta.timestamp, 0).single() {
+            Some(dt) => format!("{:?}", dt),
+            None => meta.timestamp.to_string(),
+        };
+
+        self.write_comment(&format!("tool version: {}\ntimestamp: {}\nsource count: {}", meta.tool_version, timestamp, meta.source_count))?;
+        write!(self.write, "{}", self.eol())?;
+        Ok(())
+    }
+
+    /// Formats every triple of the iterator, stops on the first error
+    pub fn format_all<'a, I: IntoIterator<Item = Triple<'a>>>(&mut self, triples: I) -> Result<(), io::Error> {
+        for triple in triples {
+            self.format(&triple)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the underlying `Write`
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.write.flush()
+    }
+
     /// Finishes to write and returns the underlying `Write`
     pub fn finish(mut self) -> Result<W, io::Error> {
+        self.write_pending_prefixes()?;
+        if self.is_buffered() {
+            self.write_buffered()?;
+        }
         if self.current_subject_type.is_some() {
-            writeln!(self.write, " .")?;
+            write!(self.write, " .{}", self.eol())?;
         }
         Ok(self.write)
     }
+
+    fn write_buffered(&mut self) -> Result<(), io::Error> {
+        let mut blocks: Vec<SubjectBlock> = vec![];
+        let mut block_of_subject: HashMap<OwnedTerm, usize> = HashMap::new();
+        for (subject, predicate, object) in std::mem::take(&mut self.buffer) {
+            let idx = *block_of_subject.entry(subject.clone()).or_insert_with(|| {
+                blocks.push(SubjectBlock {
+                    subject,
+                    predicates: vec![],
+                });
+                blocks.len() - 1
+            });
+            let predicates = &mut blocks[idx].predicates;
+            match predicates.iter_mut().find(|(p, _)| *p == predicate) {
+                Some((_, objects)) => objects.push(object),
+                None => predicates.push((predicate, vec![object])),
+            }
+        }
+
+        if self.style.type_first {
+            let rank = |predicate: &str| match predicate.trim_start_matches('<').trim_end_matches('>') {
+                "rdf:type" | RDF_TYPE => 0,
+                "rdfs:label" | RDFS_LABEL => 1,
+                _ => 2,
+            };
+            for block in blocks.iter_mut() {
+                block.predicates.sort_by_key(|(predicate, _)| rank(predicate));
+            }
+        }
+
+        if self.style.group_objects {
+            for block in blocks.iter_mut() {
+                for (_, objects) in block.predicates.iter_mut() {
+                    objects.sort_by(|a, b| object_rank(a).cmp(&object_rank(b)));
+                }
+            }
+        }
+
+        // blank node -> (count of references, block of the last reference)
+        let mut references: HashMap<&str, (usize, usize)> = HashMap::new();
+        for (idx, block) in blocks.iter().enumerate() {
+            for (_, objects) in block.predicates.iter() {
+                for o in objects.iter() {
+                    if let OwnedTerm::BlankNode(id) = o {
+                        let r = references.entry(id).or_insert((0, idx));
+                        r.0 += 1;
+                        r.1 = idx;
+                    }
+                }
+            }
+        }
+
+        // inline candidate -> block of its single reference
+        let mut parent: HashMap<usize, usize> = HashMap::new();
+        for (idx, block) in blocks.iter().enumerate() {
+            if let OwnedTerm::BlankNode(id) = &block.subject {
+                if let Some((1, p)) = references.get(id.as_str()) {
+                    parent.insert(idx, *p);
+                }
+            }
+        }
+
+        // a candidate is inlined only if the chain of its references ends at a written subject,
+        // the members of a cycle are written as subjects with explicit labels
+        let mut cyclic: Vec<usize> = vec![];
+        let mut candidates: Vec<usize> = parent.keys().copied().collect();
+        candidates.sort_unstable();
+        for start in candidates {
+            let mut path = vec![];
+            let mut cur = start;
+            while parent.contains_key(&cur) && !cyclic.contains(&cur) {
+                if let Some(pos) = path.iter().position(|x| *x == cur) {
+                    cyclic.extend_from_slice(&path[pos..]);
+                    break;
+                }
+                path.push(cur);
+                cur = parent[&cur];
+            }
+        }
+        for idx in cyclic.iter() {
+            parent.remove(idx);
+        }
+
+        // the shallowest candidates deeper than the limit become subjects, which resets the depth of their descendants
+        if self.style.max_depth > 0 {
+            loop {
+                let too_deep: Vec<usize> = parent
+                    .keys()
+                    .copied()
+                    .filter(|idx| {
+                        let mut depth = 1;
+                        let mut cur = parent[idx];
+                        while let Some(p) = parent.get(&cur) {
+                            depth += 1;
+                            cur = *p;
+                        }
+                        depth == self.style.max_depth + 1
+                    })
+                    .collect();
+                if too_deep.is_empty() {
+                    break;
+                }
+                for idx in too_deep.iter() {
+                    parent.remove(idx);
+                }
+            }
+        }
+
+        let mut used: HashSet<String> = references.keys().map(|id| (*id).to_owned()).collect();
+        for block in blocks.iter() {
+            if let OwnedTerm::BlankNode(id) = &block.subject {
+                used.insert(id.to_owned());
+            }
+        }
+        let mut cyclic_ids: Vec<&str> = cyclic
+            .iter()
+            .filter_map(|idx| match &blocks[*idx].subject {
+                OwnedTerm::BlankNode(id) => Some(id.as_str()),
+                _ => None,
+            })
+            .collect();
+        cyclic_ids.sort_unstable();
+        cyclic_ids.dedup();
+        let mut labels: HashMap<String, String> = HashMap::new();
+        let mut n = 0;
+        for id in cyclic_ids {
+            while used.contains(&format!("b{}", n)) {
+                n += 1;
+            }
+            labels.insert(id.to_owned(), format!("b{}", n));
+            used.insert(format!("b{}", n));
+        }
+
+        for (idx, block) in blocks.iter().enumerate() {
+            if parent.contains_key(&idx) {
+                continue;
+            }
+            if self.current_subject_type.is_some() {
+                write!(self.write, "{}", self.subject_end())?;
+                if self.style.flush_per_subject {
+                    self.write.flush()?;
+                }
+            }
+            self.current_subject_type = Some(NamedOrBlankNodeType::NamedNode);
+            self.write_term(relabel(&block.subject, &labels))?;
+            write!(self.write, "{}", self.subject_start())?;
+            self.write_predicates(&blocks, block, &parent, &labels, &block_of_subject, 1)?;
+        }
+        Ok(())
+    }
+
+    fn write_predicates(
+        &mut self,
+        blocks: &[SubjectBlock],
+        block: &SubjectBlock,
+        inlined: &HashMap<usize, usize>,
+        labels: &HashMap<String, String>,
+        block_of_subject: &HashMap<OwnedTerm, usize>,
+        depth: usize,
+    ) -> Result<(), io::Error> {
+        for (i, (predicate, objects)) in block.predicates.iter().enumerate() {
+            if i > 0 {
+                write!(self.write, " ;{}", self.line_break(depth))?;
+            }
+            write!(self.write, "{} ", self.predicate_name(predicate))?;
+            for (j, o) in objects.iter().enumerate() {
+                if j > 0 {
+                    write!(self.write, ", ")?;
+                }
+                match block_of_subject.get(o).filter(|idx| inlined.contains_key(idx)) {
+                    Some(idx) => {
+                        write!(self.write, "[{}", self.line_break(depth + 1))?;
+                        self.write_predicates(blocks, &blocks[*idx], inlined, labels, block_of_subject, depth + 1)?;
+                        write!(self.write, "{}]", self.line_break(depth))?;
+                    }
+                    None => self.write_term(relabel(o, labels))?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn relabel(t: &OwnedTerm, labels: &HashMap<String, String>) -> OwnedTerm {
+    match t {
+        OwnedTerm::BlankNode(id) => OwnedTerm::BlankNode(labels.get(id).unwrap_or(id).to_owned()),
+        _ => t.clone(),
+    }
 }
 
 impl<W: Write> TriplesFormatter for TurtleFormatterWithPrefixes<W> {
     type Error = io::Error;
 
     fn format(&mut self, triple: &Triple<'_>) -> Result<(), io::Error> {
+        if !self.is_accepted(triple)? {
+            return Ok(());
+        }
+        self.note_used_prefixes(triple);
+        if !self.is_prefixes_deferred() {
+            self.write_pending_prefixes()?;
+        }
+
+        if self.is_buffered() {
+            self.buffer.push((OwnedTerm::from_subject(&triple.subject), triple.predicate.iri.to_owned(), OwnedTerm::from_term(&triple.object)));
+            return Ok(());
+        }
+
+        let predicate = self.predicate_name(triple.predicate.iri);
         let s = match triple.subject {
-            NamedOrBlankNode::NamedNode(n) => n.iri,
-            NamedOrBlankNode::BlankNode(n) => n.id,
+            NamedOrBlankNode::NamedNode(n) => n.iri.to_owned(),
+            NamedOrBlankNode::BlankNode(n) => format!("_:{}", self.blank_label(n.id)),
         };
 
-        if let Some(current_subject_type) = self.current_subject_type {
-            let current_subject = current_subject_type.with_value(&self.current_subject);
-            if current_subject == triple.subject {
+        let mut object = Vec::new();
+        match triple.object {
+            Term::BlankNode(n) => write!(object, "_:{}", self.blank_label(n.id))?,
+            o => fmt_object(&o, &self.style, &mut object)?,
+        }
+        let object_width = String::from_utf8_lossy(&object).chars().count();
+
+        if self.current_subject_type.is_some() {
+            if self.is_current_subject(&triple.subject) {
                 if self.current_predicate == *triple.predicate.iri {
-                    write!(self.write, ", ")?;
+                    if !self.style.compact && self.style.max_line_width > 0 && self.column + 2 + object_width > self.style.max_line_width {
+                        write!(self.write, ",{}    ", self.eol())?;
+                        self.column = 4;
+                    } else {
+                        write!(self.write, ", ")?;
+                        self.column += 2;
+                    }
                 } else {
-                    write!(self.write, " ;\n  {} ", triple.predicate.iri)?;
+                    write!(self.write, " ;{}{} ", self.line_break(1), predicate)?;
+                    self.column = 3 + predicate.chars().count();
                 }
             } else {
-                write!(self.write, " .\n\n{} \n  {} ", &s, triple.predicate.iri)?;
+                write!(self.write, "{}", self.subject_end())?;
+                if self.style.flush_per_subject {
+                    self.write.flush()?;
+                }
+                write!(self.write, "{}{}{} ", &s, self.subject_start(), predicate)?;
+                self.column = 3 + predicate.chars().count();
             }
         } else {
-            write!(self.write, "{} \n  {} ", &s, triple.predicate.iri)?;
+            write!(self.write, "{}{}{} ", &s, self.subject_start(), predicate)?;
+            self.column = 3 + predicate.chars().count();
         }
-        fmt_object(&triple.object, &mut self.write)?;
+        self.write.write_all(&object)?;
+        self.column += object_width;
 
         self.current_subject.clear();
         match triple.subject {
@@ -113,6 +952,60 @@ fn escape(s: &str) -> impl Iterator<Item = char> + '_ {
     s.chars().flat_map(EscapeRDF::new)
 }
 
+/// Writes the string escaped, a string without the characters to escape is written as is
+fn write_escaped(s: &str, f: &mut dyn Write) -> Result<(), io::Error> {
+    if s.bytes().any(|b| matches!(b, b'\n' | b'\r' | b'"' | b'\\')) {
+        escape(s).try_for_each(|c| write!(f, "{}", c))
+    } else {
+        f.write_all(s.as_bytes())
+    }
+}
+
+fn is_forbidden_char(c: char) -> bool {
+    (c < ' ' && !matches!(c, '\t' | '\n' | '\r')) || c == '\u{FFFE}' || c == '\u{FFFF}'
+}
+
+/// The string in quotes, as the long literal `"""..."""` with the newlines kept by `long_literal_threshold`
+fn write_quoted(s: &str, style: &TurtleStyle, f: &mut dyn Write) -> Result<(), io::Error> {
+    let mut escape_forbidden = false;
+    if style.forbidden_chars != ForbiddenChars::Keep {
+        if let Some(c) = s.chars().find(|c| is_forbidden_char(*c)) {
+            if style.forbidden_chars == ForbiddenChars::Reject {
+                return Err(Error::new(ErrorKind::InvalidData, format!("forbidden char U+{:04X} in literal", c as u32)));
+            }
+            escape_forbidden = true;
+        }
+    }
+    let write_char = |c: char, f: &mut dyn Write| -> Result<(), io::Error> {
+        if escape_forbidden && is_forbidden_char(c) {
+            write!(f, "\\u{:04X}", c as u32)
+        } else {
+            EscapeRDF::new(c).try_for_each(|c| write!(f, "{}", c))
+        }
+    };
+
+    let newlines = s.matches('\n').count();
+    let is_long = style.long_literal_threshold > 0 && newlines > 0 && (newlines >= 2 || s.chars().count() > style.long_literal_threshold);
+    if !is_long {
+        f.write_all(b"\"")?;
+        if escape_forbidden {
+            s.chars().try_for_each(|c| write_char(c, f))?;
+        } else {
+            write_escaped(s, f)?;
+        }
+        return f.write_all(b"\"");
+    }
+
+    f.write_all(b"\"\"\"")?;
+    for c in s.chars() {
+        match c {
+            '\n' => f.write_all(b"\n")?,
+            c => write_char(c, f)?,
+        }
+    }
+    f.write_all(b"\"\"\"")
+}
+
 /// A customized version of EscapeDefault of the Rust standard library
 struct EscapeRDF {
     state: EscapeRdfState,
@@ -175,39 +1068,868 @@ impl ExactSizeIterator for EscapeRDF {
     }
 }
 
-fn fmt_object(o: &Term, f: &mut dyn Write) -> Result<(), io::Error> {
+const RDF_NIL: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#nil";
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const RDFS_LABEL: &str = "http://www.w3.org/2000/01/rdf-schema#label";
+/// The IRIs the formatter writes in a special way, they are not rewritten as names with discovered prefixes
+const WELL_KNOWN_IRIS: [&str; 3] = [RDF_NIL, RDF_TYPE, RDFS_LABEL];
+
+/// Integer, decimal and boolean literals which can be written without quotes and datatype
+fn is_shorthand(value: &str, datatype: &str) -> bool {
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit());
+    let unsigned = value.strip_prefix(|c| c == '+' || c == '-').unwrap_or(value);
+    match datatype {
+        "xsd:integer" | "http://www.w3.org/2001/XMLSchema#integer" => digits(unsigned),
+        "xsd:decimal" | "http://www.w3.org/2001/XMLSchema#decimal" => match unsigned.split_once('.') {
+            Some((int, frac)) => (int.is_empty() || digits(int)) && digits(frac),
+            None => false,
+        },
+        "xsd:boolean" | "http://www.w3.org/2001/XMLSchema#boolean" => value == "true" || value == "false",
+        _ => false,
+    }
+}
+
+/// Kind of the object for `group_objects`, the strings with a language are ranked by the language
+fn object_rank(o: &OwnedTerm) -> (u8, &str) {
+    match o {
+        OwnedTerm::NamedNode(_) => (0, ""),
+        OwnedTerm::BlankNode(_) => (1, ""),
+        OwnedTerm::LanguageTaggedString(_, lang) => (2, lang),
+        OwnedTerm::Simple(_) | OwnedTerm::Typed(_, _) => (3, ""),
+    }
+}
+
+/// The IRIs of the subject, the predicate, the object and the datatype of the buffered triple
+fn buffered_iris<'a>(s: &'a OwnedTerm, p: &'a str, o: &'a OwnedTerm) -> impl Iterator<Item = &'a str> {
+    let s = match s {
+        OwnedTerm::NamedNode(iri) => Some(iri.as_str()),
+        _ => None,
+    };
+    let o = match o {
+        OwnedTerm::NamedNode(iri) | OwnedTerm::Typed(_, iri) => Some(iri.as_str()),
+        _ => None,
+    };
+    s.into_iter().chain(Some(p)).chain(o)
+}
+
+/// The namespace and the local name of the full IRI, split after the last `#` or `/`,
+/// `None` for a prefixed name or a local name which can not be written after a prefix
+fn split_namespace(iri: &str) -> Option<(&str, &str)> {
+    if !iri.contains("://") {
+        return None;
+    }
+    let pos = iri.rfind(['#', '/'])? + 1;
+    let (ns, local) = iri.split_at(pos);
+    let is_local_name = local.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') && !local.starts_with('-');
+    if is_local_name {
+        Some((ns, local))
+    } else {
+        None
+    }
+}
+
+fn fmt_object(o: &Term, style: &TurtleStyle, f: &mut dyn Write) -> Result<(), io::Error> {
     match o {
+        // the empty collection
+        Term::NamedNode(n) if n.iri == "rdf:nil" || n.iri == RDF_NIL => {
+            f.write_all(b"()")?;
+        }
         Term::NamedNode(n) => {
             f.write_all(n.iri.as_bytes())?;
         }
         Term::BlankNode(n) => {
-            f.write_all(n.id.as_bytes())?;
+            write!(f, "_:{}", n.id)?;
         }
         Term::Literal(v) => match v {
             Literal::Simple {
                 value,
             } => {
-                f.write_all(b"\"")?;
-                escape(value).try_for_each(|c| write!(f, "{}", c))?;
-                f.write_all(b"\"")?;
+                write_quoted(value, style, f)?;
             }
             Literal::LanguageTaggedString {
                 value,
                 language,
             } => {
-                f.write_all(b"\"")?;
-                escape(value).try_for_each(|c| write!(f, "{}", c))?;
-                write!(f, "\"@{}", language)?;
+                write_quoted(value, style, f)?;
+                write!(f, "@{}", language)?;
+            }
+            Literal::Typed {
+                value,
+                datatype,
+            } if !style.always_explicit_datatype && is_shorthand(value, datatype.iri) => {
+                f.write_all(value.as_bytes())?;
             }
             Literal::Typed {
                 value,
                 datatype,
             } => {
-                f.write_all(b"\"")?;
-                escape(value).try_for_each(|c| write!(f, "{}", c))?;
-                write!(f, "\"^^{}", datatype.iri)?;
+                write_quoted(value, style, f)?;
+                write!(f, "^^{}", datatype.iri)?;
             }
         },
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rio_api::parser::TriplesParser;
+    use rio_turtle::{TurtleError, TurtleParser};
+
+    fn prefixes() -> HashMap<String, String> {
+        let mut res = HashMap::new();
+        res.insert("d".to_owned(), "http://example.org/d#".to_owned());
+        res.insert("v-s".to_owned(), "http://example.org/v-s#".to_owned());
+        res.insert("rdf".to_owned(), "http://www.w3.org/1999/02/22-rdf-syntax-ns#".to_owned());
+        res.insert("xsd".to_owned(), "http://www.w3.org/2001/XMLSchema#".to_owned());
+        res
+    }
+
+    fn triple<'a>(subject: &'a str, predicate: &'a str, object: Term<'a>) -> Triple<'a> {
+        Triple {
+            subject: NamedNode {
+                iri: subject,
+            }
+            .into(),
+            predicate: NamedNode {
+                iri: predicate,
+            },
+            object,
+        }
+    }
+
+    fn uri(iri: &str) -> Term<'_> {
+        NamedNode {
+            iri,
+        }
+        .into()
+    }
+
+    fn string(value: &str) -> Term<'_> {
+        Literal::Simple {
+            value,
+        }
+        .into()
+    }
+
+    fn format_with(style: TurtleStyle, triples: &[Triple<'_>]) -> String {
+        let mut formatter = TurtleFormatterWithPrefixes::new_with_style(Vec::new(), &prefixes(), style);
+        formatter.format_all(triples.iter().cloned()).unwrap();
+        String::from_utf8(formatter.finish().unwrap()).unwrap()
+    }
+
+    /// Triples of the turtle text in the N-Triples form, sorted
+    fn reparse(text: &str) -> Vec<String> {
+        let mut res = vec![];
+        TurtleParser::new(text.as_bytes(), None)
+            .parse_all(&mut |t| -> Result<(), TurtleError> {
+                res.push(t.to_string());
+                Ok(())
+            })
+            .unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        res.sort();
+        res
+    }
+
+    #[test]
+    fn format_all_writes_every_triple_and_stops_on_error() {
+        let triples = [triple("d:a", "rdf:type", uri("v-s:Document")), triple("d:a", "v-s:name", string("a")), triple("d:b", "v-s:name", string("b"))];
+        let out = format_with(TurtleStyle::default(), &triples);
+        assert_eq!(reparse(&out).len(), 3);
+
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        let res = formatter.format_all(vec![triple("d:a", "v-s:name", string("a")), triple("d:a", "", string("b")), triple("d:c", "v-s:name", string("c"))]);
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidInput);
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        assert!(out.contains("d:a"));
+        assert!(!out.contains("d:c"));
+    }
+
+    #[test]
+    fn header_precedes_prefixes() {
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        formatter
+            .write_header(&ExportMeta {
+                tool_version: "1.2.3".to_owned(),
+                timestamp: 0,
+                source_count: 7,
+            })
+            .unwrap();
+        formatter.format(&triple("d:a", "v-s:name", string("a"))).unwrap();
+        assert_eq!(
+            formatter
+                .write_header(&ExportMeta {
+                    tool_version: "1.2.3".to_owned(),
+                    timestamp: 0,
+                    source_count: 7,
+                })
+                .unwrap_err()
+                .kind(),
+            ErrorKind::InvalidInput
+        );
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "# tool version: 1.2.3");
+        assert_eq!(lines[1], "# timestamp: 1970-01-01T00:00:00Z");
+        assert_eq!(lines[2], "# source count: 7");
+        let first_prefix = lines.iter().position(|l| l.starts_with("@prefix")).unwrap();
+        assert!(lines[..first_prefix].iter().all(|l| l.starts_with('#') || l.is_empty()));
+        assert_eq!(reparse(&out).len(), 1);
+    }
+
+    fn declared_prefixes(out: &str) -> Vec<&str> {
+        out.lines().filter_map(|l| l.strip_prefix("@prefix ")).filter_map(|l| l.split(':').next()).collect()
+    }
+
+    #[test]
+    fn preferred_prefixes_are_written_first() {
+        let triples = [triple("d:a", "v-s:name", string("a"))];
+        assert_eq!(declared_prefixes(&format_with(TurtleStyle::default(), &triples)), vec!["d", "rdf", "v-s", "xsd"]);
+
+        let style = TurtleStyle {
+            prefix_order: vec!["xsd".to_owned(), "rdf".to_owned(), "owl".to_owned(), "xsd".to_owned()],
+            ..TurtleStyle::default()
+        };
+        let out = format_with(style, &triples);
+        assert_eq!(declared_prefixes(&out), vec!["xsd", "rdf", "d", "v-s"]);
+        assert_eq!(reparse(&out).len(), 1);
+    }
+
+    /// Writer which records the length of the written data at every flush
+    #[derive(Default)]
+    struct FlushRecorder {
+        data: Vec<u8>,
+        flushes: Vec<usize>,
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes.push(self.data.len());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn flush_per_subject() {
+        let triples = [triple("d:a", "v-s:name", string("a")), triple("d:a", "v-s:name", string("a2")), triple("d:b", "v-s:name", string("b")), triple("d:c", "v-s:name", string("c"))];
+
+        let mut formatter = TurtleFormatterWithPrefixes::new(FlushRecorder::default(), &prefixes());
+        formatter.format_all(triples.iter().cloned()).unwrap();
+        assert!(formatter.get_mut_writer().flushes.is_empty());
+        formatter.flush().unwrap();
+        assert_eq!(formatter.finish().unwrap().flushes.len(), 1);
+
+        let style = TurtleStyle {
+            flush_per_subject: true,
+            ..TurtleStyle::default()
+        };
+        let mut formatter = TurtleFormatterWithPrefixes::new_with_style(FlushRecorder::default(), &prefixes(), style);
+        formatter.format_all(triples.iter().cloned()).unwrap();
+        let write = formatter.finish().unwrap();
+        let out = String::from_utf8(write.data).unwrap();
+        // a flush after the blocks of d:a and d:b, each block is complete when flushed
+        assert_eq!(write.flushes.len(), 2);
+        assert!(out[..write.flushes[0]].ends_with(" .\n\n"));
+        assert!(out[..write.flushes[0]].contains("\"a2\""));
+        assert!(!out[..write.flushes[0]].contains("d:b"));
+        assert!(out[..write.flushes[1]].contains("\"b\""));
+        assert_eq!(reparse(&out).len(), 4);
+    }
+
+    fn blank_triple<'a>(subject: &'a str, predicate: &'a str, object: Term<'a>) -> Triple<'a> {
+        Triple {
+            subject: BlankNode {
+                id: subject,
+            }
+            .into(),
+            predicate: NamedNode {
+                iri: predicate,
+            },
+            object,
+        }
+    }
+
+    fn blank(id: &str) -> Term<'_> {
+        BlankNode {
+            id,
+        }
+        .into()
+    }
+
+    fn inline_style() -> TurtleStyle {
+        TurtleStyle {
+            inline_blank_nodes: true,
+            ..TurtleStyle::default()
+        }
+    }
+
+    #[test]
+    fn blank_cycle_falls_back_to_labels() {
+        let triples = [
+            blank_triple("x", "v-s:next", blank("y")),
+            blank_triple("y", "v-s:next", blank("x")),
+            triple("d:a", "v-s:author", blank("b0")),
+            blank_triple("b0", "v-s:name", string("p")),
+            triple("d:a", "v-s:first", blank("z")),
+            blank_triple("z", "v-s:next", blank("z2")),
+            blank_triple("z2", "v-s:next", blank("z")),
+        ];
+        let out = format_with(inline_style(), &triples);
+
+        // x and y are referenced once each, only from the cycle: both are labeled subjects,
+        // the labels skip the id `b0` of the data
+        assert!(out.contains("_:b1 \n  v-s:next _:b2 ."), "{}", out);
+        assert!(out.contains("_:b2 \n  v-s:next _:b1 ."), "{}", out);
+        // the blank node out of the cycle is inlined
+        assert!(out.contains("v-s:author [\n    v-s:name \"p\"\n  ]"), "{}", out);
+        // z is referenced twice, so it is a subject, and its single reference z2 is inlined into it
+        assert!(out.contains("_:z \n  v-s:next [\n    v-s:next _:z\n  ]"), "{}", out);
+        assert_eq!(format_with(inline_style(), &triples), out);
+
+        let parsed = reparse(&out);
+        assert_eq!(parsed.len(), 7);
+        let next: Vec<(&str, &str)> = parsed
+            .iter()
+            .filter(|t| t.contains("v-s#next"))
+            .map(|t| {
+                let parts: Vec<&str> = t.split(' ').collect();
+                (parts[0], parts[2])
+            })
+            .collect();
+        assert_eq!(next.len(), 4);
+        // every node of the cycles links to a node which links back to it
+        for (s, o) in next.iter() {
+            assert!(next.contains(&(*o, *s)));
+            assert_ne!(s, o);
+        }
+    }
+
+    #[test]
+    fn stable_blank_labels_by_first_appearance() {
+        let triples = [
+            triple("d:a", "v-s:author", blank("genid-9f")),
+            blank_triple("genid-9f", "v-s:knows", blank("genid-1a")),
+            blank_triple("genid-1a", "v-s:name", string("p")),
+            triple("d:b", "v-s:author", blank("genid-9f")),
+        ];
+        let style = TurtleStyle {
+            stable_blank_labels: true,
+            ..TurtleStyle::default()
+        };
+        let out = format_with(style, &triples);
+        assert!(!out.contains("genid"), "{}", out);
+        assert!(out.contains("d:a \n  v-s:author _:b0 ."), "{}", out);
+        assert!(out.contains("_:b0 \n  v-s:knows _:b1 ."), "{}", out);
+        assert!(out.contains("_:b1 \n  v-s:name \"p\" ."), "{}", out);
+        assert!(out.contains("d:b \n  v-s:author _:b0 ."), "{}", out);
+        assert_eq!(reparse(&out).len(), 4);
+    }
+
+    #[test]
+    fn long_object_lists_are_wrapped() {
+        let values: Vec<String> = (0..40).map(|n| format!("значение-{}", n)).collect();
+        let mut triples: Vec<Triple<'_>> = values.iter().map(|v| triple("d:a", "v-s:tag", string(v))).collect();
+        triples.push(triple("d:a", "v-s:name", string("a")));
+        triples.extend(values.iter().map(|v| triple("d:b", "v-s:tag", string(v))));
+        let style = TurtleStyle {
+            max_line_width: 40,
+            ..TurtleStyle::default()
+        };
+        let out = format_with(style, &triples);
+
+        let body: Vec<&str> = out.lines().filter(|l| !l.starts_with("@prefix")).collect();
+        assert!(body.iter().all(|l| l.chars().count() <= 40), "{}", out);
+        assert!(body.iter().filter(|l| l.starts_with("    \"")).count() > 20, "{}", out);
+        let parsed = reparse(&out);
+        assert_eq!(parsed.len(), 81);
+
+        // without the width every object list is on one line
+        let out = format_with(TurtleStyle::default(), &triples);
+        assert!(out.lines().any(|l| l.chars().count() > 400));
+        assert_eq!(reparse(&out), parsed);
+    }
+
+    fn typed<'a>(value: &'a str, datatype: &'a str) -> Term<'a> {
+        Literal::Typed {
+            value,
+            datatype: NamedNode {
+                iri: datatype,
+            },
+        }
+        .into()
+    }
+
+    #[test]
+    fn explicit_datatype_of_integer() {
+        let triples = [triple("d:a", "v-s:count", typed("42", "xsd:integer")), triple("d:a", "v-s:flag", typed("true", "xsd:boolean"))];
+        let short = format_with(TurtleStyle::default(), &triples);
+        assert!(short.contains("v-s:count 42 ;"), "{}", short);
+        assert!(short.contains("v-s:flag true ."), "{}", short);
+
+        let style = TurtleStyle {
+            always_explicit_datatype: true,
+            ..TurtleStyle::default()
+        };
+        let explicit = format_with(style, &triples);
+        assert!(explicit.contains("v-s:count \"42\"^^xsd:integer ;"), "{}", explicit);
+        assert!(explicit.contains("v-s:flag \"true\"^^xsd:boolean ."), "{}", explicit);
+        assert_eq!(reparse(&short), reparse(&explicit));
+    }
+
+    #[test]
+    fn empty_collection_roundtrip() {
+        let triples = [triple("d:a", "v-s:items", uri("rdf:nil")), triple("d:b", "v-s:items", uri(RDF_NIL))];
+        let out = format_with(TurtleStyle::default(), &triples);
+        assert!(out.contains("d:a \n  v-s:items () ."), "{}", out);
+        assert!(out.contains("d:b \n  v-s:items () ."), "{}", out);
+        assert_eq!(
+            reparse(&out),
+            vec![
+                format!("<http://example.org/d#a> <http://example.org/v-s#items> <{}> .", RDF_NIL),
+                format!("<http://example.org/d#b> <http://example.org/v-s#items> <{}> .", RDF_NIL)
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_style_is_single_line() {
+        let triples = [
+            triple("d:a", "rdf:type", uri("v-s:Document")),
+            triple("d:a", "v-s:name", string("a")),
+            triple("d:a", "v-s:name", string("b")),
+            triple("d:b", "v-s:name", string("c")),
+        ];
+        let expected = reparse(&format_with(TurtleStyle::default(), &triples));
+
+        let style = TurtleStyle {
+            compact: true,
+            ..TurtleStyle::default()
+        };
+        let out = format_with(style, &triples);
+        let body: Vec<&str> = out.lines().filter(|l| !l.is_empty() && !l.starts_with("@prefix")).collect();
+        assert_eq!(body.len(), 1, "{}", out);
+        assert_eq!(reparse(&out), expected);
+
+        let style = TurtleStyle {
+            compact: true,
+            compact_prefixes: true,
+            ..TurtleStyle::default()
+        };
+        let out = format_with(style, &triples);
+        assert_eq!(out.trim_end().lines().count(), 1, "{}", out);
+        assert_eq!(reparse(&out), expected);
+    }
+
+    #[test]
+    fn empty_predicate_is_an_error() {
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        assert_eq!(formatter.format(&triple("d:a", "", string("a"))).unwrap_err().kind(), ErrorKind::InvalidInput);
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        assert!(!out.contains("d:a"), "{}", out);
+
+        let style = TurtleStyle {
+            tolerant: true,
+            ..TurtleStyle::default()
+        };
+        let out = format_with(style, &[triple("d:a", "v-s:name", string("a")), triple("d:a", "", string("b"))]);
+        assert_eq!(reparse(&out), vec!["<http://example.org/d#a> <http://example.org/v-s#name> \"a\" ."]);
+    }
+
+    #[test]
+    fn crlf_line_endings_everywhere() {
+        let triples = [
+            triple("d:a", "rdf:type", uri("v-s:Document")),
+            triple("d:a", "v-s:name", string("a")),
+            triple("d:a", "v-s:author", blank("p")),
+            blank_triple("p", "v-s:name", string("p")),
+            blank_triple("p", "v-s:age", typed("42", "xsd:integer")),
+            triple("d:b", "v-s:name", string("b")),
+        ];
+        let style = TurtleStyle {
+            line_ending: LineEnding::Crlf,
+            ..inline_style()
+        };
+        let mut formatter = TurtleFormatterWithPrefixes::new_with_style(Vec::new(), &prefixes(), style);
+        formatter
+            .write_header(&ExportMeta {
+                tool_version: "1.2.3".to_owned(),
+                timestamp: 0,
+                source_count: 2,
+            })
+            .unwrap();
+        formatter.format_all(triples.iter().cloned()).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+
+        assert!(out.contains("\r\n"));
+        assert_eq!(out.matches('\n').count(), out.matches("\r\n").count(), "{:?}", out);
+        assert_eq!(reparse(&out), reparse(&format_with(inline_style(), &triples)));
+        assert_eq!(out.replace("\r\n", "\n").lines().filter(|l| l.starts_with("# ")).count(), 3);
+    }
+
+    #[test]
+    fn blank_nodes_deeper_than_max_depth_are_referenced() {
+        let triples = [
+            triple("d:a", "v-s:part", blank("x")),
+            blank_triple("x", "v-s:part", blank("y")),
+            blank_triple("y", "v-s:part", blank("z")),
+            blank_triple("z", "v-s:name", string("z")),
+        ];
+        let nested = format_with(inline_style(), &triples);
+        assert_eq!(nested.matches('[').count(), 3, "{}", nested);
+        assert!(!nested.contains("_:"), "{}", nested);
+
+        let style = TurtleStyle {
+            max_depth: 2,
+            ..inline_style()
+        };
+        let out = format_with(style, &triples);
+        assert_eq!(out.matches('[').count(), 2, "{}", out);
+        assert_eq!(out.matches("_:").count(), 2, "{}", out);
+        assert_eq!(reparse(&out).len(), 4);
+    }
+
+    #[test]
+    fn restriction_as_nested_property_list() {
+        let triples = [
+            triple("d:Document", "rdf:type", uri("owl:Class")),
+            triple("d:Document", "rdfs:subClassOf", blank("r")),
+            blank_triple("r", "rdf:type", uri("owl:Restriction")),
+            blank_triple("r", "owl:onProperty", uri("v-s:author")),
+            blank_triple("r", "owl:someValuesFrom", blank("v")),
+            blank_triple("v", "rdf:type", uri("owl:Class")),
+            blank_triple("v", "owl:unionOf", uri("rdf:nil")),
+        ];
+        let mut all = prefixes();
+        all.insert("owl".to_owned(), "http://www.w3.org/2002/07/owl#".to_owned());
+        all.insert("rdfs".to_owned(), "http://www.w3.org/2000/01/rdf-schema#".to_owned());
+        let style = TurtleStyle {
+            rdf_type_as_a: true,
+            ..inline_style()
+        };
+        let mut formatter = TurtleFormatterWithPrefixes::new_with_style(Vec::new(), &all, style);
+        formatter.format_all(triples.iter().cloned()).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        let body = &out[out.find("d:Document").unwrap()..];
+        assert_eq!(
+            body,
+            "d:Document \n  a owl:Class ;\n  rdfs:subClassOf [\n    a owl:Restriction ;\n    owl:onProperty v-s:author ;\n    \
+             owl:someValuesFrom [\n      a owl:Class ;\n      owl:unionOf ()\n    ]\n  ] .\n"
+        );
+        assert_eq!(reparse(&out).len(), 7);
+    }
+
+    #[test]
+    fn unused_prefixes_are_reported_and_pruned() {
+        let triples = [
+            triple("d:a", "v-s:name", string("a")),
+            triple("d:a", "v-s:count", typed("42", "xsd:integer")),
+            triple("d:a", "v-s:items", uri("rdf:nil")),
+        ];
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        formatter.format_all(triples.iter().cloned()).unwrap();
+        let used: HashSet<String> = ["d".to_owned(), "v-s".to_owned()].iter().cloned().collect();
+        assert_eq!(formatter.used_prefixes(), &used);
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        assert_eq!(declared_prefixes(&out), vec!["d", "rdf", "v-s", "xsd"]);
+
+        let style = TurtleStyle {
+            prune_unused_prefixes: true,
+            ..inline_style()
+        };
+        let out = format_with(style, &triples);
+        assert_eq!(declared_prefixes(&out), vec!["d", "v-s"]);
+        assert_eq!(reparse(&out), reparse(&format_with(TurtleStyle::default(), &triples)));
+    }
+
+    #[test]
+    fn statement_about_quoted_triple() {
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        formatter.format(&triple("d:a", "v-s:name", string("a"))).unwrap();
+        let quoted = triple("d:a", "v-s:author", uri("d:p"));
+        formatter.format_about_triple(&quoted, NamedNode { iri: "v-s:source" }, &uri("d:import")).unwrap();
+        formatter.format(&triple("d:b", "v-s:name", string("b"))).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        let body = &out[out.find("d:a").unwrap()..];
+        assert_eq!(body, "d:a \n  v-s:name \"a\" .\n\n<< d:a v-s:author d:p >> v-s:source d:import .\n\nd:b \n  v-s:name \"b\" .\n");
+        // the quoted triple is not asserted
+        assert_eq!(reparse(&out.replace("<< d:a v-s:author d:p >> v-s:source d:import .", "")).len(), 2);
+    }
+
+    #[test]
+    fn subject_block_of_format_predicate_calls() {
+        let name = NamedNode {
+            iri: "v-s:name",
+        };
+        let count = NamedNode {
+            iri: "v-s:count",
+        };
+
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        formatter.format(&triple("d:a", "rdf:type", uri("v-s:Document"))).unwrap();
+        formatter.format_predicate(name, &string("a")).unwrap();
+        formatter.format_predicate(count, &typed("1", "xsd:integer")).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        let expected = [triple("d:a", "rdf:type", uri("v-s:Document")), triple("d:a", "v-s:name", string("a")), triple("d:a", "v-s:count", typed("1", "xsd:integer"))];
+        assert_eq!(out, format_with(TurtleStyle::default(), &expected));
+
+        // the subject is written by the caller
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        formatter.format_predicate(name, &string("a")).unwrap();
+        formatter.format_predicate(count, &typed("1", "xsd:integer")).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        let pos = out.find("v-s:name").unwrap();
+        assert_eq!(&out[pos..], "v-s:name \"a\" ;\n  v-s:count 1 .\n");
+        assert_eq!(reparse(&format!("{}d:s {}", &out[..pos], &out[pos..])).len(), 2);
+
+        let mut formatter = TurtleFormatterWithPrefixes::new_with_style(Vec::new(), &prefixes(), inline_style());
+        assert_eq!(formatter.format_predicate(name, &string("a")).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn type_leads_the_block() {
+        let triples = [
+            triple("d:a", "v-s:name", string("a")),
+            triple("d:a", "rdfs:label", string("label")),
+            triple("d:a", "v-s:count", typed("1", "xsd:integer")),
+            triple("d:a", "rdf:type", uri("v-s:Document")),
+            blank_triple("p", "v-s:name", string("p")),
+            blank_triple("p", "rdf:type", uri("v-s:Person")),
+            triple("d:a", "v-s:author", blank("p")),
+        ];
+        let mut all = prefixes();
+        all.insert("rdfs".to_owned(), "http://www.w3.org/2000/01/rdf-schema#".to_owned());
+        let style = TurtleStyle {
+            type_first: true,
+            rdf_type_as_a: true,
+            ..inline_style()
+        };
+        let mut formatter = TurtleFormatterWithPrefixes::new_with_style(Vec::new(), &all, style);
+        formatter.format_all(triples.iter().cloned()).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        let body = &out[out.find("d:a").unwrap()..];
+        assert_eq!(
+            body,
+            "d:a \n  a v-s:Document ;\n  rdfs:label \"label\" ;\n  v-s:name \"a\" ;\n  v-s:count 1 ;\n  \
+             v-s:author [\n    a v-s:Person ;\n    v-s:name \"p\"\n  ] .\n"
+        );
+        assert_eq!(reparse(&out).len(), 7);
+    }
+
+    #[test]
+    fn formula_object() {
+        let mut formatter = TurtleFormatterWithPrefixes::new(Vec::new(), &prefixes());
+        formatter.format(&triple("d:rule", "rdf:type", uri("v-s:Rule"))).unwrap();
+        let subject = NamedNode {
+            iri: "d:rule",
+        }
+        .into();
+        let predicate = NamedNode {
+            iri: "v-s:premise",
+        };
+        formatter.format_formula(subject, predicate, &[triple("d:a", "d:b", uri("d:c"))]).unwrap();
+        formatter.format_formula(subject, predicate, &[triple("d:a", "d:b", uri("d:c")), triple("d:c", "v-s:name", string("c"))]).unwrap();
+        let out = String::from_utf8(formatter.finish().unwrap()).unwrap();
+        let body = &out[out.find("d:rule").unwrap()..];
+        assert_eq!(
+            body,
+            "d:rule \n  rdf:type v-s:Rule .\n\nd:rule v-s:premise { d:a d:b d:c } .\n\nd:rule v-s:premise { d:a d:b d:c . d:c v-s:name \"c\" } .\n\n"
+        );
+    }
+
+    #[test]
+    fn long_literal_threshold_on_both_sides() {
+        let short = "ab\ncd";
+        let long = "a longer line\nand the next one";
+        let two_lines = "a\nb\nc";
+        let triples = [triple("d:a", "v-s:short", string(short)), triple("d:a", "v-s:long", string(long)), triple("d:a", "v-s:lines", string(two_lines))];
+
+        let style = TurtleStyle {
+            long_literal_threshold: 10,
+            ..TurtleStyle::default()
+        };
+        let out = format_with(style, &triples);
+        assert!(out.contains("v-s:short \"ab\\ncd\""), "{}", out);
+        assert!(out.contains("v-s:long \"\"\"a longer line\nand the next one\"\"\""), "{}", out);
+        assert!(out.contains("v-s:lines \"\"\"a\nb\nc\"\"\""), "{}", out);
+        let escaped = format_with(TurtleStyle::default(), &triples);
+        assert!(!escaped.contains("\"\"\""), "{}", escaped);
+        assert_eq!(reparse(&out), reparse(&escaped));
+
+        // the count of chars equal to the threshold is not over it
+        let style = TurtleStyle {
+            long_literal_threshold: short.chars().count(),
+            ..TurtleStyle::default()
+        };
+        assert!(format_with(style, &triples[..1]).contains("\"ab\\ncd\""));
+        let style = TurtleStyle {
+            long_literal_threshold: short.chars().count() - 1,
+            ..TurtleStyle::default()
+        };
+        assert!(format_with(style, &triples[..1]).contains("\"\"\"ab\ncd\"\"\""));
+    }
+
+    #[test]
+    fn objects_grouped_by_kind() {
+        let lang = |value, language| -> Term<'_> {
+            Literal::LanguageTaggedString {
+                value,
+                language,
+            }
+            .into()
+        };
+        let triples = [
+            triple("d:a", "v-s:value", typed("1", "xsd:integer")),
+            triple("d:a", "v-s:value", lang("b", "ru")),
+            triple("d:a", "v-s:value", uri("d:x")),
+            triple("d:a", "v-s:value", string("plain")),
+            triple("d:a", "v-s:value", lang("a", "en")),
+            triple("d:a", "v-s:value", uri("d:y")),
+        ];
+        let style = TurtleStyle {
+            group_objects: true,
+            ..inline_style()
+        };
+        let out = format_with(style, &triples);
+        let body = &out[out.find("d:a").unwrap()..];
+        assert_eq!(body, "d:a \n  v-s:value d:x, d:y, \"a\"@en, \"b\"@ru, 1, \"plain\" .\n");
+        assert_eq!(reparse(&out), reparse(&format_with(TurtleStyle::default(), &triples)));
+    }
+
+    #[test]
+    fn nul_in_value_rejected_or_escaped() {
+        let triples = [triple("d:a", "v-s:name", string("a\0b")), triple("d:a", "v-s:text", string("one\ntwo\nthree\0"))];
+        let style = |forbidden_chars| TurtleStyle {
+            forbidden_chars,
+            long_literal_threshold: 1,
+            ..TurtleStyle::default()
+        };
+
+        let mut formatter = TurtleFormatterWithPrefixes::new_with_style(Vec::new(), &prefixes(), style(ForbiddenChars::Reject));
+        let res = formatter.format_all(triples.iter().cloned()).and_then(|_| formatter.finish().map(|_| ()));
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let escaped = format_with(style(ForbiddenChars::Escape), &triples);
+        assert!(!escaped.contains('\0'), "{:?}", escaped);
+        assert!(escaped.contains("v-s:name \"a\\u0000b\""), "{}", escaped);
+        assert!(escaped.contains("v-s:text \"\"\"one\ntwo\nthree\\u0000\"\"\""), "{}", escaped);
+        assert_eq!(reparse(&escaped), reparse(&format_with(style(ForbiddenChars::Keep), &triples)));
+
+        // the strings without forbidden chars are written the same in every mode
+        let plain = [triple("d:a", "v-s:name", string("tab\there"))];
+        assert_eq!(format_with(style(ForbiddenChars::Reject), &plain), format_with(style(ForbiddenChars::Keep), &plain));
+    }
+
+    #[test]
+    fn discovered_prefixes_are_declared_and_used() {
+        let triples = [
+            triple("http://a.org/x/s", "http://a.org/x/p", uri("http://b.org/y/o")),
+            triple("http://a.org/x/s", "http://a.org/x/p", uri("http://a.org/x/a.b")),
+            triple("http://a.org/x/s", RDF_TYPE, uri("http://a.org/x/C")),
+            triple("d:a", "v-s:name", string("a")),
+        ];
+        let style = TurtleStyle {
+            discover_prefixes: 2,
+            type_first: true,
+            ..inline_style()
+        };
+        let out = format_with(style, &triples);
+        assert!(out.contains("@prefix ns0: <http://a.org/x/> ."), "{}", out);
+        assert!(!out.contains("ns1:"), "{}", out);
+        assert!(out.contains("ns0:s \n  <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> ns0:C ;"), "{}", out);
+        // below the threshold and not a valid local name
+        assert!(out.contains("ns0:p <http://b.org/y/o>, <http://a.org/x/a.b> ."), "{}", out);
+        assert_eq!(
+            reparse(&out),
+            vec![
+                "<http://a.org/x/s> <http://a.org/x/p> <http://a.org/x/a.b> .",
+                "<http://a.org/x/s> <http://a.org/x/p> <http://b.org/y/o> .",
+                "<http://a.org/x/s> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <http://a.org/x/C> .",
+                "<http://example.org/d#a> <http://example.org/v-s#name> \"a\" .",
+            ]
+        );
+
+        let style = TurtleStyle {
+            discover_prefixes: 2,
+            rdf_type_as_a: true,
+            ..inline_style()
+        };
+        let out = format_with(style, &triples);
+        assert!(out.contains("ns0:s \n  ns0:p <http://b.org/y/o>, <http://a.org/x/a.b> ;\n  a ns0:C ."), "{}", out);
+        assert_eq!(reparse(&out).len(), 4);
+    }
+
+    #[test]
+    fn named_and_blank_subjects_with_one_string_are_separate_blocks() {
+        let triples = [
+            triple("b0", "v-s:name", string("named")),
+            blank_triple("b0", "v-s:name", string("blank")),
+            blank_triple("b0", "v-s:count", typed("1", "xsd:integer")),
+            triple("b0", "v-s:count", typed("2", "xsd:integer")),
+        ];
+        let out = format_with(TurtleStyle::default(), &triples);
+        let body = &out[out.find("b0").unwrap()..];
+        assert_eq!(
+            body,
+            "b0 \n  v-s:name \"named\" .\n\n_:b0 \n  v-s:name \"blank\" ;\n  v-s:count 1 .\n\nb0 \n  v-s:count 2 .\n"
+        );
+    }
+
+    fn escaped(s: &str) -> String {
+        let mut out = vec![];
+        write_escaped(s, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn write_escaped_of_plain_and_special_strings() {
+        // the direct write
+        for plain in ["", "abc", "tab\there", "юникод ü ✓", "'single' quotes"] {
+            assert_eq!(escaped(plain), plain);
+        }
+        // the escaped chars, alone, together and among the plain ones
+        let cases = [
+            ("\"", "\\\""),
+            ("\\", "\\\\"),
+            ("\n", "\\n"),
+            ("\r", "\\r"),
+            ("a \"b\" \\ c\r\nd", "a \\\"b\\\" \\\\ c\\r\\nd"),
+        ];
+        for (value, expected) in cases.iter() {
+            assert_eq!(escaped(value), *expected);
+            assert_eq!(escaped(value), escape(value).collect::<String>());
+        }
+
+        let triples: Vec<Triple> = cases.iter().map(|(value, _)| triple("d:a", "v-s:name", string(value))).collect();
+        let out = format_with(TurtleStyle::default(), &triples);
+        let mut expected: Vec<String> = cases
+            .iter()
+            .map(|(value, _)| format!("<http://example.org/d#a> <http://example.org/v-s#name> {} .", Literal::Simple { value }))
+            .collect();
+        expected.sort();
+        assert_eq!(reparse(&out), expected);
+    }
+
+    #[test]
+    fn write_escaped_of_large_plain_text() {
+        let text = "plain text of a document without special chars. ".repeat(100_000);
+
+        let start = std::time::Instant::now();
+        let fast = escaped(&text);
+        let fast_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let mut slow = vec![];
+        escape(&text).try_for_each(|c| write!(slow, "{}", c)).unwrap();
+        let slow_time = start.elapsed();
+
+        assert_eq!(fast.as_bytes(), slow.as_slice());
+        assert_eq!(fast, text);
+        assert!(fast_time <= slow_time, "direct write {:?}, char by char {:?}", fast_time, slow_time);
+    }
+}